@@ -0,0 +1,730 @@
+//! Managed services that run next to an app: Postgres and Valkey, a Redis
+//! fork that speaks the same protocol.
+//!
+//! Each add-on is a compose file in `/opt/minion/<app>/addons/` with its data
+//! under `/opt/minion/<app>/volumes/<addon>`. The app directory's `.env` lists
+//! these files in `COMPOSE_FILE`, so every `docker compose` command run there
+//! sees the add-ons as part of the app's project and deploys keep them
+//! running. Add-ons join `traefik_network` under a `<app>-<addon>` alias,
+//! which is the host the app connects to.
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::env::{read_env_file, write_env_file, write_private_env};
+use crate::utils::{
+    shell_quote, utc_timestamp, AppConfig, AppConfigOverrides, EnvFile, RemoteClient, SshClient,
+};
+
+const POSTGRES_IMAGE: &str = "postgres:17";
+/// Role and database created for the app.
+const POSTGRES_USER: &str = "app";
+const REDIS_IMAGE: &str = "valkey/valkey:8";
+
+pub struct AddonsCommand;
+
+#[derive(Debug, Clone)]
+pub enum AddonsAction {
+    Create { addon: String, persist: bool },
+    List,
+    Psql,
+    Dump { output: Option<String> },
+    Restore { dump: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Addon {
+    Postgres,
+    Redis,
+}
+
+impl Addon {
+    const ALL: &'static [Addon] = &[Addon::Postgres, Addon::Redis];
+
+    fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|addon| addon.name() == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown add-on {}. Available add-ons: {}",
+                    name,
+                    Self::ALL
+                        .iter()
+                        .map(|addon| addon.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+
+    /// Compose service name, also the directory of its volume.
+    fn name(self) -> &'static str {
+        match self {
+            Addon::Postgres => "postgres",
+            Addon::Redis => "redis",
+        }
+    }
+
+    /// Variable set in the app environment to reach the add-on.
+    fn url_key(self) -> &'static str {
+        match self {
+            Addon::Postgres => "DATABASE_URL",
+            Addon::Redis => "REDIS_URL",
+        }
+    }
+}
+
+impl AddonsCommand {
+    pub fn new() -> Self {
+        AddonsCommand
+    }
+
+    pub fn execute(&self, action: AddonsAction, overrides: AppConfigOverrides) -> Result<()> {
+        if let AddonsAction::Create { addon, persist } = &action {
+            if *persist && Addon::parse(addon)? != Addon::Redis {
+                return Err(anyhow!("--persist only applies to the redis add-on"));
+            }
+        }
+        let config = AppConfig::load(overrides, false, false)?;
+        config.require_app_control()?;
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        self.execute_action(action, &config, &client)
+    }
+
+    fn execute_action(
+        &self,
+        action: AddonsAction,
+        config: &AppConfig,
+        client: &dyn RemoteClient,
+    ) -> Result<()> {
+        match action {
+            AddonsAction::Create { addon, persist } => {
+                self.create(client, config, Addon::parse(&addon)?, persist)
+            }
+            AddonsAction::List => self.list(client, config),
+            AddonsAction::Psql => self.psql(client, config),
+            AddonsAction::Dump { output } => {
+                let name = format!(
+                    "{}-postgres-{}.dump",
+                    config.app_name,
+                    utc_timestamp(SystemTime::now())
+                );
+                self.dump(client, config, &name, output.as_deref())
+            }
+            AddonsAction::Restore { dump } => self.restore(client, config, &dump),
+        }
+    }
+
+    /// Creates `addon`. `persist` turns on Redis AOF persistence; Postgres
+    /// always keeps its data in a volume.
+    fn create(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        addon: Addon,
+        persist: bool,
+    ) -> Result<()> {
+        let app_dir = app_dir(config);
+        let compose_file = addon_compose_file(&app_dir, addon);
+        let (_, status) = client.execute_command(&format!("test -e {}", compose_file))?;
+        if status == 0 {
+            return Err(anyhow!(
+                "{} already has a {} add-on",
+                config.app_name,
+                addon.name()
+            ));
+        }
+
+        let persist = persist || addon == Addon::Postgres;
+        println!("Creating {} for {}...", addon.name(), config.app_name);
+        let volume_dir = if persist {
+            format!(" {}/volumes/{}", app_dir, addon.name())
+        } else {
+            String::new()
+        };
+        run(
+            client,
+            &format!(
+                "sudo mkdir -p {dir}/addons{volume} && sudo chown minion:minion {dir} {dir}/addons",
+                dir = app_dir,
+                volume = volume_dir
+            ),
+        )?;
+
+        let password = generate_password(client)?;
+        let mut env_file = EnvFile::default();
+        let url = match addon {
+            Addon::Postgres => {
+                env_file.set("POSTGRES_USER".to_string(), POSTGRES_USER.to_string());
+                env_file.set("POSTGRES_PASSWORD".to_string(), password.clone());
+                env_file.set("POSTGRES_DB".to_string(), POSTGRES_USER.to_string());
+                format!(
+                    "postgres://{}:{}@{}:5432/{}",
+                    POSTGRES_USER,
+                    password,
+                    addon_host(config, addon),
+                    POSTGRES_USER
+                )
+            }
+            Addon::Redis => {
+                env_file.set("REDIS_PASSWORD".to_string(), password.clone());
+                format!("redis://:{}@{}:6379/0", password, addon_host(config, addon))
+            }
+        };
+        write_private_env(client, config, &addon_env_file(&app_dir, addon), &env_file)?;
+
+        run(
+            client,
+            &format!(
+                "cat > {} << 'EOL'\n{}EOL",
+                compose_file,
+                render_service(config, &app_dir, addon, persist)
+            ),
+        )?;
+        run(client, &compose_file_env_command(&app_dir))?;
+
+        println!("Starting {}...", addon.name());
+        let (output, status) = client.execute_command(&format!(
+            "docker compose --project-directory {} -f {} up -d --wait {} 2>&1",
+            app_dir,
+            compose_file,
+            addon.name()
+        ))?;
+        if status != 0 {
+            return Err(anyhow!(
+                "Failed to start {}: {}",
+                addon.name(),
+                output.trim()
+            ));
+        }
+
+        let mut env_file = read_env_file(client, config)?;
+        env_file.set(addon.url_key().to_string(), url);
+        write_env_file(client, config, &env_file)?;
+
+        println!("✓ {} is running for {}", addon.name(), config.app_name);
+        println!(
+            "✓ {} added to the app environment. Deploy again or run `minion env list` to see it.",
+            addon.url_key()
+        );
+        Ok(())
+    }
+
+    fn list(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+        let addons = list_addons(client, &app_dir(config))?;
+        if addons.is_empty() {
+            println!(
+                "{} has no add-ons. Create one with `minion addons create postgres`.",
+                config.app_name
+            );
+            return Ok(());
+        }
+
+        for addon in addons {
+            println!("{}", addon);
+        }
+
+        Ok(())
+    }
+
+    fn psql(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+        let app_dir = app_dir(config);
+        require_addon(client, config, &app_dir, Addon::Postgres)?;
+
+        let status = client.execute_interactive(&format!(
+            "cd {} && docker compose exec postgres psql -U {} {}",
+            app_dir, POSTGRES_USER, POSTGRES_USER
+        ))?;
+        if status != 0 {
+            return Err(anyhow!("psql exited with status {}", status));
+        }
+
+        Ok(())
+    }
+
+    fn dump(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        name: &str,
+        output: Option<&str>,
+    ) -> Result<()> {
+        let app_dir = app_dir(config);
+        require_addon(client, config, &app_dir, Addon::Postgres)?;
+
+        let remote_path = format!("{}/backups/{}", app_dir, name);
+        println!("Dumping the {} database...", config.app_name);
+        let dumped = run(
+            client,
+            &format!(
+                "cd {} && mkdir -p backups && docker compose exec -T postgres pg_dump -U {} -d {} --format=custom > {}",
+                app_dir, POSTGRES_USER, POSTGRES_USER, remote_path
+            ),
+        );
+        if let Err(error) = dumped {
+            client.execute_command(&format!("rm -f {}", remote_path))?;
+            return Err(error);
+        }
+
+        let local_path = match output {
+            Some(output) if Path::new(output).is_dir() => {
+                Path::new(output).join(name).to_string_lossy().to_string()
+            }
+            Some(output) => output.to_string(),
+            None => name.to_string(),
+        };
+        println!("Downloading {}...", name);
+        let downloaded = client.download_file(&remote_path, &local_path);
+        client.execute_command(&format!("rm -f {}", remote_path))?;
+        downloaded?;
+
+        println!("✓ Database dump saved to {}", local_path);
+        Ok(())
+    }
+
+    fn restore(&self, client: &dyn RemoteClient, config: &AppConfig, dump: &str) -> Result<()> {
+        let local = Path::new(dump);
+        let name = local
+            .file_name()
+            .filter(|_| local.is_file())
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("Database dump {} not found", dump))?;
+
+        let app_dir = app_dir(config);
+        require_addon(client, config, &app_dir, Addon::Postgres)?;
+
+        let remote_path = format!("{}/backups/{}", app_dir, name);
+        println!("Uploading {}...", name);
+        run(client, &format!("mkdir -p {}/backups", app_dir))?;
+        client.copy_file(dump, &remote_path)?;
+
+        // --clean drops the objects in the dump first, so restoring into a
+        // database that is in use replaces its tables.
+        println!("Restoring the {} database...", config.app_name);
+        let status = client.execute_command_stream(&format!(
+            "cd {} && docker compose exec -T postgres pg_restore -U {} -d {} --clean --if-exists --no-owner --single-transaction < {} 2>&1",
+            app_dir, POSTGRES_USER, POSTGRES_USER, remote_path
+        ));
+        client.execute_command(&format!("rm -f {}", remote_path))?;
+        let status = status?;
+        if status != 0 {
+            return Err(anyhow!("pg_restore exited with status {}", status));
+        }
+
+        println!("✓ Restored {}", name);
+        Ok(())
+    }
+}
+
+fn render_service(config: &AppConfig, app_dir: &str, addon: Addon, persist: bool) -> String {
+    let service = match addon {
+        Addon::Postgres => format!(
+            "  postgres:\n    image: {image}\n    restart: unless-stopped\n    env_file:\n      - {env_file}\n    volumes:\n      - {dir}/volumes/postgres:/var/lib/postgresql/data\n    healthcheck:\n      test: [\"CMD-SHELL\", \"pg_isready -U {user} -d {user}\"]\n      interval: 10s\n      timeout: 5s\n      retries: 5\n",
+            image = POSTGRES_IMAGE,
+            env_file = addon_env_file(app_dir, addon),
+            dir = app_dir,
+            user = POSTGRES_USER
+        ),
+        // The password comes from the env file; `$$` keeps compose from
+        // interpolating it.
+        Addon::Redis => format!(
+            "  redis:\n    image: {image}\n    restart: unless-stopped\n    command: [\"sh\", \"-c\", \"exec valkey-server --requirepass \\\"$$REDIS_PASSWORD\\\" {persistence}\"]\n    env_file:\n      - {env_file}\n{volumes}    healthcheck:\n      test: [\"CMD-SHELL\", \"valkey-cli --no-auth-warning -a \\\"$$REDIS_PASSWORD\\\" ping | grep -q PONG\"]\n      interval: 10s\n      timeout: 5s\n      retries: 5\n",
+            image = REDIS_IMAGE,
+            persistence = if persist {
+                "--appendonly yes --dir /data"
+            } else {
+                "--save '' --appendonly no"
+            },
+            env_file = addon_env_file(app_dir, addon),
+            volumes = if persist {
+                format!("    volumes:\n      - {}/volumes/redis:/data\n", app_dir)
+            } else {
+                String::new()
+            }
+        ),
+    };
+
+    format!(
+        "services:\n{}    networks:\n      traefik_network:\n        aliases:\n          - {}\n\nnetworks:\n  traefik_network:\n    external: true\n",
+        service,
+        addon_host(config, addon)
+    )
+}
+
+/// Points `COMPOSE_FILE` at the app's compose file and every add-on, so
+/// `docker compose` in the app directory manages them together.
+fn compose_file_env_command(app_dir: &str) -> String {
+    format!(
+        "cd {} && printf 'COMPOSE_FILE=docker-compose.yml%s\\n' \"$(for file in addons/*.yml; do [ -e \"$file\" ] && printf ':%s' \"$file\"; done)\" > .env",
+        app_dir
+    )
+}
+
+/// Reads 24 random bytes on the VPS as hex, safe to use in a URL.
+fn generate_password(client: &dyn RemoteClient) -> Result<String> {
+    let (output, status) =
+        client.execute_command("od -An -N24 -tx1 /dev/urandom | tr -d ' \\n'")?;
+    let password = output.trim().to_string();
+    if status != 0 || password.len() != 48 || !password.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "Failed to generate a password on the VPS: {}",
+            output
+        ));
+    }
+
+    Ok(password)
+}
+
+/// Lines `minion status` prints about the app's add-ons: the memory used by
+/// Redis.
+pub(super) fn status_lines(client: &dyn RemoteClient, config: &AppConfig) -> Result<Vec<String>> {
+    let app_dir = app_dir(config);
+    if !list_addons(client, &app_dir)?
+        .iter()
+        .any(|addon| addon == Addon::Redis.name())
+    {
+        return Ok(Vec::new());
+    }
+
+    let (output, status) = client.execute_command(&format!(
+        "cd {} && docker compose exec -T redis sh -c {} 2>&1",
+        app_dir,
+        shell_quote("valkey-cli --no-auth-warning -a \"$REDIS_PASSWORD\" info memory")
+    ))?;
+    if status != 0 {
+        return Ok(vec![format!(
+            "redis: memory usage unavailable. {}",
+            output.trim()
+        )]);
+    }
+
+    let value = |key: &str| {
+        output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(&format!("{}:", key)))
+            .unwrap_or("?")
+            .to_string()
+    };
+    let limit = match value("maxmemory_human").as_str() {
+        "0B" => "none".to_string(),
+        limit => limit.to_string(),
+    };
+    Ok(vec![format!(
+        "redis: {} used, {} peak, limit {}",
+        value("used_memory_human"),
+        value("used_memory_peak_human"),
+        limit
+    )])
+}
+
+/// Whether `name` is an add-on's compose service, e.g. `postgres`.
+pub(super) fn is_addon(name: &str) -> bool {
+    Addon::parse(name).is_ok()
+}
+
+/// Add-ons of the app that keep data in `/opt/minion/<app>/volumes/<addon>`,
+/// so backups include them next to the app's own volumes.
+pub(super) fn data_volumes(client: &dyn RemoteClient, config: &AppConfig) -> Result<Vec<String>> {
+    let (output, status) = client.execute_command(&format!(
+        "cd {} && for file in addons/*.yml; do addon=$(basename \"$file\" .yml); [ -d \"volumes/$addon\" ] && echo \"$addon\"; done; true",
+        app_dir(config)
+    ))?;
+    if status != 0 {
+        return Err(anyhow!("Failed to list add-ons: {}", output));
+    }
+
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|name| is_addon(name))
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+fn require_addon(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    addon: Addon,
+) -> Result<()> {
+    let (_, status) =
+        client.execute_command(&format!("test -f {}", addon_compose_file(app_dir, addon)))?;
+    if status != 0 {
+        return Err(anyhow!(
+            "{} has no {} add-on. Create it with `minion addons create {}`.",
+            config.app_name,
+            addon.name(),
+            addon.name()
+        ));
+    }
+
+    Ok(())
+}
+
+fn list_addons(client: &dyn RemoteClient, app_dir: &str) -> Result<Vec<String>> {
+    let (output, status) =
+        client.execute_command(&format!("ls -1 {}/addons 2>/dev/null || true", app_dir))?;
+    if status != 0 {
+        return Err(anyhow!("Failed to list add-ons: {}", output));
+    }
+
+    Ok(output
+        .lines()
+        .filter_map(|line| line.trim().strip_suffix(".yml"))
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+fn addon_compose_file(app_dir: &str, addon: Addon) -> String {
+    format!("{}/addons/{}.yml", app_dir, addon.name())
+}
+
+fn addon_env_file(app_dir: &str, addon: Addon) -> String {
+    format!("{}/addons/{}.env", app_dir, addon.name())
+}
+
+/// Network alias of the add-on, unique across the apps on the VPS.
+fn addon_host(config: &AppConfig, addon: Addon) -> String {
+    format!("{}-{}", config.app_name, addon.name())
+}
+
+fn run(client: &dyn RemoteClient, command: &str) -> Result<()> {
+    let (output, status) = client.execute_command(command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to execute command {}: {}", command, output));
+    }
+
+    Ok(())
+}
+
+fn app_dir(config: &AppConfig) -> String {
+    format!("/opt/minion/{}", config.app_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    const PASSWORD: &str = "00112233445566778899aabbccddeeff0011223344556677";
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            host: "example.com".to_string(),
+            app_name: "my-app".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unknown_addons_are_rejected() {
+        assert_eq!(Addon::parse("postgres").unwrap(), Addon::Postgres);
+        assert_eq!(
+            Addon::parse("mysql").unwrap_err().to_string(),
+            "Unknown add-on mysql. Available add-ons: postgres, redis"
+        );
+    }
+
+    #[test]
+    fn create_postgres_joins_the_app_project_and_sets_database_url() {
+        let remote = FakeRemoteClient::new()
+            .on_command("test -e", "", 1)
+            .on_command("/dev/urandom", PASSWORD, 0)
+            .on_command(
+                "cat '/opt/minion/my-app/app.env'",
+                "RAILS_ENV=production\n",
+                0,
+            );
+
+        AddonsCommand::new()
+            .create(&remote, &app_config(), Addon::Postgres, false)
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands[1],
+            "sudo mkdir -p /opt/minion/my-app/addons /opt/minion/my-app/volumes/postgres && sudo chown minion:minion /opt/minion/my-app /opt/minion/my-app/addons"
+        );
+        assert!(commands[3].contains("cat > '/opt/minion/my-app/addons/postgres.env'"));
+        assert!(commands[3].contains(&format!("POSTGRES_PASSWORD='{}'\n", PASSWORD)));
+
+        let compose = &commands[4];
+        assert!(compose.starts_with("cat > /opt/minion/my-app/addons/postgres.yml << 'EOL'\nservices:\n  postgres:\n    image: postgres:17\n"));
+        assert!(compose
+            .contains("      - /opt/minion/my-app/volumes/postgres:/var/lib/postgresql/data\n"));
+        assert!(compose.contains("        aliases:\n          - my-app-postgres\n"));
+        assert!(commands[5].contains("COMPOSE_FILE=docker-compose.yml%s"));
+        assert_eq!(
+            commands[6],
+            "docker compose --project-directory /opt/minion/my-app -f /opt/minion/my-app/addons/postgres.yml up -d --wait postgres 2>&1"
+        );
+
+        let app_env = commands.last().unwrap();
+        assert!(app_env.contains("cat > '/opt/minion/my-app/app.env'"));
+        assert!(app_env.contains(&format!(
+            "DATABASE_URL='postgres://app:{}@my-app-postgres:5432/app'\nRAILS_ENV='production'\n",
+            PASSWORD
+        )));
+    }
+
+    #[test]
+    fn create_redis_with_aof_persistence_sets_redis_url() {
+        let remote = FakeRemoteClient::new()
+            .on_command("test -e", "", 1)
+            .on_command("/dev/urandom", PASSWORD, 0);
+
+        AddonsCommand::new()
+            .create(&remote, &app_config(), Addon::Redis, true)
+            .unwrap();
+
+        let commands = remote.commands();
+        assert!(commands[1].starts_with(
+            "sudo mkdir -p /opt/minion/my-app/addons /opt/minion/my-app/volumes/redis &&"
+        ));
+        assert!(commands[3].contains(&format!("REDIS_PASSWORD='{}'\n", PASSWORD)));
+        let compose = &commands[4];
+        assert!(compose.contains("    image: valkey/valkey:8\n"));
+        assert!(compose
+            .contains("--requirepass \\\"$$REDIS_PASSWORD\\\" --appendonly yes --dir /data\"]\n"));
+        assert!(compose.contains("      - /opt/minion/my-app/volumes/redis:/data\n"));
+        assert!(commands.last().unwrap().contains(&format!(
+            "REDIS_URL='redis://:{}@my-app-redis:6379/0'\n",
+            PASSWORD
+        )));
+    }
+
+    #[test]
+    fn redis_without_persistence_has_no_volume() {
+        let compose = render_service(&app_config(), "/opt/minion/my-app", Addon::Redis, false);
+
+        assert!(compose.contains("--save '' --appendonly no"));
+        assert!(!compose.contains("volumes:"));
+    }
+
+    #[test]
+    fn status_reports_redis_memory() {
+        let remote = FakeRemoteClient::new()
+            .on_command("ls -1", "postgres.env\npostgres.yml\nredis.env\nredis.yml\n", 0)
+            .on_command(
+                "info memory",
+                "# Memory\r\nused_memory:1048576\r\nused_memory_human:1.00M\r\nused_memory_peak_human:1.20M\r\nmaxmemory_human:0B\r\n",
+                0,
+            );
+
+        let lines = status_lines(&remote, &app_config()).unwrap();
+
+        assert_eq!(lines, vec!["redis: 1.00M used, 1.20M peak, limit none"]);
+        assert_eq!(
+            remote.commands()[1],
+            "cd /opt/minion/my-app && docker compose exec -T redis sh -c 'valkey-cli --no-auth-warning -a \"$REDIS_PASSWORD\" info memory' 2>&1"
+        );
+    }
+
+    #[test]
+    fn status_without_redis_reports_nothing() {
+        let remote = FakeRemoteClient::new().on_command("ls -1", "postgres.yml\n", 0);
+
+        assert!(status_lines(&remote, &app_config()).unwrap().is_empty());
+        assert_eq!(remote.commands().len(), 1);
+    }
+
+    #[test]
+    fn existing_addon_is_not_created_again() {
+        let remote = FakeRemoteClient::new();
+
+        let error = AddonsCommand::new()
+            .create(&remote, &app_config(), Addon::Postgres, false)
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "my-app already has a postgres add-on");
+        assert_eq!(remote.commands().len(), 1);
+    }
+
+    #[test]
+    fn psql_opens_an_interactive_session() {
+        let remote = FakeRemoteClient::new();
+
+        AddonsCommand::new().psql(&remote, &app_config()).unwrap();
+
+        assert_eq!(
+            remote.interactive_commands(),
+            vec!["cd /opt/minion/my-app && docker compose exec postgres psql -U app app"]
+        );
+    }
+
+    #[test]
+    fn dump_downloads_and_removes_the_remote_file() {
+        let remote = FakeRemoteClient::new();
+
+        AddonsCommand::new()
+            .dump(&remote, &app_config(), "my-app-postgres-1.dump", None)
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands[1],
+            "cd /opt/minion/my-app && mkdir -p backups && docker compose exec -T postgres pg_dump -U app -d app --format=custom > /opt/minion/my-app/backups/my-app-postgres-1.dump"
+        );
+        assert_eq!(
+            remote.downloaded_files(),
+            vec![(
+                "/opt/minion/my-app/backups/my-app-postgres-1.dump".to_string(),
+                "my-app-postgres-1.dump".to_string()
+            )]
+        );
+        assert_eq!(
+            commands[2],
+            "rm -f /opt/minion/my-app/backups/my-app-postgres-1.dump"
+        );
+    }
+
+    #[test]
+    fn restore_uploads_the_dump_and_runs_pg_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = dir.path().join("prod.dump");
+        std::fs::write(&dump, b"PGDMP").unwrap();
+        let remote = FakeRemoteClient::with_stream_responses(vec![1]);
+
+        let error = AddonsCommand::new()
+            .restore(&remote, &app_config(), dump.to_str().unwrap())
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "pg_restore exited with status 1");
+        assert_eq!(
+            remote.copied_files(),
+            vec![(
+                dump.to_string_lossy().to_string(),
+                "/opt/minion/my-app/backups/prod.dump".to_string()
+            )]
+        );
+        assert!(remote.streamed_commands()[0].starts_with(
+            "cd /opt/minion/my-app && docker compose exec -T postgres pg_restore -U app -d app --clean"
+        ));
+        assert_eq!(
+            remote.commands().last().unwrap(),
+            "rm -f /opt/minion/my-app/backups/prod.dump"
+        );
+    }
+
+    #[test]
+    fn postgres_commands_require_the_addon() {
+        let remote = FakeRemoteClient::new().on_command("test -f", "", 1);
+
+        let error = AddonsCommand::new()
+            .psql(&remote, &app_config())
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "my-app has no postgres add-on. Create it with `minion addons create postgres`."
+        );
+        assert!(remote.interactive_commands().is_empty());
+    }
+}
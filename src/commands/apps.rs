@@ -0,0 +1,234 @@
+//! Overview of every app deployed to a VPS.
+//!
+//! The scan runs as one shell script on the server, which prints
+//! tab-separated `key\tvalue` records; an `app` record starts each app.
+
+use anyhow::{anyhow, Result};
+
+use crate::utils::{AppConfig, AppConfigOverrides, RemoteClient, SshClient};
+
+const APPS_SCRIPT: &str = include_str!("../resources/apps.sh");
+
+pub struct AppsCommand;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct AppSummary {
+    name: String,
+    states: Vec<String>,
+    image_age_secs: Option<u64>,
+    domains: Vec<String>,
+    volumes_kib: Option<u64>,
+}
+
+impl AppsCommand {
+    pub fn new() -> Self {
+        AppsCommand
+    }
+
+    pub fn execute(&self, overrides: AppConfigOverrides) -> Result<()> {
+        let config = AppConfig::load(overrides, false, false)?;
+        config.require_host()?;
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        for line in self.table(&client)? {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    fn table(&self, client: &dyn RemoteClient) -> Result<Vec<String>> {
+        let (output, status) = client.execute_command(APPS_SCRIPT)?;
+        if status != 0 {
+            return Err(anyhow!("Failed to list apps: {}", output.trim()));
+        }
+
+        let apps = parse_apps(&output);
+        if apps.is_empty() {
+            return Ok(vec!["No apps deployed".to_string()]);
+        }
+        Ok(format_table(&apps))
+    }
+}
+
+fn parse_apps(output: &str) -> Vec<AppSummary> {
+    let mut apps: Vec<AppSummary> = Vec::new();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('\t') else {
+            continue;
+        };
+        let value = value.trim();
+        if key == "app" {
+            apps.push(AppSummary {
+                name: value.to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(app) = apps.last_mut() else {
+            continue;
+        };
+        match key {
+            "state" => app.states.push(value.to_string()),
+            "age" => app.image_age_secs = value.parse().ok(),
+            "domain" if !app.domains.iter().any(|domain| domain == value) => {
+                app.domains.push(value.to_string())
+            }
+            "volumes" => app.volumes_kib = value.parse().ok(),
+            _ => {}
+        }
+    }
+    apps
+}
+
+fn format_table(apps: &[AppSummary]) -> Vec<String> {
+    let mut rows = vec![[
+        "APP".to_string(),
+        "STATE".to_string(),
+        "IMAGE AGE".to_string(),
+        "VOLUMES".to_string(),
+        "DOMAINS".to_string(),
+    ]];
+    for app in apps {
+        rows.push([
+            app.name.clone(),
+            format_state(&app.states),
+            app.image_age_secs
+                .map(format_age)
+                .unwrap_or_else(|| "-".to_string()),
+            app.volumes_kib
+                .map(format_kib)
+                .unwrap_or_else(|| "-".to_string()),
+            if app.domains.is_empty() {
+                "-".to_string()
+            } else {
+                app.domains.join(", ")
+            },
+        ]);
+    }
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (cell, width) in row.iter().zip(widths) {
+                line.push_str(&format!("{:<width$}  ", cell, width = width));
+            }
+            line.push_str(&row[4]);
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+fn format_state(states: &[String]) -> String {
+    let running = states.iter().filter(|state| *state == "running").count();
+    if states.is_empty() || running == 0 {
+        "stopped".to_string()
+    } else if running == states.len() {
+        format!("running {}/{}", running, states.len())
+    } else {
+        format!("degraded {}/{}", running, states.len())
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86_399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+fn format_kib(kib: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    let mut value = kib as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    const OUTPUT: &str = "app\tblog\n\
+        state\trunning\n\
+        state\trunning\n\
+        age\t7200\n\
+        domain\tblog.example.com\n\
+        domain\twww.blog.example.com\n\
+        domain\tblog.example.com\n\
+        volumes\t1536\n\
+        app\tshop\n\
+        state\trunning\n\
+        state\texited\n\
+        age\t259200\n\
+        app\told\n\
+        state\texited\n";
+
+    #[test]
+    fn parses_records_per_app() {
+        let apps = parse_apps(OUTPUT);
+
+        assert_eq!(apps.len(), 3);
+        assert_eq!(
+            apps[0],
+            AppSummary {
+                name: "blog".to_string(),
+                states: vec!["running".to_string(), "running".to_string()],
+                image_age_secs: Some(7200),
+                domains: vec![
+                    "blog.example.com".to_string(),
+                    "www.blog.example.com".to_string()
+                ],
+                volumes_kib: Some(1536),
+            }
+        );
+        assert_eq!(apps[2].image_age_secs, None);
+        assert!(apps[2].domains.is_empty());
+    }
+
+    #[test]
+    fn prints_an_aligned_table() {
+        let remote = FakeRemoteClient::with_responses(vec![(OUTPUT, 0)]);
+
+        let lines = AppsCommand::new().table(&remote).unwrap();
+
+        assert_eq!(remote.commands(), vec![APPS_SCRIPT.to_string()]);
+        assert_eq!(
+            lines,
+            vec![
+                "APP   STATE         IMAGE AGE  VOLUMES  DOMAINS",
+                "blog  running 2/2   2h         1.5 MiB  blog.example.com, www.blog.example.com",
+                "shop  degraded 1/2  3d         -        -",
+                "old   stopped       -          -        -",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_an_empty_server() {
+        let remote = FakeRemoteClient::new();
+
+        let lines = AppsCommand::new().table(&remote).unwrap();
+
+        assert_eq!(lines, vec!["No apps deployed"]);
+    }
+}
@@ -0,0 +1,561 @@
+//! Backups of the app's volumes under `/opt/minion/<app>/volumes`.
+//!
+//! The volumes are those of `APP_VOLUMES` plus the data directories of the
+//! app's add-ons, such as `postgres`. An archive is a gzipped tar of one or
+//! more volume directories, stored in
+//! `/opt/minion/<app>/backups` as `<app>-<timestamp>.tar.gz`. Archives kept on
+//! the VPS are pruned down to `BACKUP_KEEP`; downloaded ones are removed from
+//! the VPS once they are copied. `minion backup schedule` uploads archives to
+//! S3-compatible storage instead; see `offsite`.
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::{addons, offsite};
+use crate::utils::{
+    shell_quote, utc_timestamp, AppConfig, AppConfigOverrides, RemoteClient, SshClient,
+};
+
+pub struct BackupCommand;
+
+#[derive(Debug, Clone)]
+pub enum BackupAction {
+    Create {
+        volumes: Vec<String>,
+        stop: bool,
+        output: Option<String>,
+    },
+    List,
+    Restore {
+        archive: String,
+    },
+    Schedule {
+        remove: bool,
+        now: bool,
+    },
+}
+
+impl BackupCommand {
+    pub fn new() -> Self {
+        BackupCommand
+    }
+
+    pub fn execute(&self, action: BackupAction, overrides: AppConfigOverrides) -> Result<()> {
+        let config = AppConfig::load(overrides, false, false)?;
+        config.require_app_control()?;
+        config.backup_keep_count()?;
+        if let BackupAction::Schedule { remove: false, .. } = action {
+            config.require_backup_upload()?;
+        }
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        self.execute_action(action, &config, &client)
+    }
+
+    fn execute_action(
+        &self,
+        action: BackupAction,
+        config: &AppConfig,
+        client: &dyn RemoteClient,
+    ) -> Result<()> {
+        match action {
+            BackupAction::Create {
+                volumes,
+                stop,
+                output,
+            } => {
+                let name = format!(
+                    "{}-{}.tar.gz",
+                    config.app_name,
+                    utc_timestamp(SystemTime::now())
+                );
+                self.create(client, config, &name, &volumes, stop, output.as_deref())
+            }
+            BackupAction::List => self.list(client, config),
+            BackupAction::Restore { archive } => self.restore(client, config, &archive),
+            BackupAction::Schedule { remove, now } => self.schedule(client, config, remove, now),
+        }
+    }
+
+    fn create(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        name: &str,
+        volumes: &[String],
+        stop: bool,
+        output: Option<&str>,
+    ) -> Result<()> {
+        let app_dir = app_dir(config);
+        ensure_deployed(client, &app_dir)?;
+        let volumes = select_volumes(config, &addons::data_volumes(client, config)?, volumes)?;
+
+        let archive = format!("{}/{}", backups_dir(&app_dir), name);
+        run(client, &format!("mkdir -p {}", backups_dir(&app_dir)))?;
+
+        if stop {
+            println!("Stopping {} for a consistent backup...", config.app_name);
+            run(client, &format!("cd {} && docker compose stop", app_dir))?;
+        }
+
+        println!("Archiving {}...", volumes.join(", "));
+        // Containers often write their files as root, so read them with sudo.
+        let archived = run(
+            client,
+            &format!(
+                "sudo tar -C {}/volumes -czf {} {} && sudo chown minion:minion {}",
+                app_dir,
+                archive,
+                volumes
+                    .iter()
+                    .map(|volume| shell_quote(volume))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                archive
+            ),
+        );
+
+        if stop {
+            println!("Starting {}...", config.app_name);
+            run(client, &format!("cd {} && docker compose start", app_dir))?;
+        }
+        if let Err(error) = archived {
+            client.execute_command(&format!("rm -f {}", archive))?;
+            return Err(error);
+        }
+
+        match output {
+            Some(directory) => {
+                let local_path = Path::new(directory).join(name);
+                let local_path = local_path.to_string_lossy();
+                println!("Downloading {}...", name);
+                client.download_file(&archive, &local_path)?;
+                run(client, &format!("rm -f {}", archive))?;
+                println!("✓ Backup saved to {}", local_path);
+            }
+            None => {
+                prune_backups(client, config, &app_dir)?;
+                println!("✓ Backup saved on the VPS as {}", name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+        let backups = list_backups(client, config, &app_dir(config))?;
+        if backups.is_empty() {
+            println!(
+                "No backups found for {}. Run `minion backup` first.",
+                config.app_name
+            );
+            return Ok(());
+        }
+
+        for backup in backups {
+            println!("{}", backup);
+        }
+
+        Ok(())
+    }
+
+    fn restore(&self, client: &dyn RemoteClient, config: &AppConfig, archive: &str) -> Result<()> {
+        let app_dir = app_dir(config);
+        ensure_deployed(client, &app_dir)?;
+
+        let local = Path::new(archive);
+        let name = if local.is_file() {
+            let name = local
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .ok_or_else(|| anyhow!("Invalid backup path {}", archive))?;
+            println!("Uploading {}...", name);
+            run(client, &format!("mkdir -p {}", backups_dir(&app_dir)))?;
+            client.copy_file(archive, &format!("{}/{}", backups_dir(&app_dir), name))?;
+            name
+        } else {
+            if archive.contains('/')
+                || !list_backups(client, config, &app_dir)?
+                    .iter()
+                    .any(|backup| backup == archive)
+            {
+                return Err(anyhow!(
+                    "Backup {} not found locally or on the VPS. Run `minion backup list` to list the backups on the VPS.",
+                    archive
+                ));
+            }
+            archive.to_string()
+        };
+
+        println!("Stopping {}...", config.app_name);
+        run(client, &format!("cd {} && docker compose stop", app_dir))?;
+
+        println!("Restoring {}...", name);
+        let restored = run(client, &restore_command(&app_dir, &name));
+
+        println!("Starting {}...", config.app_name);
+        run(client, &format!("cd {} && docker compose up -d", app_dir))?;
+        restored?;
+
+        println!("✓ Restored {}", name);
+        Ok(())
+    }
+
+    fn schedule(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        remove: bool,
+        now: bool,
+    ) -> Result<()> {
+        let app_dir = app_dir(config);
+        if remove {
+            offsite::remove(client, config, &app_dir)?;
+            println!("✓ Removed the scheduled backup of {}", config.app_name);
+            return Ok(());
+        }
+
+        ensure_deployed(client, &app_dir)?;
+        let volumes = select_volumes(config, &addons::data_volumes(client, config)?, &[])?;
+        offsite::install(client, config, &app_dir, &volumes)?;
+        println!(
+            "✓ Scheduled backups of {} to {} ({})",
+            volumes.join(", "),
+            offsite::upload_location(config),
+            config.backup_schedule
+        );
+
+        if now {
+            println!("Running the backup now...");
+            offsite::run_now(client, &app_dir)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Unpacks the archive `name` next to the volumes first, so a broken archive
+/// leaves them untouched, then swaps in each restored directory.
+pub(super) fn restore_command(app_dir: &str, name: &str) -> String {
+    format!(
+        "cd {app_dir} && staging=$(mktemp -d {app_dir}/backups/restore.XXXXXX) || exit 1; \
+sudo tar -C \"$staging\" -xzf {archive}; status=$?; \
+if [ $status -eq 0 ]; then for volume in $(ls -A \"$staging\"); do \
+sudo rm -rf \"volumes/$volume\" && sudo mv \"$staging/$volume\" \"volumes/$volume\" || {{ status=1; break; }}; \
+done; fi; sudo rm -rf \"$staging\"; exit $status",
+        app_dir = app_dir,
+        archive = shell_quote(&format!("{}/{}", backups_dir(app_dir), name))
+    )
+}
+
+/// Every configured volume and add-on volume, or the requested ones after
+/// checking they exist.
+fn select_volumes(
+    config: &AppConfig,
+    addon_volumes: &[String],
+    requested: &[String],
+) -> Result<Vec<String>> {
+    let mut configured = config.volume_names();
+    for volume in addon_volumes {
+        if !configured.contains(volume) {
+            configured.push(volume.clone());
+        }
+    }
+    if configured.is_empty() {
+        return Err(anyhow!(
+            "{} has no volumes to back up. Volumes come from APP_VOLUMES and add-ons with data.",
+            config.app_name
+        ));
+    }
+    if requested.is_empty() {
+        return Ok(configured);
+    }
+
+    for volume in requested {
+        if !configured.contains(volume) {
+            return Err(anyhow!(
+                "Unknown volume {}. Known volumes: {}",
+                volume,
+                configured.join(", ")
+            ));
+        }
+    }
+
+    Ok(requested.to_vec())
+}
+
+fn list_backups(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+) -> Result<Vec<String>> {
+    let (output, status) = client.execute_command(&format!(
+        "ls -1 {} 2>/dev/null | grep -E {} || true",
+        backups_dir(app_dir),
+        shell_quote(&archive_pattern(config))
+    ))?;
+    if status != 0 {
+        return Err(anyhow!("Failed to list backups: {}", output));
+    }
+
+    let mut backups = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    backups.sort();
+    Ok(backups)
+}
+
+/// Extended regex matching the names of the app's archives.
+pub(super) fn archive_pattern(config: &AppConfig) -> String {
+    format!(
+        "^{}-[0-9]{{14}}\\.tar\\.gz$",
+        config.app_name.replace('.', "\\.")
+    )
+}
+
+fn prune_backups(client: &dyn RemoteClient, config: &AppConfig, app_dir: &str) -> Result<()> {
+    let keep = config.backup_keep_count()?;
+    let backups = list_backups(client, config, app_dir)?;
+    if backups.len() <= keep {
+        return Ok(());
+    }
+
+    let expired = backups[..backups.len() - keep]
+        .iter()
+        .map(|backup| format!("{}/{}", backups_dir(app_dir), backup))
+        .collect::<Vec<_>>();
+    run(client, &format!("rm -f {}", expired.join(" ")))
+}
+
+fn ensure_deployed(client: &dyn RemoteClient, app_dir: &str) -> Result<()> {
+    let (_, status) = client.execute_command(&format!("test -f {}/docker-compose.yml", app_dir))?;
+    if status != 0 {
+        return Err(anyhow!(
+            "No Minion app found at {}. Run `minion deploy` first.",
+            app_dir
+        ));
+    }
+
+    Ok(())
+}
+
+fn run(client: &dyn RemoteClient, command: &str) -> Result<()> {
+    let (output, status) = client.execute_command(command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to execute command {}: {}", command, output));
+    }
+
+    Ok(())
+}
+
+fn backups_dir(app_dir: &str) -> String {
+    format!("{}/backups", app_dir)
+}
+
+fn app_dir(config: &AppConfig) -> String {
+    format!("/opt/minion/{}", config.app_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    const BACKUP: &str = "my-app-20261017153000.tar.gz";
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            host: "example.com".to_string(),
+            app_name: "my-app".to_string(),
+            app_volumes: "data:/app/data, uploads:/app/uploads".to_string(),
+            backup_keep: "2".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backup_archives_every_volume_and_prunes_old_archives() {
+        let remote = FakeRemoteClient::new().on_command(
+            "ls -1",
+            "my-app-20261015000000.tar.gz\nmy-app-20261016000000.tar.gz\nmy-app-20261017153000.tar.gz\n",
+            0,
+        );
+
+        BackupCommand::new()
+            .create(&remote, &app_config(), BACKUP, &[], false, None)
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands,
+            vec![
+                "test -f /opt/minion/my-app/docker-compose.yml".to_string(),
+                "cd /opt/minion/my-app && for file in addons/*.yml; do addon=$(basename \"$file\" .yml); [ -d \"volumes/$addon\" ] && echo \"$addon\"; done; true".to_string(),
+                "mkdir -p /opt/minion/my-app/backups".to_string(),
+                "sudo tar -C /opt/minion/my-app/volumes -czf /opt/minion/my-app/backups/my-app-20261017153000.tar.gz 'data' 'uploads' && sudo chown minion:minion /opt/minion/my-app/backups/my-app-20261017153000.tar.gz".to_string(),
+                "ls -1 /opt/minion/my-app/backups 2>/dev/null | grep -E '^my-app-[0-9]{14}\\.tar\\.gz$' || true".to_string(),
+                "rm -f /opt/minion/my-app/backups/my-app-20261015000000.tar.gz".to_string(),
+            ]
+        );
+        assert!(remote.downloaded_files().is_empty());
+    }
+
+    #[test]
+    fn stopped_backup_restarts_the_app_and_downloads_the_archive() {
+        let remote = FakeRemoteClient::new();
+
+        BackupCommand::new()
+            .create(
+                &remote,
+                &app_config(),
+                BACKUP,
+                &["uploads".to_string()],
+                true,
+                Some("backups"),
+            )
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(commands[3], "cd /opt/minion/my-app && docker compose stop");
+        assert!(commands[4]
+            .contains("-czf /opt/minion/my-app/backups/my-app-20261017153000.tar.gz 'uploads' &&"));
+        assert_eq!(commands[5], "cd /opt/minion/my-app && docker compose start");
+        assert_eq!(
+            commands[6],
+            "rm -f /opt/minion/my-app/backups/my-app-20261017153000.tar.gz"
+        );
+        assert_eq!(
+            remote.downloaded_files(),
+            vec![(
+                "/opt/minion/my-app/backups/my-app-20261017153000.tar.gz".to_string(),
+                "backups/my-app-20261017153000.tar.gz".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn failed_archive_still_restarts_a_stopped_app() {
+        let remote = FakeRemoteClient::new().on_command("sudo tar", "tar: data: Cannot open", 2);
+
+        let error = BackupCommand::new()
+            .create(&remote, &app_config(), BACKUP, &[], true, None)
+            .unwrap_err();
+
+        assert!(error.to_string().contains("tar: data: Cannot open"));
+        let commands = remote.commands();
+        assert_eq!(commands[5], "cd /opt/minion/my-app && docker compose start");
+        assert_eq!(
+            commands[6],
+            "rm -f /opt/minion/my-app/backups/my-app-20261017153000.tar.gz"
+        );
+    }
+
+    #[test]
+    fn addon_data_is_backed_up_without_app_volumes() {
+        let remote =
+            FakeRemoteClient::new().on_command("for file in addons/*.yml", "postgres\n", 0);
+        let config = AppConfig {
+            app_volumes: String::new(),
+            ..app_config()
+        };
+
+        BackupCommand::new()
+            .create(&remote, &config, BACKUP, &[], false, None)
+            .unwrap();
+
+        assert!(remote.commands()[3].starts_with(
+            "sudo tar -C /opt/minion/my-app/volumes -czf /opt/minion/my-app/backups/my-app-20261017153000.tar.gz 'postgres' &&"
+        ));
+        assert_eq!(
+            select_volumes(&app_config(), &["postgres".to_string()], &[]).unwrap(),
+            vec!["data", "uploads", "postgres"]
+        );
+    }
+
+    #[test]
+    fn volumes_must_be_configured() {
+        let error = select_volumes(&app_config(), &[], &["cache".to_string()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown volume cache. Known volumes: data, uploads"
+        );
+
+        let config = AppConfig {
+            app_volumes: String::new(),
+            ..app_config()
+        };
+        assert!(select_volumes(&config, &[], &[])
+            .unwrap_err()
+            .to_string()
+            .starts_with("my-app has no volumes to back up"));
+    }
+
+    #[test]
+    fn restore_from_the_vps_swaps_volumes_and_restarts_the_app() {
+        let remote = FakeRemoteClient::new().on_command("ls -1", &format!("{}\n", BACKUP), 0);
+
+        BackupCommand::new()
+            .restore(&remote, &app_config(), BACKUP)
+            .unwrap();
+
+        assert_eq!(
+            remote.commands(),
+            vec![
+                "test -f /opt/minion/my-app/docker-compose.yml",
+                "ls -1 /opt/minion/my-app/backups 2>/dev/null | grep -E '^my-app-[0-9]{14}\\.tar\\.gz$' || true",
+                "cd /opt/minion/my-app && docker compose stop",
+                "cd /opt/minion/my-app && staging=$(mktemp -d /opt/minion/my-app/backups/restore.XXXXXX) || exit 1; \
+sudo tar -C \"$staging\" -xzf '/opt/minion/my-app/backups/my-app-20261017153000.tar.gz'; status=$?; \
+if [ $status -eq 0 ]; then for volume in $(ls -A \"$staging\"); do \
+sudo rm -rf \"volumes/$volume\" && sudo mv \"$staging/$volume\" \"volumes/$volume\" || { status=1; break; }; \
+done; fi; sudo rm -rf \"$staging\"; exit $status",
+                "cd /opt/minion/my-app && docker compose up -d",
+            ]
+        );
+        assert!(remote.copied_files().is_empty());
+    }
+
+    #[test]
+    fn restore_uploads_a_local_archive_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join(BACKUP);
+        std::fs::write(&archive, b"archive").unwrap();
+
+        let remote = FakeRemoteClient::new();
+        BackupCommand::new()
+            .restore(&remote, &app_config(), archive.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            remote.copied_files(),
+            vec![(
+                archive.to_string_lossy().to_string(),
+                "/opt/minion/my-app/backups/my-app-20261017153000.tar.gz".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn unknown_backup_is_not_restored() {
+        let remote = FakeRemoteClient::new();
+
+        let error = BackupCommand::new()
+            .restore(&remote, &app_config(), "my-app-20200101000000.tar.gz")
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("Backup my-app-20200101000000.tar.gz not found"));
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.contains("docker compose stop")));
+    }
+}
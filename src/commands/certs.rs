@@ -0,0 +1,672 @@
+//! Certificates Traefik serves, and those supplied by the user instead of
+//! issued through ACME.
+//!
+//! ACME certificates live in Traefik's `acme.json`, keyed by resolver. Traefik's
+//! file provider watches `/opt/traefik/config/dynamic`. Each
+//! certificate is stored under its `certs/` subdirectory, which the provider
+//! ignores because it only reads YAML and TOML files, and registered by a
+//! `certs-<name>.yml` file next to it. Apps with `CERT_RESOLVER=custom` only
+//! enable TLS on their routers, and Traefik serves whichever stored
+//! certificate matches the requested host.
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils::{
+    shell_quote, AppConfig, AppConfigOverrides, RemoteClient, SshClient, CUSTOM_CERTS,
+};
+
+const CERTS_SCRIPT: &str = include_str!("../resources/certs.sh");
+const DYNAMIC_DIR: &str = "/opt/traefik/config/dynamic";
+/// Where `DYNAMIC_DIR` is mounted in the Traefik container.
+const CONTAINER_DYNAMIC_DIR: &str = "/etc/traefik/dynamic";
+const DOMAINS_COMMENT: &str = "# domains: ";
+/// `minion doctor` warns about certificates expiring within this many days.
+const EXPIRY_WARNING_DAYS: u64 = 14;
+
+pub struct CertsCommand;
+
+#[derive(Debug, Clone)]
+pub enum CertsAction {
+    Add {
+        cert: String,
+        key: String,
+        domains: Vec<String>,
+    },
+    List,
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Certificate {
+    /// ACME resolver that issued the certificate, or `custom`.
+    source: String,
+    /// Name given by `minion certs add`, empty for ACME certificates.
+    name: String,
+    /// Subject alternative names.
+    domains: Vec<String>,
+    issuer: String,
+    /// Seconds since the Unix epoch, or `None` when the file is unreadable.
+    expires_at: Option<u64>,
+    expiry_date: String,
+}
+
+/// State of the certificate for one of an app's hosts, as `minion doctor`
+/// reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum HostCertificate {
+    Valid,
+    Missing,
+    Expired { date: String },
+    Expiring { date: String, days: u64 },
+}
+
+impl CertsCommand {
+    pub fn new() -> Self {
+        CertsCommand
+    }
+
+    pub fn execute(&self, action: CertsAction, overrides: AppConfigOverrides) -> Result<()> {
+        if let CertsAction::Add { cert, key, domains } = &action {
+            for path in [cert, key] {
+                if !Path::new(path).is_file() {
+                    return Err(anyhow!("File not found: {}", path));
+                }
+            }
+            validate_domains(domains)?;
+        }
+        let config = AppConfig::load(overrides, false, false)?;
+        config.require_host()?;
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        match action {
+            CertsAction::Add { cert, key, domains } => self.add(&client, &cert, &key, &domains),
+            CertsAction::List => {
+                for line in self.list(&client, SystemTime::now())? {
+                    println!("{}", line);
+                }
+                Ok(())
+            }
+            CertsAction::Remove { name } => self.remove(&client, &name),
+        }
+    }
+
+    /// Uploads a certificate and its key, checks that they belong together
+    /// and cover `domains`, and registers them with Traefik.
+    fn add(
+        &self,
+        client: &dyn RemoteClient,
+        cert: &str,
+        key: &str,
+        domains: &[String],
+    ) -> Result<()> {
+        let name = cert_name(&domains[0]);
+        let (staging, status) = client.execute_command("mktemp -d")?;
+        let staging = staging.trim().to_string();
+        if status != 0 || staging.is_empty() {
+            return Err(anyhow!("Failed to create a staging directory: {}", staging));
+        }
+
+        let installed = self.install(client, &staging, &name, cert, key, domains);
+        client.execute_command(&format!("rm -rf {}", staging))?;
+        installed?;
+
+        println!("✓ Added certificate {} for {}", name, domains.join(", "));
+        println!("  Apps use it when CERT_RESOLVER is custom.");
+        Ok(())
+    }
+
+    fn install(
+        &self,
+        client: &dyn RemoteClient,
+        staging: &str,
+        name: &str,
+        cert: &str,
+        key: &str,
+        domains: &[String],
+    ) -> Result<()> {
+        println!("Uploading certificate...");
+        client.copy_file(cert, &format!("{}/cert.pem", staging))?;
+        client.copy_file(key, &format!("{}/key.pem", staging))?;
+
+        // A wildcard name is checked with a sample host in its place.
+        let hosts = domains
+            .iter()
+            .map(|domain| shell_quote(&domain.replacen('*', "minion-check", 1)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (output, status) = client.execute_command(&format!(
+            "cd {} && openssl x509 -in cert.pem -noout 2>/dev/null || {{ echo 'The certificate is not a PEM certificate'; exit 1; }}; \
+[ \"$(openssl x509 -in cert.pem -noout -pubkey)\" = \"$(openssl pkey -in key.pem -pubout 2>/dev/null)\" ] || {{ echo 'The key does not match the certificate'; exit 1; }}; \
+for host in {}; do openssl x509 -in cert.pem -noout -checkhost \"$host\" | grep -q 'does match' || {{ echo \"The certificate does not cover $host\"; exit 1; }}; done",
+            staging, hosts
+        ))?;
+        if status != 0 {
+            return Err(anyhow!("{}", output.trim()));
+        }
+
+        // Store the files before registering them, so Traefik never loads a
+        // half-written certificate.
+        run(
+            client,
+            &format!(
+                "sudo mkdir -p {dir}/certs && sudo install -m 644 {staging}/cert.pem {dir}/certs/{name}.crt && \
+sudo install -m 600 {staging}/key.pem {dir}/certs/{name}.key",
+                dir = DYNAMIC_DIR,
+                staging = staging,
+                name = name
+            ),
+        )?;
+        let (output, status) = client.execute_with_stdin(
+            &format!("sudo tee {}/certs-{}.yml > /dev/null", DYNAMIC_DIR, name),
+            &mut render_dynamic_config(name, domains).as_bytes(),
+        )?;
+        if status != 0 {
+            return Err(anyhow!("Failed to register the certificate: {}", output));
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, client: &dyn RemoteClient, now: SystemTime) -> Result<Vec<String>> {
+        let certs = certificates(client)?;
+        if certs.is_empty() {
+            return Ok(vec![
+                "No certificates yet. Traefik requests them on the first visit to an app, or add one with `minion certs add`."
+                    .to_string(),
+            ]);
+        }
+
+        let now = unix_secs(now);
+        let mut rows = vec![[
+            "SOURCE".to_string(),
+            "NAME".to_string(),
+            "EXPIRES".to_string(),
+            "ISSUER".to_string(),
+            "DOMAINS".to_string(),
+        ]];
+        for cert in &certs {
+            let expiry = match cert.expires_at {
+                None => "unreadable".to_string(),
+                Some(expires_at) if expires_at <= now => {
+                    format!("{} (expired)", cert.expiry_date)
+                }
+                Some(expires_at) => format!(
+                    "{} ({} days)",
+                    cert.expiry_date,
+                    (expires_at - now) / 86_400
+                ),
+            };
+            rows.push([
+                cert.source.clone(),
+                or_dash(&cert.name),
+                expiry,
+                or_dash(&cert.issuer),
+                or_dash(&cert.domains.join(", ")),
+            ]);
+        }
+
+        let mut widths = [0; 4];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let mut line = String::new();
+                for (cell, width) in row.iter().zip(widths) {
+                    line.push_str(&format!("{:<width$}  ", cell, width = width));
+                }
+                line.push_str(&row[4]);
+                line
+            })
+            .collect())
+    }
+
+    fn remove(&self, client: &dyn RemoteClient, name: &str) -> Result<()> {
+        if !certificates(client)?
+            .iter()
+            .any(|cert| cert.source == CUSTOM_CERTS && cert.name == name)
+        {
+            return Err(anyhow!(
+                "Certificate {} not found. Run `minion certs list` to list the added certificates.",
+                name
+            ));
+        }
+
+        // Unregister first, so Traefik stops using the files before they go.
+        run(
+            client,
+            &format!(
+                "sudo rm -f {dir}/certs-{name}.yml && sudo rm -f {dir}/certs/{name}.crt {dir}/certs/{name}.key",
+                dir = DYNAMIC_DIR,
+                name = name
+            ),
+        )?;
+
+        println!("✓ Removed certificate {}", name);
+        Ok(())
+    }
+}
+
+/// Fails unless some added certificate covers each of `hosts`, which keeps an
+/// app with `CERT_RESOLVER=custom` from being served Traefik's default
+/// certificate.
+pub(super) fn ensure_covered(client: &dyn RemoteClient, hosts: &[&str]) -> Result<()> {
+    let domains = certificates(client)?
+        .into_iter()
+        .filter(|cert| cert.source == CUSTOM_CERTS)
+        .flat_map(|cert| cert.domains)
+        .collect::<Vec<_>>();
+    for host in hosts {
+        if !domains.iter().any(|domain| covers(domain, host)) {
+            return Err(anyhow!(
+                "No certificate covers {}. Add one with `minion certs add` or change CERT_RESOLVER.",
+                host
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks each of `hosts` against the certificates Traefik has, ACME or
+/// added, using the covering certificate that expires last.
+pub(super) fn check_hosts(
+    client: &dyn RemoteClient,
+    hosts: &[&str],
+    now: SystemTime,
+) -> Result<Vec<(String, HostCertificate)>> {
+    let certs = certificates(client)?;
+    let now = unix_secs(now);
+
+    Ok(hosts
+        .iter()
+        .map(|host| {
+            let latest = certs
+                .iter()
+                .filter(|cert| cert.domains.iter().any(|domain| covers(domain, host)))
+                .filter_map(|cert| Some((cert.expires_at?, cert)))
+                .max_by_key(|(expires_at, _)| *expires_at);
+            let state = match latest {
+                None => HostCertificate::Missing,
+                Some((expires_at, cert)) if expires_at <= now => HostCertificate::Expired {
+                    date: cert.expiry_date.clone(),
+                },
+                Some((expires_at, cert)) if expires_at - now < EXPIRY_WARNING_DAYS * 86_400 => {
+                    HostCertificate::Expiring {
+                        date: cert.expiry_date.clone(),
+                        days: (expires_at - now) / 86_400,
+                    }
+                }
+                Some(_) => HostCertificate::Valid,
+            };
+            (host.to_string(), state)
+        })
+        .collect())
+}
+
+/// ACME errors Traefik logged in the last week, preferring those that mention
+/// one of `hosts`.
+pub(super) fn acme_errors(client: &dyn RemoteClient, hosts: &[&str]) -> Result<Vec<String>> {
+    let (output, _) = client.execute_command(
+        "docker logs --since 168h traefik 2>&1 | grep -i acme | grep -iE 'error|unable|fail' | tail -n 50",
+    )?;
+    let lines = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+
+    let mentioned = lines
+        .iter()
+        .filter(|line| {
+            hosts
+                .iter()
+                .any(|host| line.contains(host.trim_start_matches("*.")))
+        })
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    if !mentioned.is_empty() {
+        return Ok(mentioned);
+    }
+    Ok(lines[lines.len().saturating_sub(5)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+fn certificates(client: &dyn RemoteClient) -> Result<Vec<Certificate>> {
+    let (output, status) = client.execute_command(CERTS_SCRIPT)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to list certificates: {}", output.trim()));
+    }
+
+    Ok(parse_certificates(&output))
+}
+
+fn parse_certificates(output: &str) -> Vec<Certificate> {
+    let mut certs: Vec<Certificate> = Vec::new();
+    for line in output.lines() {
+        let mut fields = line.split('\t').map(str::trim);
+        let key = fields.next().unwrap_or_default();
+        let first = fields.next().unwrap_or_default();
+        let second = fields.next().unwrap_or_default();
+        if key == "cert" {
+            certs.push(Certificate {
+                source: first.to_string(),
+                name: second.to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(cert) = certs.last_mut() else {
+            continue;
+        };
+        match key {
+            "expires" => {
+                cert.expires_at = first.parse().ok();
+                cert.expiry_date = second.to_string();
+            }
+            "issuer" => {
+                cert.issuer = match (first.is_empty(), second.is_empty()) {
+                    (false, false) => format!("{} ({})", first, second),
+                    (false, true) => first.to_string(),
+                    _ => second.to_string(),
+                }
+            }
+            "san" if !first.is_empty() && !cert.domains.iter().any(|domain| domain == first) => {
+                cert.domains.push(first.to_string())
+            }
+            _ => {}
+        }
+    }
+    certs
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn or_dash(value: &str) -> String {
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn render_dynamic_config(name: &str, domains: &[String]) -> String {
+    format!(
+        "# Added by `minion certs add`.\n{comment}{domains}\ntls:\n  certificates:\n    - certFile: {dir}/certs/{name}.crt\n      keyFile: {dir}/certs/{name}.key\n",
+        comment = DOMAINS_COMMENT,
+        domains = domains.join(","),
+        dir = CONTAINER_DYNAMIC_DIR,
+        name = name
+    )
+}
+
+/// Whether a certificate for `domain`, possibly a wildcard, is valid for
+/// `host`.
+fn covers(domain: &str, host: &str) -> bool {
+    if domain == host {
+        return true;
+    }
+    match (domain.strip_prefix("*."), host.split_once('.')) {
+        (Some(parent), Some((_, host_parent))) => parent == host_parent,
+        _ => false,
+    }
+}
+
+/// File name for a certificate, taken from its first domain.
+fn cert_name(domain: &str) -> String {
+    domain.replacen('*', "_wildcard", 1)
+}
+
+fn validate_domains(domains: &[String]) -> Result<()> {
+    if domains.is_empty() {
+        return Err(anyhow!("Pass at least one --domain the certificate is for"));
+    }
+    for domain in domains {
+        let name = domain.strip_prefix("*.").unwrap_or(domain);
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+        {
+            return Err(anyhow!(
+                "Invalid domain {}. Use lowercase host names, optionally starting with *.",
+                domain
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn run(client: &dyn RemoteClient, command: &str) -> Result<()> {
+    let (output, status) = client.execute_command(command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to execute command {}: {}", command, output));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+    use std::time::Duration;
+
+    fn domains(domains: &[&str]) -> Vec<String> {
+        domains.iter().map(|domain| domain.to_string()).collect()
+    }
+
+    #[test]
+    fn add_checks_the_files_on_the_vps_and_registers_them() {
+        let remote = FakeRemoteClient::new().on_command("mktemp -d", "/tmp/tmp.abc\n", 0);
+
+        CertsCommand::new()
+            .add(
+                &remote,
+                "fullchain.pem",
+                "key.pem",
+                &domains(&["*.example.com", "example.com"]),
+            )
+            .unwrap();
+
+        assert_eq!(
+            remote.copied_files(),
+            vec![
+                (
+                    "fullchain.pem".to_string(),
+                    "/tmp/tmp.abc/cert.pem".to_string()
+                ),
+                ("key.pem".to_string(), "/tmp/tmp.abc/key.pem".to_string()),
+            ]
+        );
+        let commands = remote.commands();
+        assert!(commands[1].starts_with("cd /tmp/tmp.abc && openssl x509 -in cert.pem -noout"));
+        assert!(commands[1].contains("for host in 'minion-check.example.com' 'example.com'; do"));
+        assert_eq!(
+            commands[2],
+            "sudo mkdir -p /opt/traefik/config/dynamic/certs && sudo install -m 644 /tmp/tmp.abc/cert.pem /opt/traefik/config/dynamic/certs/_wildcard.example.com.crt && \
+sudo install -m 600 /tmp/tmp.abc/key.pem /opt/traefik/config/dynamic/certs/_wildcard.example.com.key"
+        );
+        assert_eq!(
+            remote.stdin_inputs(),
+            vec![(
+                "sudo tee /opt/traefik/config/dynamic/certs-_wildcard.example.com.yml > /dev/null"
+                    .to_string(),
+                b"# Added by `minion certs add`.\n# domains: *.example.com,example.com\ntls:\n  certificates:\n    - certFile: /etc/traefik/dynamic/certs/_wildcard.example.com.crt\n      keyFile: /etc/traefik/dynamic/certs/_wildcard.example.com.key\n"
+                    .to_vec()
+            )]
+        );
+        assert_eq!(commands.last().unwrap(), "rm -rf /tmp/tmp.abc");
+    }
+
+    #[test]
+    fn mismatched_key_is_not_installed() {
+        let remote = FakeRemoteClient::new()
+            .on_command("mktemp -d", "/tmp/tmp.abc\n", 0)
+            .on_command("openssl", "The key does not match the certificate\n", 1);
+
+        let error = CertsCommand::new()
+            .add(&remote, "cert.pem", "key.pem", &domains(&["example.com"]))
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "The key does not match the certificate");
+        let commands = remote.commands();
+        assert!(!commands.iter().any(|command| command.contains("install")));
+        assert_eq!(commands.last().unwrap(), "rm -rf /tmp/tmp.abc");
+    }
+
+    const CERTS: &str = "cert\tletsencrypt\t\n\
+        expires\t1800000000\t2027-01-15\n\
+        issuer\tLet's Encrypt\tR11\n\
+        san\tapp.example.com\n\
+        cert\tcloudflare\t\n\
+        expires\t1600000000\t2020-09-13\n\
+        issuer\tLet's Encrypt\tR10\n\
+        san\t*.example.org\n\
+        san\texample.org\n\
+        cert\tcustom\tbroken.example.net\n\
+        cert\tcustom\tshop.example.net\n\
+        expires\t1900000000\t2030-03-17\n\
+        issuer\t\tshop.example.net\n\
+        san\tshop.example.net\n";
+
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_800_000_000 - 10 * 86_400)
+    }
+
+    #[test]
+    fn parses_acme_and_added_certificates() {
+        let certs = parse_certificates(CERTS);
+
+        assert_eq!(certs.len(), 4);
+        assert_eq!(
+            certs[1],
+            Certificate {
+                source: "cloudflare".to_string(),
+                name: String::new(),
+                domains: domains(&["*.example.org", "example.org"]),
+                issuer: "Let's Encrypt (R10)".to_string(),
+                expires_at: Some(1_600_000_000),
+                expiry_date: "2020-09-13".to_string(),
+            }
+        );
+        assert_eq!(certs[2].expires_at, None);
+        assert_eq!(certs[3].issuer, "shop.example.net");
+    }
+
+    #[test]
+    fn list_shows_every_certificate_with_days_until_expiry() {
+        let remote = FakeRemoteClient::with_responses(vec![(CERTS, 0)]);
+
+        let lines = CertsCommand::new().list(&remote, now()).unwrap();
+
+        assert_eq!(remote.commands(), vec![CERTS_SCRIPT.to_string()]);
+        assert_eq!(
+            lines,
+            vec![
+                "SOURCE       NAME                EXPIRES                 ISSUER               DOMAINS",
+                "letsencrypt  -                   2027-01-15 (10 days)    Let's Encrypt (R11)  app.example.com",
+                "cloudflare   -                   2020-09-13 (expired)    Let's Encrypt (R10)  *.example.org, example.org",
+                "custom       broken.example.net  unreadable              -                    -",
+                "custom       shop.example.net    2030-03-17 (1167 days)  shop.example.net     shop.example.net",
+            ]
+        );
+    }
+
+    #[test]
+    fn hosts_are_checked_against_the_latest_covering_certificate() {
+        let remote = FakeRemoteClient::with_responses(vec![(CERTS, 0)]);
+
+        let checks = check_hosts(
+            &remote,
+            &[
+                "app.example.com",
+                "www.example.org",
+                "shop.example.net",
+                "api.example.com",
+            ],
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            checks,
+            vec![
+                (
+                    "app.example.com".to_string(),
+                    HostCertificate::Expiring {
+                        date: "2027-01-15".to_string(),
+                        days: 10
+                    }
+                ),
+                (
+                    "www.example.org".to_string(),
+                    HostCertificate::Expired {
+                        date: "2020-09-13".to_string()
+                    }
+                ),
+                ("shop.example.net".to_string(), HostCertificate::Valid),
+                ("api.example.com".to_string(), HostCertificate::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    fn acme_errors_prefer_lines_about_the_hosts() {
+        let logs = "time=1 level=error msg=\"Unable to obtain ACME certificate for domains [other.example.net]\"\n\
+            time=2 level=error msg=\"Unable to obtain ACME certificate for domains [app.example.com]\" providerName=letsencrypt.acme\n";
+        let remote = FakeRemoteClient::with_responses(vec![(logs, 0), (logs, 0)]);
+
+        assert_eq!(
+            acme_errors(&remote, &["app.example.com"]).unwrap(),
+            vec!["time=2 level=error msg=\"Unable to obtain ACME certificate for domains [app.example.com]\" providerName=letsencrypt.acme"]
+        );
+        assert_eq!(acme_errors(&remote, &["*.example.org"]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn only_added_certificates_are_removed() {
+        let remote = FakeRemoteClient::with_responses(vec![(CERTS, 0)]);
+
+        assert!(CertsCommand::new()
+            .remove(&remote, "app.example.com")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Certificate app.example.com not found."));
+        assert_eq!(remote.commands().len(), 1);
+    }
+
+    #[test]
+    fn wildcards_cover_one_label() {
+        assert!(covers("example.com", "example.com"));
+        assert!(covers("*.example.com", "pr-1.example.com"));
+        assert!(!covers("*.example.com", "example.com"));
+        assert!(!covers("*.example.com", "a.b.example.com"));
+        assert!(covers("*.example.com", "*.example.com"));
+    }
+
+    #[test]
+    fn domains_are_validated() {
+        assert!(validate_domains(&domains(&["*.example.com", "a-b.example.com"])).is_ok());
+        assert!(validate_domains(&[]).is_err());
+        assert!(validate_domains(&domains(&["example.com;rm"])).is_err());
+        assert!(validate_domains(&domains(&["a.*.example.com"])).is_err());
+    }
+}
@@ -1,18 +1,39 @@
 use anyhow::{anyhow, Result};
+use std::time::SystemTime;
 
-use crate::utils::{AppConfig, AppConfigOverrides, RemoteClient, SshClient};
+use super::addons;
+use super::certs::{self, HostCertificate};
+use super::processes::{find_process, schedule_log_path};
+use crate::utils::{
+    shell_quote, AppConfig, AppConfigOverrides, RemoteClient, SshClient, CUSTOM_CERTS,
+};
 
 pub struct ControlCommand;
 
 #[derive(Debug, Clone)]
 pub enum ControlAction {
-    Status,
+    Status {
+        process: Option<String>,
+    },
     Ps,
-    Logs { follow: bool, tail: u16 },
-    Restart,
+    Logs {
+        follow: bool,
+        tail: u16,
+        process: Option<String>,
+    },
+    Restart {
+        process: Option<String>,
+    },
     Stop,
     Start,
     Doctor,
+    Exec {
+        process: Option<String>,
+        command: Vec<String>,
+    },
+    Shell {
+        process: Option<String>,
+    },
 }
 
 impl ControlCommand {
@@ -38,23 +59,51 @@ impl ControlCommand {
         client: &dyn RemoteClient,
     ) -> Result<()> {
         match action {
-            ControlAction::Status => self.status(client, config),
+            ControlAction::Status { process } => self.status(client, config, process.as_deref()),
             ControlAction::Ps => self.ps(client, config),
-            ControlAction::Logs { follow, tail } => self.logs(client, config, follow, tail),
-            ControlAction::Restart => self.compose_action(client, config, "restart"),
+            ControlAction::Logs {
+                follow,
+                tail,
+                process,
+            } => self.logs(client, config, follow, tail, process.as_deref()),
+            ControlAction::Restart { process } => self.restart(client, config, process.as_deref()),
             ControlAction::Stop => self.compose_action(client, config, "stop"),
             ControlAction::Start => self.compose_action(client, config, "up -d"),
             ControlAction::Doctor => self.doctor(client, config),
+            ControlAction::Exec { process, command } => {
+                self.exec(client, config, process.as_deref(), &command)
+            }
+            ControlAction::Shell { process } => self.shell(client, config, process.as_deref()),
         }
     }
 
-    fn status(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+    fn status(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        process: Option<&str>,
+    ) -> Result<()> {
+        let service = service_arg(config, process)?;
         self.ensure_app(client, config)?;
         let command = format!(
-            "cd {} && docker compose ps && printf '\\nRecent logs:\\n' && docker compose logs --tail 40 2>&1",
-            shell_quote(&app_dir(config))
+            "cd {} && docker compose ps{} && printf '\\nRecent logs:\\n' && docker compose logs --tail 40{} 2>&1",
+            shell_quote(&app_dir(config)),
+            service,
+            service
         );
-        run_and_print(client, &command, "Failed to read app status")
+        run_and_print(client, &command, "Failed to read app status")?;
+
+        if process.is_none() {
+            let lines = addons::status_lines(client, config)?;
+            if !lines.is_empty() {
+                println!("\nAdd-ons:");
+                for line in lines {
+                    println!("{}", line);
+                }
+            }
+        }
+
+        Ok(())
     }
 
     fn ps(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
@@ -69,19 +118,102 @@ impl ControlCommand {
         config: &AppConfig,
         follow: bool,
         tail: u16,
+        process: Option<&str>,
     ) -> Result<()> {
+        let scheduled = match process {
+            Some(name) => find_process(config, name)?.filter(|process| process.is_scheduled()),
+            None => None,
+        };
+        let service = service_arg(config, process)?;
+        self.ensure_app(client, config)?;
+
+        let command = match scheduled {
+            // Runs of scheduled processes are removed when they finish, so
+            // their output only survives in the log file cron appends to.
+            Some(process) => format!(
+                "tail -n {}{} {}",
+                tail,
+                if follow { " -F" } else { "" },
+                shell_quote(&schedule_log_path(&app_dir(config), &process.name))
+            ),
+            None => format!(
+                "cd {} && docker compose logs --tail {}{}{} 2>&1",
+                shell_quote(&app_dir(config)),
+                tail,
+                if follow { " --follow" } else { "" },
+                service
+            ),
+        };
+        let status = client.execute_command_stream(&command)?;
+        if status != 0 {
+            return Err(anyhow!("Failed to read app logs"));
+        }
+
+        Ok(())
+    }
+
+    fn restart(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        process: Option<&str>,
+    ) -> Result<()> {
+        ensure_running_process(config, process, "restart")?;
+        let action = format!("restart{}", service_arg(config, process)?);
+        self.compose_action(client, config, &action)
+    }
+
+    fn exec(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        process: Option<&str>,
+        command: &[String],
+    ) -> Result<()> {
+        if command.is_empty() {
+            return Err(anyhow!("No command given. Usage: minion exec -- <command>"));
+        }
+        ensure_running_process(config, process, "exec into")?;
+        let service = service_arg(config, Some(process.unwrap_or("web")))?;
         self.ensure_app(client, config)?;
 
-        let follow_arg = if follow { " --follow" } else { "" };
         let command = format!(
-            "cd {} && docker compose logs --tail {}{} 2>&1",
+            "cd {} && docker compose exec -T{} {} 2>&1",
             shell_quote(&app_dir(config)),
-            tail,
-            follow_arg
+            service,
+            command
+                .iter()
+                .map(|arg| shell_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" ")
         );
         let status = client.execute_command_stream(&command)?;
         if status != 0 {
-            return Err(anyhow!("Failed to read app logs"));
+            return Err(anyhow!("Command exited with status {}", status));
+        }
+
+        Ok(())
+    }
+
+    fn shell(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        process: Option<&str>,
+    ) -> Result<()> {
+        ensure_running_process(config, process, "open a shell in")?;
+        let service = service_arg(config, Some(process.unwrap_or("web")))?;
+        self.ensure_app(client, config)?;
+
+        // Prefer bash, but slim images often only ship sh.
+        let command = format!(
+            "cd {} && docker compose exec{} sh -c 'if command -v bash >/dev/null 2>&1; then exec bash; else exec sh; fi'",
+            shell_quote(&app_dir(config)),
+            service
+        );
+        let status = client.execute_interactive(&command)?;
+        if status != 0 {
+            return Err(anyhow!("Shell exited with status {}", status));
         }
 
         Ok(())
@@ -124,6 +256,7 @@ impl ControlCommand {
                 shell_quote(&app_dir(config))
             ),
         )?;
+        healthy &= self.check_certificates(client, config)?;
 
         if healthy {
             println!("[ok] Basic server checks passed");
@@ -134,6 +267,52 @@ impl ControlCommand {
         Err(anyhow!("One or more server checks failed"))
     }
 
+    /// Flags app hosts without a certificate or with one that expires soon,
+    /// then shows what Traefik logged about failed ACME requests.
+    fn check_certificates(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<bool> {
+        let hosts = config.hosts();
+        if hosts.is_empty() {
+            return Ok(true);
+        }
+
+        let mut healthy = true;
+        let mut affected = Vec::new();
+        for (host, state) in certs::check_hosts(client, &hosts, SystemTime::now())? {
+            match &state {
+                HostCertificate::Valid => println!("[ok] Certificate for {}", host),
+                HostCertificate::Missing => {
+                    println!("[fail] No certificate for {}", host);
+                    healthy = false;
+                }
+                HostCertificate::Expired { date } => {
+                    println!("[fail] Certificate for {} expired on {}", host, date);
+                    healthy = false;
+                }
+                HostCertificate::Expiring { date, days } => {
+                    println!(
+                        "[warn] Certificate for {} expires on {} ({} days)",
+                        host, date, days
+                    );
+                }
+            }
+            if state != HostCertificate::Valid {
+                affected.push(host);
+            }
+        }
+
+        if !affected.is_empty() && config.cert_resolver != CUSTOM_CERTS {
+            let affected = affected.iter().map(String::as_str).collect::<Vec<_>>();
+            let errors = certs::acme_errors(client, &affected)?;
+            if !errors.is_empty() {
+                println!("Recent ACME errors from Traefik:");
+                for line in errors {
+                    println!("  {}", line);
+                }
+            }
+        }
+        Ok(healthy)
+    }
+
     fn ensure_app(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
         let command = format!(
             "test -f {}/docker-compose.yml",
@@ -179,18 +358,43 @@ fn run_and_print(client: &dyn RemoteClient, command: &str, error_message: &str)
     Ok(())
 }
 
-fn app_dir(config: &AppConfig) -> String {
-    format!("/opt/minion/{}", config.app_name)
+/// Compose service argument for `--process`, with a leading space, or an
+/// empty string to address every service.
+fn service_arg(config: &AppConfig, process: Option<&str>) -> Result<String> {
+    let Some(name) = process else {
+        return Ok(String::new());
+    };
+
+    Ok(match find_process(config, name)? {
+        Some(process) => format!(" {}", process.name),
+        None => format!(" {}", config.app_name),
+    })
+}
+
+/// Scheduled processes only have a container while a run is in progress.
+fn ensure_running_process(config: &AppConfig, process: Option<&str>, verb: &str) -> Result<()> {
+    if let Some(name) = process {
+        if find_process(config, name)?.is_some_and(|process| process.is_scheduled()) {
+            return Err(anyhow!(
+                "{} runs on a schedule and has no container to {}",
+                name,
+                verb
+            ));
+        }
+    }
+
+    Ok(())
 }
 
-fn shell_quote(value: &str) -> String {
-    format!("'{}'", value.replace('\'', "'\\''"))
+fn app_dir(config: &AppConfig) -> String {
+    format!("/opt/minion/{}", config.app_name)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::utils::test_support::FakeRemoteClient;
+    use crate::utils::ProcessConfig;
 
     fn app_config() -> AppConfig {
         AppConfig {
@@ -205,6 +409,7 @@ mod tests {
             ssh_password: None,
             ssh_passphrase: None,
             docker_platform: "linux/amd64".to_string(),
+            ..Default::default()
         }
     }
 
@@ -213,7 +418,11 @@ mod tests {
         let remote = FakeRemoteClient::new();
 
         ControlCommand::new()
-            .execute_action(ControlAction::Status, &app_config(), &remote)
+            .execute_action(
+                ControlAction::Status { process: None },
+                &app_config(),
+                &remote,
+            )
             .unwrap();
 
         let commands = remote.commands();
@@ -222,6 +431,7 @@ mod tests {
             vec![
                 "test -f '/opt/minion/my-app'/docker-compose.yml",
                 "cd '/opt/minion/my-app' && docker compose ps && printf '\\nRecent logs:\\n' && docker compose logs --tail 40 2>&1",
+                "ls -1 /opt/minion/my-app/addons 2>/dev/null || true",
             ]
         );
     }
@@ -253,6 +463,7 @@ mod tests {
                 ControlAction::Logs {
                     follow: false,
                     tail: 100,
+                    process: None,
                 },
                 &app_config(),
                 &remote,
@@ -278,6 +489,7 @@ mod tests {
                 ControlAction::Logs {
                     follow: true,
                     tail: 250,
+                    process: None,
                 },
                 &app_config(),
                 &remote,
@@ -299,6 +511,7 @@ mod tests {
                 ControlAction::Logs {
                     follow: false,
                     tail: 100,
+                    process: None,
                 },
                 &app_config(),
                 &remote,
@@ -308,10 +521,203 @@ mod tests {
         assert!(error.to_string().contains("Failed to read app logs"));
     }
 
+    fn config_with_processes() -> AppConfig {
+        AppConfig {
+            processes: vec![
+                ProcessConfig {
+                    name: "worker".to_string(),
+                    command: "work".to_string(),
+                    replicas: "2".to_string(),
+                    ..Default::default()
+                },
+                ProcessConfig {
+                    name: "nightly".to_string(),
+                    command: "rake cleanup".to_string(),
+                    replicas: "1".to_string(),
+                    schedule: "0 3 * * *".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..app_config()
+        }
+    }
+
+    #[test]
+    fn process_option_limits_status_logs_and_restart_to_one_service() {
+        let remote = FakeRemoteClient::new();
+        let command = ControlCommand::new();
+        let config = config_with_processes();
+
+        command
+            .execute_action(
+                ControlAction::Status {
+                    process: Some("worker".to_string()),
+                },
+                &config,
+                &remote,
+            )
+            .unwrap();
+        command
+            .execute_action(
+                ControlAction::Logs {
+                    follow: true,
+                    tail: 20,
+                    process: Some("web".to_string()),
+                },
+                &config,
+                &remote,
+            )
+            .unwrap();
+        command
+            .execute_action(
+                ControlAction::Restart {
+                    process: Some("worker".to_string()),
+                },
+                &config,
+                &remote,
+            )
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands[1],
+            "cd '/opt/minion/my-app' && docker compose ps worker && printf '\\nRecent logs:\\n' && docker compose logs --tail 40 worker 2>&1"
+        );
+        assert_eq!(
+            remote.streamed_commands(),
+            vec!["cd '/opt/minion/my-app' && docker compose logs --tail 20 --follow my-app 2>&1"]
+        );
+        assert_eq!(
+            commands[4],
+            "cd '/opt/minion/my-app' && docker compose restart worker"
+        );
+    }
+
+    #[test]
+    fn scheduled_process_logs_come_from_its_log_file() {
+        let remote = FakeRemoteClient::new();
+
+        ControlCommand::new()
+            .execute_action(
+                ControlAction::Logs {
+                    follow: false,
+                    tail: 50,
+                    process: Some("nightly".to_string()),
+                },
+                &config_with_processes(),
+                &remote,
+            )
+            .unwrap();
+
+        assert_eq!(
+            remote.streamed_commands(),
+            vec!["tail -n 50 '/opt/minion/my-app/logs/nightly.log'"]
+        );
+    }
+
+    #[test]
+    fn unknown_or_scheduled_process_cannot_be_restarted() {
+        let remote = FakeRemoteClient::new();
+        let command = ControlCommand::new();
+
+        for (process, expected) in [
+            ("mailer", "Unknown process mailer"),
+            ("nightly", "runs on a schedule"),
+        ] {
+            let error = command
+                .execute_action(
+                    ControlAction::Restart {
+                        process: Some(process.to_string()),
+                    },
+                    &config_with_processes(),
+                    &remote,
+                )
+                .unwrap_err();
+            assert!(error.to_string().contains(expected));
+        }
+        assert!(remote.commands().is_empty());
+    }
+
+    #[test]
+    fn exec_runs_quoted_command_in_web_container_and_streams_output() {
+        let remote = FakeRemoteClient::new();
+
+        ControlCommand::new()
+            .execute_action(
+                ControlAction::Exec {
+                    process: None,
+                    command: vec![
+                        "bin/rails".to_string(),
+                        "runner".to_string(),
+                        "puts 'hi'".to_string(),
+                    ],
+                },
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        assert_eq!(
+            remote.streamed_commands(),
+            vec!["cd '/opt/minion/my-app' && docker compose exec -T my-app 'bin/rails' 'runner' 'puts '\\''hi'\\''' 2>&1"]
+        );
+    }
+
+    #[test]
+    fn exec_reports_the_commands_exit_status() {
+        let remote = FakeRemoteClient::with_stream_responses(vec![3]);
+
+        let error = ControlCommand::new()
+            .execute_action(
+                ControlAction::Exec {
+                    process: Some("worker".to_string()),
+                    command: vec!["false".to_string()],
+                },
+                &config_with_processes(),
+                &remote,
+            )
+            .unwrap_err();
+
+        assert!(remote.streamed_commands()[0].contains("docker compose exec -T worker 'false'"));
+        assert_eq!(error.to_string(), "Command exited with status 3");
+    }
+
+    #[test]
+    fn shell_opens_an_interactive_session_in_the_process_container() {
+        let remote = FakeRemoteClient::new();
+
+        ControlCommand::new()
+            .execute_action(
+                ControlAction::Shell {
+                    process: Some("worker".to_string()),
+                },
+                &config_with_processes(),
+                &remote,
+            )
+            .unwrap();
+
+        assert!(remote.streamed_commands().is_empty());
+        assert_eq!(
+            remote.interactive_commands(),
+            vec!["cd '/opt/minion/my-app' && docker compose exec worker sh -c 'if command -v bash >/dev/null 2>&1; then exec bash; else exec sh; fi'"]
+        );
+
+        let error = ControlCommand::new()
+            .execute_action(
+                ControlAction::Shell {
+                    process: Some("nightly".to_string()),
+                },
+                &config_with_processes(),
+                &remote,
+            )
+            .unwrap_err();
+        assert!(error.to_string().contains("runs on a schedule"));
+    }
+
     #[test]
     fn restart_stop_and_start_run_expected_compose_actions() {
         for (action, expected) in [
-            (ControlAction::Restart, "restart"),
+            (ControlAction::Restart { process: None }, "restart"),
             (ControlAction::Stop, "stop"),
             (ControlAction::Start, "up -d"),
         ] {
@@ -346,19 +752,31 @@ mod tests {
 
     #[test]
     fn doctor_checks_server_and_app_then_runs_ps_when_healthy() {
-        let remote = FakeRemoteClient::new();
+        let remote = FakeRemoteClient::new().on_command(
+            "acme.json",
+            "cert\tletsencrypt\t\nexpires\t4102444800\t2100-01-01\nsan\tapp.example.com\n",
+            0,
+        );
 
         ControlCommand::new()
             .execute_action(ControlAction::Doctor, &app_config(), &remote)
             .unwrap();
 
+        let commands = remote.commands();
+        assert_eq!(commands.len(), 7);
         assert_eq!(
-            remote.commands(),
-            vec![
+            commands[..4],
+            [
                 "docker --version",
                 "docker compose version",
                 "docker ps --filter 'name=traefik' --format '{{.Status}}' | grep -q Up",
                 "test -f '/opt/minion/my-app'/docker-compose.yml",
+            ]
+        );
+        assert!(commands[4].contains("/opt/traefik/data/acme.json"));
+        assert_eq!(
+            commands[5..],
+            [
                 "test -f '/opt/minion/my-app'/docker-compose.yml",
                 "cd '/opt/minion/my-app' && docker compose ps",
             ]
@@ -372,6 +790,10 @@ mod tests {
             ("compose missing", 1),
             ("", 0),
             ("", 0),
+            (
+                "cert\tletsencrypt\t\nexpires\t4102444800\t2100-01-01\nsan\tapp.example.com\n",
+                0,
+            ),
         ]);
 
         let error = ControlCommand::new()
@@ -381,8 +803,9 @@ mod tests {
         assert!(error
             .to_string()
             .contains("One or more server checks failed"));
+        let commands = remote.commands();
         assert_eq!(
-            remote.commands(),
+            commands[..4],
             vec![
                 "docker --version",
                 "docker compose version",
@@ -390,5 +813,23 @@ mod tests {
                 "test -f '/opt/minion/my-app'/docker-compose.yml",
             ]
         );
+        assert_eq!(commands.len(), 5);
+    }
+
+    #[test]
+    fn doctor_fails_without_a_certificate_and_reads_acme_errors() {
+        let remote = FakeRemoteClient::new();
+
+        let error = ControlCommand::new()
+            .execute_action(ControlAction::Doctor, &app_config(), &remote)
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("One or more server checks failed"));
+        assert_eq!(
+            remote.commands().last().unwrap(),
+            "docker logs --since 168h traefik 2>&1 | grep -i acme | grep -iE 'error|unable|fail' | tail -n 50"
+        );
     }
 }
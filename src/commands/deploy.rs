@@ -1,10 +1,21 @@
+use super::certs;
+use super::env::env_file_path;
+use super::hooks;
+use super::image::provide_image;
+use super::middlewares;
+use super::processes;
+use super::release::{self, release_dir};
+use super::rollout;
+use super::routes;
+use super::secrets::{install_secrets, prepare_secrets};
 use crate::utils::{
-    AppConfig, AppConfigOverrides, CommandExecutor, LocalCommandRunner, RemoteClient, SshClient,
+    utc_timestamp, AppConfig, AppConfigOverrides, CommandExecutor, LocalCommandRunner, Manifest,
+    RemoteClient, SshClient, CUSTOM_CERTS, HTTP_RESOLVER,
 };
 use anyhow::{anyhow, Result};
 use std::path::Path;
 use std::rc::Rc;
-use tempfile::Builder;
+use std::time::SystemTime;
 
 // Include the resource files at compile time
 const APP_DOCKER_COMPOSE: &str = include_str!("../resources/docker-compose.app.yml");
@@ -48,13 +59,31 @@ impl DeployCommand {
         Ok(mappings)
     }
 
+    /// Names a release after the deploy time, suffixed with the short git SHA
+    /// when the project is a git checkout.
+    fn release_id(&self) -> String {
+        let timestamp = utc_timestamp(SystemTime::now());
+        let sha = self
+            .command_runner
+            .execute("git", &["rev-parse", "--short", "HEAD"])
+            .ok()
+            .filter(|(_, status)| *status == 0)
+            .map(|(output, _)| output.trim().to_string())
+            .filter(|sha| !sha.is_empty() && sha.chars().all(|c| c.is_ascii_hexdigit()));
+
+        match sha {
+            Some(sha) => format!("{}-{}", timestamp, sha),
+            None => timestamp,
+        }
+    }
+
     fn render_compose(
-        app_name: &str,
-        urls: &str,
-        port: &str,
+        config: &AppConfig,
+        release: &str,
         volume_mappings: &[String],
     ) -> Result<String> {
-        let url_list: Vec<&str> = urls
+        let url_list: Vec<&str> = config
+            .app_url
             .split(',')
             .map(str::trim)
             .filter(|url| !url.is_empty())
@@ -63,23 +92,33 @@ impl DeployCommand {
             return Err(anyhow!("At least one URL must be provided"));
         }
 
-        let host_rules = url_list
-            .iter()
-            .map(|url| format!("Host(`{}`)", url))
-            .collect::<Vec<_>>()
-            .join(" || ");
-
         let volumes_section = if volume_mappings.is_empty() {
             String::new()
         } else {
             format!("    volumes:\n{}", volume_mappings.join("\n"))
         };
+        let process_services = processes::render_services(config, release, &volumes_section);
 
         Ok(APP_DOCKER_COMPOSE
-            .replace("{{app_name}}", app_name)
-            .replace("{{host_rules}}", &host_rules)
-            .replace("{{port}}", port)
-            .replace("{{volumes_section}}", &volumes_section))
+            .replace("{{app_name}}", &config.app_name)
+            .replace("{{release}}", release)
+            .replace("{{host_rules}}", &host_rules(&url_list))
+            .replace(
+                "{{tls_labels}}",
+                &tls_labels(config, &config.app_name, &url_list),
+            )
+            .replace(
+                "{{middleware_labels}}",
+                &middlewares::labels(config, &routes::routers(config)),
+            )
+            .replace("{{route_labels}}", &routes::labels(config))
+            .replace("{{port}}", &config.app_port)
+            .replace(
+                "{{healthcheck_section}}",
+                &rollout::healthcheck_section(config),
+            )
+            .replace("{{volumes_section}}", &volumes_section)
+            .replace("{{process_services}}", &process_services))
     }
 
     fn deploy_app(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
@@ -93,48 +132,53 @@ impl DeployCommand {
             return Err(anyhow!("At least one URL must be provided"));
         }
         let parsed_volumes = Self::parse_volumes(&config.app_volumes)?;
-
-        // Build and save the Docker image
-        println!("Building Docker image for {}...", config.docker_platform);
-        let image_name = format!("minion_{}", config.app_name);
-        let platform_arg = format!("--platform={}", config.docker_platform);
-
-        // Build the image with platform specified
-        let (output, status) = self
-            .command_runner
-            .execute("docker", &["build", "-t", &image_name, ".", &platform_arg])?;
-
-        if status != 0 {
-            return Err(anyhow!("Failed to build Docker image: {}", output));
+        if let Some(environment) = &config.environment {
+            println!(
+                "Deploying {} to the {} environment",
+                config.app_name, environment
+            );
         }
 
-        println!("✓ Docker image built successfully");
+        ensure_cert_resolver(client, config)?;
+        middlewares::ensure_unique(client, config)?;
+        routes::ensure_unique(client, config)?;
 
-        // Create a temporary file with .tar extension
-        let temp_file = Builder::new().prefix("minion_").suffix(".tar").tempfile()?;
-        let temp_path = temp_file.path().to_string_lossy().to_string();
+        let release = self.release_id();
+        let secrets = prepare_secrets(self.command_runner.as_ref(), config)?;
 
-        // Save the image to the temporary file
-        println!("Saving image to temporary file...");
-        let (output, status) = self
-            .command_runner
-            .execute("docker", &["save", "-o", &temp_path, &image_name])?;
+        let image_name = format!("minion_{}:{}", config.app_name, release);
+        if config.image_source == "local" {
+            // Build the image with platform specified
+            println!("Building Docker image for {}...", config.docker_platform);
+            let platform_arg = format!("--platform={}", config.docker_platform);
+            let (output, status) = self
+                .command_runner
+                .execute("docker", &["build", "-t", &image_name, ".", &platform_arg])?;
 
-        if status != 0 {
-            return Err(anyhow!("Failed to save Docker image: {}", output));
-        }
+            if status != 0 {
+                return Err(anyhow!("Failed to build Docker image: {}", output));
+            }
 
-        println!("✓ Docker image saved to temporary file");
+            println!("✓ Docker image built successfully");
+        }
 
         println!("Creating app directory on VPS...");
         let app_dir = format!("/opt/minion/{}", config.app_name);
         let volumes_dir = format!("{}/volumes", app_dir);
+        let release_dir = release_dir(&app_dir, &release);
+        let env_file = env_file_path(config);
 
-        // Create directory and set permissions
+        // Create directory and set permissions. Volume contents are left
+        // alone: add-ons keep their data there, owned by their own users.
         let setup_commands = [
             &format!("sudo mkdir -p {}", app_dir),
             &format!("sudo mkdir -p {}", volumes_dir),
-            &format!("sudo chown -R minion:minion {}", app_dir),
+            &format!("sudo mkdir -p {}", release_dir),
+            &format!(
+                "sudo find {} -path '{}/*' -prune -o -exec chown minion:minion {{}} +",
+                app_dir, volumes_dir
+            ),
+            &format!("touch {} && chmod 600 {}", env_file, env_file),
         ];
 
         for cmd in setup_commands {
@@ -144,6 +188,8 @@ impl DeployCommand {
             }
         }
 
+        install_secrets(client, config, &app_dir, &secrets)?;
+
         // Process volumes
         let mut volume_mappings = Vec::new();
 
@@ -174,13 +220,8 @@ impl DeployCommand {
         }
 
         // Generate and upload docker-compose file
-        let compose_content = Self::render_compose(
-            &config.app_name,
-            &config.app_url,
-            &config.app_port,
-            &volume_mappings,
-        )?;
-        let compose_path = format!("{}/docker-compose.yml", app_dir);
+        let compose_content = Self::render_compose(config, &release, &volume_mappings)?;
+        let compose_path = format!("{}/docker-compose.yml", release_dir);
 
         println!("Creating docker-compose.yml...");
         let write_compose = format!("cat > {} << 'EOL'\n{}\nEOL", compose_path, compose_content);
@@ -192,42 +233,42 @@ impl DeployCommand {
 
         println!("✓ Docker compose file created");
 
-        // Copy the image file to the VPS
-        println!("Copying Docker image to VPS...");
-        client.copy_file(&temp_path, &format!("{}/{}.tar", app_dir, config.app_name))?;
+        provide_image(
+            self.command_runner.as_ref(),
+            client,
+            config,
+            &app_dir,
+            &image_name,
+        )?;
 
-        // Load the image and clean up
-        let deploy_commands = [
-            &format!("cd {} && docker load -i {}.tar", app_dir, config.app_name),
-            &format!("rm {}/{}.tar", app_dir, config.app_name),
-            &format!("cd {} && docker compose up -d", app_dir),
-        ];
+        hooks::run_pre_deploy(client, config, &app_dir, &release_dir)?;
 
-        for cmd in deploy_commands {
-            let (output, status) = client.execute_command(cmd)?;
-            if status != 0 {
-                return Err(anyhow!("Failed to execute command {}: {}", cmd, output));
-            }
-        }
+        // Start the new version next to the old one and cut over once healthy
+        release::activate_release(client, config, &app_dir, &release)?;
+        processes::install_schedules(client, config, &app_dir)?;
+        hooks::run_post_deploy(client, config, &app_dir)?;
 
-        println!("✓ Application deployed successfully!");
         println!(
-            "✓ Your app should be available at https://{} shortly",
-            url_list[0]
+            "✓ Application deployed successfully as release {}!",
+            release
         );
+        println!("✓ Your app is available at https://{}", url_list[0]);
         Ok(())
     }
 
     pub fn execute(&self, options: DeployOptions) -> Result<()> {
         let interactive = !(options.yes || options.ci);
+        Manifest::migrate_project()?;
         let config = AppConfig::load(options.overrides, interactive, interactive)?;
         config.require_deploy()?;
 
-        // Verify dockerfile before proceeding
-        if !Path::new("Dockerfile").exists() {
-            return Err(anyhow!("Dockerfile not found in current directory"));
+        // Verify dockerfile before proceeding, unless the image is pulled
+        if config.image_source != "registry" {
+            if !Path::new("Dockerfile").exists() {
+                return Err(anyhow!("Dockerfile not found in current directory"));
+            }
+            println!("✓ Dockerfile found");
         }
-        println!("✓ Dockerfile found");
 
         // Connect to VPS and deploy
         println!("Connecting to {} as {}...", config.host, config.ssh_user);
@@ -249,7 +290,7 @@ impl DeployCommand {
         let config = AppConfig::load(options.overrides, interactive, interactive)?;
         config.require_deploy()?;
 
-        if !project_dir.join("Dockerfile").exists() {
+        if config.image_source != "registry" && !project_dir.join("Dockerfile").exists() {
             return Err(anyhow!("Dockerfile not found in current directory"));
         }
 
@@ -257,24 +298,117 @@ impl DeployCommand {
     }
 }
 
+/// Traefik rule matching any of `urls`. A wildcard like `*.example.com`
+/// matches any single label in its place.
+pub(super) fn host_rules(urls: &[&str]) -> String {
+    urls.iter()
+        .map(|url| match url.strip_prefix("*.") {
+            // `[.]` rather than `\.`, which the quoted YAML label would
+            // otherwise need escaped again.
+            Some(domain) => format!("HostRegexp(`^[^.]+[.]{}$`)", domain.replace('.', "[.]")),
+            None => format!("Host(`{}`)", url),
+        })
+        .collect::<Vec<_>>()
+        .join(" || ")
+}
+
+/// Checks that `minion setup` configured the app's certificate resolver, or
+/// that added certificates cover its hosts, since Traefik would otherwise
+/// only log the failure.
+fn ensure_cert_resolver(client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+    if config.cert_resolver == HTTP_RESOLVER {
+        return Ok(());
+    }
+    if config.cert_resolver == CUSTOM_CERTS {
+        return certs::ensure_covered(client, &config.hosts());
+    }
+
+    let (_, status) = client.execute_command(&format!(
+        "grep -q '^  {}:$' /opt/traefik/config/traefik.yml",
+        config.cert_resolver
+    ))?;
+    if status != 0 {
+        return Err(anyhow!(
+            "Traefik on {} has no {} certificate resolver. Run `minion setup` again and enable the {} DNS provider.",
+            config.host,
+            config.cert_resolver,
+            config.cert_resolver
+        ));
+    }
+
+    Ok(())
+}
+
+/// TLS labels for `router`. Traefik cannot derive certificate domains from a
+/// `HostRegexp` rule, so with a wildcard among `urls` every domain is listed.
+pub(super) fn tls_labels(config: &AppConfig, router: &str, urls: &[&str]) -> String {
+    if config.cert_resolver == CUSTOM_CERTS {
+        return format!("      - \"traefik.http.routers.{}.tls=true\"\n", router);
+    }
+
+    let mut labels = format!(
+        "      - \"traefik.http.routers.{}.tls.certresolver={}\"\n",
+        router, config.cert_resolver
+    );
+    if urls.iter().any(|url| url.starts_with("*.")) {
+        for (index, url) in urls.iter().enumerate() {
+            labels.push_str(&format!(
+                "      - \"traefik.http.routers.{}.tls.domains[{}].main={}\"\n",
+                router, index, url
+            ));
+        }
+    }
+    labels
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::utils::test_support::{FakeLocalCommandRunner, FakeRemoteClient};
+    use crate::utils::ProcessConfig;
 
     fn app_config() -> AppConfig {
         AppConfig {
+            environment: None,
             host: "example.com".to_string(),
             app_name: "my-app".to_string(),
             app_url: "app.example.com".to_string(),
             app_port: "3000".to_string(),
             app_volumes: String::new(),
+            cert_resolver: "letsencrypt".to_string(),
             ssh_user: "minion".to_string(),
             ssh_key_path: None,
             ssh_private_key: None,
             ssh_password: None,
             ssh_passphrase: None,
             docker_platform: "linux/amd64".to_string(),
+            image_compression: "gzip".to_string(),
+            image_source: "local".to_string(),
+            image: String::new(),
+            registry_username: None,
+            registry_password: None,
+            healthcheck_type: "tcp".to_string(),
+            healthcheck_path: "/".to_string(),
+            healthcheck_wait: "60".to_string(),
+            healthcheck_interval: "10".to_string(),
+            healthcheck_timeout: "5".to_string(),
+            healthcheck_retries: "3".to_string(),
+            pre_deploy: String::new(),
+            post_deploy: String::new(),
+            backup_keep: "7".to_string(),
+            backup_schedule: "0 3 * * *".to_string(),
+            backup_s3_endpoint: String::new(),
+            backup_s3_bucket: String::new(),
+            backup_s3_prefix: String::new(),
+            backup_s3_keep: "30".to_string(),
+            backup_s3_access_key_id: None,
+            backup_s3_secret_access_key: None,
+            secrets_file: ".minion.secrets".to_string(),
+            secrets_decrypt: "local".to_string(),
+            age_key: None,
+            processes: Vec::new(),
+            routes: Vec::new(),
+            middlewares: Default::default(),
         }
     }
 
@@ -285,7 +419,7 @@ mod tests {
     fn compose_write_command(commands: &[String]) -> &str {
         commands
             .iter()
-            .find(|command| command.contains("docker-compose.yml"))
+            .find(|command| command.starts_with("cat > ") && command.contains("docker-compose.yml"))
             .expect("compose write command not found")
     }
 
@@ -315,68 +449,295 @@ mod tests {
 
     #[test]
     fn successful_deploy_runs_local_and_remote_steps() {
-        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![(
+            "abc1234\n",
+            0,
+        )]));
         let command = command_with_runner(runner.clone());
         let remote = FakeRemoteClient::new();
 
         command.deploy_app(&remote, &app_config()).unwrap();
 
         let local_commands = runner.commands();
-        assert_eq!(local_commands.len(), 2);
-        assert_eq!(local_commands[0].command, "docker");
+        assert_eq!(local_commands.len(), 4);
+        assert_eq!(local_commands[0].command, "git");
+        assert_eq!(local_commands[0].args, vec!["rev-parse", "--short", "HEAD"]);
+        assert_eq!(local_commands[1].command, "docker");
+        let image = local_commands[1].args[2].clone();
+        assert!(image.starts_with("minion_my-app:"));
+        assert!(image.ends_with("-abc1234"));
         assert_eq!(
-            local_commands[0].args,
+            local_commands[1].args,
+            vec!["build", "-t", image.as_str(), ".", "--platform=linux/amd64"]
+        );
+        assert_eq!(local_commands[2].args[..2], ["image", "inspect"]);
+        assert_eq!(local_commands[3].command, "bash");
+        assert_eq!(
+            local_commands[3].args,
             vec![
-                "build",
-                "-t",
-                "minion_my-app",
-                ".",
-                "--platform=linux/amd64"
+                "-o".to_string(),
+                "pipefail".to_string(),
+                "-c".to_string(),
+                format!("'docker' 'save' '{}' | gzip -1 -c", image),
             ]
         );
-        assert_eq!(local_commands[1].command, "docker");
-        assert_eq!(local_commands[1].args[0], "save");
-        assert_eq!(local_commands[1].args[3], "minion_my-app");
 
+        let release = image.trim_start_matches("minion_my-app:");
+        let release_dir = format!("/opt/minion/my-app/releases/{}", release);
         let remote_commands = remote.commands();
         assert!(remote_commands.contains(&"sudo mkdir -p /opt/minion/my-app".to_string()));
         assert!(remote_commands.contains(&"sudo mkdir -p /opt/minion/my-app/volumes".to_string()));
-        assert!(
-            remote_commands.contains(&"sudo chown -R minion:minion /opt/minion/my-app".to_string())
-        );
-        assert!(compose_write_command(&remote_commands).contains("Host(`app.example.com`)"));
-        assert!(remote_commands
-            .contains(&"cd /opt/minion/my-app && docker load -i my-app.tar".to_string()));
-        assert!(remote_commands.contains(&"rm /opt/minion/my-app/my-app.tar".to_string()));
+        assert!(remote_commands.contains(&format!("sudo mkdir -p {}", release_dir)));
+        assert!(remote_commands.contains(
+            &"sudo find /opt/minion/my-app -path '/opt/minion/my-app/volumes/*' -prune -o -exec chown minion:minion {} +".to_string()
+        ));
+        let compose = compose_write_command(&remote_commands);
+        assert!(compose.starts_with(&format!("cat > {}/docker-compose.yml", release_dir)));
+        assert!(compose.contains("Host(`app.example.com`)"));
+        assert!(compose.contains(&format!("image: {}", image)));
+        assert!(compose.contains("    env_file:\n      - app.env\n      - secrets.env\n"));
+        assert!(remote_commands.contains(
+            &"touch /opt/minion/my-app/app.env && chmod 600 /opt/minion/my-app/app.env".to_string()
+        ));
+        assert!(remote_commands.contains(&format!(
+            "cp {}/docker-compose.yml /opt/minion/my-app/docker-compose.yml",
+            release_dir
+        )));
+        assert!(remote_commands.contains(&format!(
+            "echo {} > /opt/minion/my-app/current_release",
+            release
+        )));
         assert!(
             remote_commands.contains(&"cd /opt/minion/my-app && docker compose up -d".to_string())
         );
 
-        let copied_files = remote.copied_files();
-        assert_eq!(copied_files.len(), 1);
-        assert_eq!(copied_files[0].1, "/opt/minion/my-app/my-app.tar");
+        let uploads = remote.stdin_inputs();
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(
+            uploads[0].0,
+            "set -o pipefail; (gzip -dc | docker load) 2>&1"
+        );
+        assert!(remote.copied_files().is_empty());
     }
 
     #[test]
-    fn docker_build_failure_stops_before_remote_commands() {
+    fn registry_deploy_skips_local_build_and_pulls_on_vps() {
         let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![(
-            "build failed",
-            1,
+            "abc1234\n",
+            0,
         )]));
         let command = command_with_runner(runner.clone());
         let remote = FakeRemoteClient::new();
+        let config = AppConfig {
+            image_source: "registry".to_string(),
+            image: "ghcr.io/acme/app:1.2.3".to_string(),
+            ..app_config()
+        };
+
+        command.deploy_app(&remote, &config).unwrap();
+
+        let local_commands = runner.commands();
+        assert_eq!(local_commands.len(), 1);
+        assert_eq!(local_commands[0].command, "git");
+        assert_eq!(
+            remote.streamed_commands()[0],
+            "docker pull 'ghcr.io/acme/app:1.2.3' 2>&1"
+        );
+        assert!(remote.commands().iter().any(
+            |command| command.starts_with("docker tag 'ghcr.io/acme/app:1.2.3' minion_my-app:")
+        ));
+        assert!(remote.stdin_inputs().is_empty());
+    }
+
+    #[test]
+    fn processes_are_rendered_into_the_compose_project_and_scheduled() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![(
+            "abc1234\n",
+            0,
+        )]));
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::new();
+        let config = AppConfig {
+            processes: vec![
+                ProcessConfig {
+                    name: "worker".to_string(),
+                    command: "bundle exec sidekiq".to_string(),
+                    replicas: "2".to_string(),
+                    ..Default::default()
+                },
+                ProcessConfig {
+                    name: "nightly".to_string(),
+                    command: "rake cleanup".to_string(),
+                    replicas: "1".to_string(),
+                    schedule: "0 3 * * *".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..app_config()
+        };
+
+        command.deploy_app(&remote, &config).unwrap();
+
+        let remote_commands = remote.commands();
+        let compose = compose_write_command(&remote_commands);
+        assert!(compose.contains("\n  worker:\n    image: minion_my-app:"));
+        assert!(compose.contains("    deploy:\n      replicas: 2\n"));
+        assert!(compose.contains("\n  nightly:\n"));
+        assert!(compose.contains("\nnetworks:\n  traefik_network:\n    external: true"));
+        assert!(remote_commands.iter().any(|command| command.contains(
+            "printf '%s\\n' '0 3 * * * cd /opt/minion/my-app && docker compose run --rm --no-deps -T nightly"
+        )));
+    }
+
+    #[test]
+    fn http_healthcheck_is_added_and_public_url_is_checked_before_recording_release() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![(
+            "abc1234\n",
+            0,
+        )]));
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::new();
+        let config = AppConfig {
+            healthcheck_type: "http".to_string(),
+            healthcheck_path: "/health".to_string(),
+            ..app_config()
+        };
+
+        command.deploy_app(&remote, &config).unwrap();
+
+        let remote_commands = remote.commands();
+        let compose = compose_write_command(&remote_commands);
+        assert!(compose.contains(
+            "      - traefik_network\n    healthcheck:\n      test: [\"CMD-SHELL\", \"curl -fsS -o /dev/null 'http://localhost:3000/health'"
+        ));
+        assert!(compose.contains("      start_period: 60s\n    labels:\n"));
+        let public_check = remote_commands
+            .iter()
+            .position(|command| command.contains("'https://app.example.com/health'"))
+            .unwrap();
+        let recorded = remote_commands
+            .iter()
+            .position(|command| command.ends_with("> /opt/minion/my-app/current_release"))
+            .unwrap();
+        assert!(public_check < recorded);
+    }
+
+    #[test]
+    fn uncompressed_image_is_piped_from_docker_save_into_docker_load() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![
+            ("", 0),
+            ("", 0),
+            ("", 0),
+            ("image tar bytes", 0),
+        ]));
+        let command = command_with_runner(runner.clone());
+        let remote = FakeRemoteClient::new();
+        let mut config = app_config();
+        config.image_compression = "none".to_string();
+
+        command.deploy_app(&remote, &config).unwrap();
+
+        let save = &runner.commands()[3];
+        assert_eq!(save.command, "docker");
+        assert_eq!(save.args[0], "save");
+        assert_eq!(
+            remote.stdin_inputs(),
+            vec![("docker load 2>&1".to_string(), b"image tar bytes".to_vec())]
+        );
+    }
+
+    #[test]
+    fn remote_load_failure_stops_before_activation() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::new().on_command(
+            "docker load",
+            "gzip: stdin: unexpected end of file",
+            1,
+        );
+
+        let error = command.deploy_app(&remote, &app_config()).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("Failed to load Docker image on VPS: gzip: stdin: unexpected end of file"));
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.contains("current_release")));
+    }
+
+    #[test]
+    fn failing_pre_deploy_hook_keeps_the_previous_version() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::with_stream_responses(vec![1]);
+        let config = AppConfig {
+            pre_deploy: "bin/rails db:migrate".to_string(),
+            post_deploy: "bin/notify".to_string(),
+            ..app_config()
+        };
+
+        let error = command.deploy_app(&remote, &config).unwrap_err();
+
+        assert!(error.to_string().starts_with("PRE_DEPLOY failed"));
+        assert_eq!(remote.streamed_commands().len(), 1);
+        assert!(remote.streamed_commands()[0].contains("sh -c 'bin/rails db:migrate'"));
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.contains("cp ") || command.contains("current_release")));
+    }
+
+    #[test]
+    fn secrets_are_decrypted_before_build_and_written_next_to_app_env() {
+        let secrets = tempfile::NamedTempFile::new().unwrap();
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![
+            ("abc1234\n", 0),
+            ("API_TOKEN=s3cr3t\n", 0),
+        ]));
+        let command = command_with_runner(runner.clone());
+        let remote = FakeRemoteClient::new();
+        let mut config = app_config();
+        config.secrets_file = secrets.path().to_string_lossy().to_string();
+
+        command.deploy_app(&remote, &config).unwrap();
+
+        let local_commands = runner.commands();
+        assert_eq!(local_commands[1].command, "sops");
+        assert_eq!(local_commands[2].args[0], "build");
+        let remote_commands = remote.commands();
+        let write = remote_commands
+            .iter()
+            .find(|command| command.contains("cat > '/opt/minion/my-app/secrets.env'"))
+            .expect("secrets write command not found");
+        assert!(write.ends_with("\nAPI_TOKEN='s3cr3t'\nMINION_ENV"));
+    }
+
+    #[test]
+    fn docker_build_failure_stops_before_remote_commands() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![
+            ("fatal: not a git repository", 128),
+            ("build failed", 1),
+        ]));
+        let command = command_with_runner(runner.clone());
+        let remote = FakeRemoteClient::new();
 
         let error = command.deploy_app(&remote, &app_config()).unwrap_err();
 
         assert!(error.to_string().contains("Failed to build Docker image"));
-        assert_eq!(runner.commands().len(), 1);
-        assert!(remote.commands().is_empty());
+        assert_eq!(runner.commands().len(), 2);
+        let remote_commands = remote.commands();
+        assert_eq!(remote_commands.len(), 1);
+        assert!(remote_commands[0].contains("traefik[.]http[.](routers|services)"));
         assert!(remote.copied_files().is_empty());
     }
 
     #[test]
-    fn docker_save_failure_stops_before_remote_copy() {
+    fn docker_save_failure_stops_before_activation() {
         let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![
+            ("", 0),
+            ("", 0),
             ("", 0),
             ("save failed", 1),
         ]));
@@ -386,9 +747,11 @@ mod tests {
         let error = command.deploy_app(&remote, &app_config()).unwrap_err();
 
         assert!(error.to_string().contains("Failed to save Docker image"));
-        assert_eq!(runner.commands().len(), 2);
-        assert!(remote.commands().is_empty());
-        assert!(remote.copied_files().is_empty());
+        assert_eq!(runner.commands().len(), 4);
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.contains("current_release")));
     }
 
     #[test]
@@ -421,6 +784,39 @@ mod tests {
         assert!(remote.commands().is_empty());
     }
 
+    #[test]
+    fn release_id_falls_back_to_timestamp_outside_git() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::with_responses(vec![(
+            "fatal: not a git repository",
+            128,
+        )]));
+        let command = command_with_runner(runner);
+
+        let release = command.release_id();
+
+        assert_eq!(release.len(), 14);
+        assert!(release.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn failed_health_check_reports_error_after_image_load() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::new()
+            .on_command("docker compose ps -q", "old1\n", 0)
+            .on_command("docker compose ps -q", "old1\nnew1\n", 0)
+            .on_command("id=new1;", "", 1);
+
+        let error = command.deploy_app(&remote, &app_config()).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("previous version is still serving traffic"));
+        let remote_commands = remote.commands();
+        assert_eq!(remote.stdin_inputs().len(), 1);
+        assert!(remote_commands.contains(&"docker rm -f new1".to_string()));
+    }
+
     #[test]
     fn multiple_urls_render_traefik_host_rules() {
         let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
@@ -436,6 +832,176 @@ mod tests {
             .contains("Host(`app.example.com`) || Host(`www.example.com`)"));
     }
 
+    #[test]
+    fn wildcard_urls_use_a_dns_resolver_and_list_their_domains() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::new();
+        let mut config = app_config();
+        config.app_url = "*.preview.example.com,preview.example.com".to_string();
+        config.cert_resolver = "cloudflare".to_string();
+
+        command.deploy_app(&remote, &config).unwrap();
+
+        let remote_commands = remote.commands();
+        assert_eq!(
+            remote_commands[0],
+            "grep -q '^  cloudflare:$' /opt/traefik/config/traefik.yml"
+        );
+        let compose = compose_write_command(&remote_commands);
+        assert!(compose.contains(
+            "rule=HostRegexp(`^[^.]+[.]preview[.]example[.]com$`) || Host(`preview.example.com`)\""
+        ));
+        assert!(compose.contains("\"traefik.http.routers.my-app.tls.certresolver=cloudflare\""));
+        assert!(compose
+            .contains("\"traefik.http.routers.my-app.tls.domains[0].main=*.preview.example.com\""));
+        assert!(compose
+            .contains("\"traefik.http.routers.my-app.tls.domains[1].main=preview.example.com\""));
+    }
+
+    #[test]
+    fn wildcard_first_url_is_checked_through_a_sample_host() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::new();
+        let mut config = app_config();
+        config.app_url = "*.preview.example.com,preview.example.com".to_string();
+        config.cert_resolver = "cloudflare".to_string();
+
+        command.deploy_app(&remote, &config).unwrap();
+
+        let remote_commands = remote.commands();
+        let public_check = remote_commands
+            .iter()
+            .find(|command| command.contains("--resolve"))
+            .unwrap();
+        assert!(public_check.contains(
+            "--resolve 'minion-check.preview.example.com:443:127.0.0.1' 'https://minion-check.preview.example.com/'"
+        ));
+    }
+
+    #[test]
+    fn custom_certificates_only_enable_tls_once_they_cover_every_host() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let mut config = app_config();
+        config.app_url = "app.example.com,www.example.com".to_string();
+        config.cert_resolver = "custom".to_string();
+
+        let remote = FakeRemoteClient::new().on_command(
+            "acme.json",
+            "cert\tcustom\tapp.example.com\nexpires\t1800000000\t2027-01-15\nsan\tapp.example.com\n",
+            0,
+        );
+        let error = command.deploy_app(&remote, &config).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("No certificate covers www.example.com."));
+
+        let remote = FakeRemoteClient::new().on_command(
+            "acme.json",
+            "cert\tletsencrypt\t\nsan\twww.example.com\n\
+cert\tcustom\tapp.example.com\nsan\tapp.example.com\nsan\t*.example.com\n",
+            0,
+        );
+        command.deploy_app(&remote, &config).unwrap();
+        let remote_commands = remote.commands();
+        let compose = compose_write_command(&remote_commands);
+        assert!(compose.contains("\"traefik.http.routers.my-app.tls=true\""));
+        assert!(!compose.contains("certresolver"));
+    }
+
+    #[test]
+    fn middlewares_are_labelled_after_checking_other_apps() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let mut config = app_config();
+        config.middlewares.basic_auth = "admin:$2y$05$abc".to_string();
+
+        let remote = FakeRemoteClient::new().on_command(
+            "traefik[.]http[.]middlewares",
+            "/opt/minion/other/docker-compose.yml:traefik.http.middlewares.my-app-auth\n",
+            0,
+        );
+        let error = command.deploy_app(&remote, &config).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Middleware my-app-auth is already defined by other on the VPS."));
+
+        let remote = FakeRemoteClient::new();
+        command.deploy_app(&remote, &config).unwrap();
+        let remote_commands = remote.commands();
+        let compose = compose_write_command(&remote_commands);
+        assert!(compose.contains(
+            "\"traefik.http.middlewares.my-app-auth.basicauth.users=admin:$$2y$$05$$abc\""
+        ));
+        assert!(compose.contains("\"traefik.http.routers.my-app.middlewares=my-app-auth\""));
+    }
+
+    #[test]
+    fn deploy_stops_when_another_app_defines_a_route_router() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let mut config = app_config();
+        config.routes = vec![crate::utils::RouteConfig {
+            name: "api".to_string(),
+            path: "/api".to_string(),
+            ..Default::default()
+        }];
+
+        let remote = FakeRemoteClient::new().on_command(
+            "traefik[.]http[.](routers|services)",
+            "/opt/minion/my-app-api/docker-compose.yml:traefik.http.routers.my-app-api\n",
+            0,
+        );
+        let error = command.deploy_app(&remote, &config).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("Router my-app-api is already defined by my-app-api on the VPS."));
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.starts_with("cat > ")));
+    }
+
+    #[test]
+    fn routes_render_extra_routers_on_the_web_service() {
+        let mut config = app_config();
+        config.routes = vec![crate::utils::RouteConfig {
+            name: "ws".to_string(),
+            path: "/cable".to_string(),
+            port: "3001".to_string(),
+            ..Default::default()
+        }];
+
+        let compose = DeployCommand::render_compose(&config, "r1", &[]).unwrap();
+
+        assert!(compose.contains("\"traefik.http.routers.my-app.service=my-app\"\n"));
+        assert!(compose.contains(
+            "\"traefik.http.routers.my-app-ws.rule=Host(`app.example.com`) && PathPrefix(`/cable`)\"\n"
+        ));
+        assert!(
+            compose.contains("\"traefik.http.services.my-app-ws.loadbalancer.server.port=3001\"\n")
+        );
+    }
+
+    #[test]
+    fn missing_dns_resolver_stops_the_deploy() {
+        let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
+        let command = command_with_runner(runner);
+        let remote = FakeRemoteClient::new().on_command("grep -q", "", 1);
+        let mut config = app_config();
+        config.cert_resolver = "route53".to_string();
+
+        let error = command.deploy_app(&remote, &config).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("Traefik on example.com has no route53 certificate resolver."));
+        assert_eq!(remote.commands().len(), 1);
+    }
+
     #[test]
     fn empty_volumes_omit_compose_volumes_section() {
         let runner = std::rc::Rc::new(FakeLocalCommandRunner::new());
@@ -495,16 +1061,9 @@ mod tests {
 
         command.deploy_app(&remote, &config).unwrap();
 
-        assert_eq!(
-            runner.commands()[0].args,
-            vec![
-                "build",
-                "-t",
-                "minion_my-app",
-                ".",
-                "--platform=linux/arm64"
-            ]
-        );
+        let build = &runner.commands()[1];
+        assert_eq!(build.args[0], "build");
+        assert_eq!(build.args[4], "--platform=linux/arm64");
     }
 
     #[test]
@@ -534,15 +1093,8 @@ mod tests {
             )
             .unwrap();
 
-        assert_eq!(
-            runner.commands()[0].args,
-            vec![
-                "build",
-                "-t",
-                "minion_my-app",
-                ".",
-                "--platform=linux/arm64"
-            ]
-        );
+        let build = &runner.commands()[1];
+        assert_eq!(build.args[0], "build");
+        assert_eq!(build.args[4], "--platform=linux/arm64");
     }
 }
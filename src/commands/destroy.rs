@@ -0,0 +1,309 @@
+//! Removes an app from the VPS: the inverse of `minion deploy`.
+//!
+//! The app's containers and add-ons, its `minion_<app>` images, its crontab
+//! lines and everything under `/opt/minion/<app>` go away, except the volumes
+//! and the backups. Volumes are either kept in place, so a later deploy picks
+//! them up again, or archived into `backups/` where `minion restore` finds
+//! them.
+
+use anyhow::{anyhow, Result};
+use std::io::{self, BufRead, Write};
+use std::time::SystemTime;
+
+use super::{offsite, processes};
+use crate::utils::{
+    shell_quote, utc_timestamp, AppConfig, AppConfigOverrides, RemoteClient, SshClient,
+};
+
+pub struct DestroyCommand;
+
+#[derive(Debug, Clone)]
+pub struct DestroyOptions {
+    pub archive: bool,
+    pub yes: bool,
+    pub overrides: AppConfigOverrides,
+}
+
+impl DestroyCommand {
+    pub fn new() -> Self {
+        DestroyCommand
+    }
+
+    pub fn execute(&self, options: DestroyOptions) -> Result<()> {
+        let config = AppConfig::load(options.overrides, false, false)?;
+        config.require_app_control()?;
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        let app_dir = app_dir(&config);
+        ensure_exists(&client, &app_dir)?;
+        if !options.yes {
+            println!(
+                "This removes {} from {}: its containers, add-ons, images, releases and scheduled jobs.",
+                config.app_name, config.host
+            );
+            if options.archive {
+                println!(
+                    "Its volumes are archived to {}/backups and then deleted.",
+                    app_dir
+                );
+            } else {
+                println!("Its volumes are kept in {}/volumes.", app_dir);
+            }
+            confirm(&config.app_name, &mut io::stdin().lock())?;
+        }
+
+        let archive = format!(
+            "{}-{}.tar.gz",
+            config.app_name,
+            utc_timestamp(SystemTime::now())
+        );
+        self.destroy(
+            &client,
+            &config,
+            options.archive.then_some(archive.as_str()),
+        )
+    }
+
+    /// Removes the app, first archiving its volumes as `archive` when given.
+    fn destroy(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        archive: Option<&str>,
+    ) -> Result<()> {
+        let app_dir = app_dir(config);
+
+        println!("Stopping and removing {}...", config.app_name);
+        // `.env` lists the add-on compose files, so they come down as well.
+        let status = client.execute_command_stream(&format!(
+            "cd {dir} && if [ -f docker-compose.yml ]; then docker compose down --remove-orphans 2>&1; fi",
+            dir = app_dir
+        ))?;
+        if status != 0 {
+            return Err(anyhow!(
+                "docker compose down failed with exit status {}",
+                status
+            ));
+        }
+
+        let tags = [processes::schedule_tag(config), offsite::cron_tag(config)]
+            .iter()
+            .map(|tag| format!("-e {}", shell_quote(tag)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        run(
+            client,
+            &format!(
+                "if crontab -l 2>/dev/null | grep -q -F {tags}; then \
+(crontab -l 2>/dev/null | grep -v -F {tags}) | crontab -; fi",
+                tags = tags
+            ),
+        )?;
+
+        if let Some(archive) = archive {
+            self.archive_volumes(client, &app_dir, archive)?;
+        }
+
+        println!("Removing images...");
+        run(
+            client,
+            &format!(
+                "docker images --format '{{{{.Repository}}}}:{{{{.Tag}}}}' {} | xargs -r docker rmi",
+                shell_quote(&format!("minion_{}", config.app_name))
+            ),
+        )?;
+
+        println!("Removing {}...", app_dir);
+        // Containers write as root, so delete with sudo. The directory itself
+        // stays only when volumes or backups are left in it.
+        run(
+            client,
+            &format!(
+                "sudo find {dir} -mindepth 1 -maxdepth 1 ! -name volumes ! -name backups -exec rm -rf {{}} + && \
+(rmdir {dir}/volumes {dir}/backups {dir} 2>/dev/null || true)",
+                dir = app_dir
+            ),
+        )?;
+
+        println!("✓ Destroyed {}", config.app_name);
+        if archive.is_none() {
+            let (output, _) =
+                client.execute_command(&format!("test -d {}/volumes && echo kept", app_dir))?;
+            if output.trim() == "kept" {
+                println!(
+                    "Volumes kept in {}/volumes. Deploy the app again to use them, or delete them with `sudo rm -rf {}`.",
+                    app_dir, app_dir
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn archive_volumes(&self, client: &dyn RemoteClient, app_dir: &str, name: &str) -> Result<()> {
+        let (output, status) =
+            client.execute_command(&format!("ls -1 {}/volumes 2>/dev/null || true", app_dir))?;
+        if status != 0 {
+            return Err(anyhow!("Failed to list volumes: {}", output.trim()));
+        }
+        let volumes = output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(shell_quote)
+            .collect::<Vec<_>>();
+        if volumes.is_empty() {
+            println!("No volumes to archive.");
+            return Ok(());
+        }
+
+        let archive = format!("{}/backups/{}", app_dir, name);
+        println!("Archiving volumes to {}...", archive);
+        run(
+            client,
+            &format!(
+                "mkdir -p {dir}/backups && sudo tar -C {dir}/volumes -czf {archive} {volumes} && \
+sudo chown minion:minion {archive} && sudo rm -rf {dir}/volumes",
+                dir = app_dir,
+                archive = archive,
+                volumes = volumes.join(" ")
+            ),
+        )
+    }
+}
+
+/// Asks for the app name to be typed back before anything is removed.
+fn confirm(app_name: &str, input: &mut dyn BufRead) -> Result<()> {
+    print!("Type {} to confirm: ", app_name);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    if answer.trim() != app_name {
+        return Err(anyhow!(
+            "Aborted: the confirmation did not match {}",
+            app_name
+        ));
+    }
+
+    Ok(())
+}
+
+fn ensure_exists(client: &dyn RemoteClient, app_dir: &str) -> Result<()> {
+    let (_, status) = client.execute_command(&format!("test -d {}", app_dir))?;
+    if status != 0 {
+        return Err(anyhow!("No Minion app found at {}", app_dir));
+    }
+
+    Ok(())
+}
+
+fn run(client: &dyn RemoteClient, command: &str) -> Result<()> {
+    let (output, status) = client.execute_command(command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to execute command {}: {}", command, output));
+    }
+
+    Ok(())
+}
+
+fn app_dir(config: &AppConfig) -> String {
+    format!("/opt/minion/{}", config.app_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    const ARCHIVE: &str = "my-app-20261017153000.tar.gz";
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            host: "example.com".to_string(),
+            app_name: "my-app".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn destroy_removes_containers_jobs_images_and_files_but_keeps_volumes() {
+        let remote = FakeRemoteClient::new();
+
+        DestroyCommand::new()
+            .destroy(&remote, &app_config(), None)
+            .unwrap();
+
+        assert_eq!(
+            remote.streamed_commands(),
+            vec!["cd /opt/minion/my-app && if [ -f docker-compose.yml ]; then docker compose down --remove-orphans 2>&1; fi"]
+        );
+        let commands = remote.commands();
+        assert_eq!(
+            commands[0],
+            "if crontab -l 2>/dev/null | grep -q -F -e '# minion:my-app:' -e '# minion-backup:my-app:'; then \
+(crontab -l 2>/dev/null | grep -v -F -e '# minion:my-app:' -e '# minion-backup:my-app:') | crontab -; fi"
+        );
+        assert_eq!(
+            commands[1],
+            "docker images --format '{{.Repository}}:{{.Tag}}' 'minion_my-app' | xargs -r docker rmi"
+        );
+        assert_eq!(
+            commands[2],
+            "sudo find /opt/minion/my-app -mindepth 1 -maxdepth 1 ! -name volumes ! -name backups -exec rm -rf {} + && \
+(rmdir /opt/minion/my-app/volumes /opt/minion/my-app/backups /opt/minion/my-app 2>/dev/null || true)"
+        );
+        assert!(!commands.iter().any(|command| command.contains("tar ")));
+    }
+
+    #[test]
+    fn destroy_can_archive_volumes_into_backups() {
+        let remote = FakeRemoteClient::new().on_command("ls -1", "data\npostgres\n", 0);
+
+        DestroyCommand::new()
+            .destroy(&remote, &app_config(), Some(ARCHIVE))
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands[1],
+            "ls -1 /opt/minion/my-app/volumes 2>/dev/null || true"
+        );
+        assert_eq!(
+            commands[2],
+            "mkdir -p /opt/minion/my-app/backups && sudo tar -C /opt/minion/my-app/volumes -czf /opt/minion/my-app/backups/my-app-20261017153000.tar.gz 'data' 'postgres' && \
+sudo chown minion:minion /opt/minion/my-app/backups/my-app-20261017153000.tar.gz && sudo rm -rf /opt/minion/my-app/volumes"
+        );
+        assert!(commands[3].starts_with("docker images"));
+    }
+
+    #[test]
+    fn failed_archive_leaves_the_app_files_in_place() {
+        let remote = FakeRemoteClient::new()
+            .on_command("ls -1", "data\n", 0)
+            .on_command("sudo tar", "tar: write error", 2);
+
+        let error = DestroyCommand::new()
+            .destroy(&remote, &app_config(), Some(ARCHIVE))
+            .unwrap_err();
+
+        assert!(error.to_string().contains("tar: write error"));
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.starts_with("sudo find")));
+    }
+
+    #[test]
+    fn confirmation_requires_the_app_name() {
+        assert!(confirm("my-app", &mut "my-app\n".as_bytes()).is_ok());
+        assert_eq!(
+            confirm("my-app", &mut "y\n".as_bytes())
+                .unwrap_err()
+                .to_string(),
+            "Aborted: the confirmation did not match my-app"
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+
+use super::rollout;
+use crate::utils::{
+    parse_assignment, shell_quote, validate_env_key, AppConfig, AppConfigOverrides, EnvFile,
+    RemoteClient, SshClient,
+};
+
+const HIDDEN_VALUE: &str = "********";
+
+pub struct EnvCommand;
+
+#[derive(Debug, Clone)]
+pub enum EnvAction {
+    List { reveal: bool },
+    Set { assignments: Vec<String> },
+    Unset { keys: Vec<String> },
+    Import { path: String },
+}
+
+impl EnvCommand {
+    pub fn new() -> Self {
+        EnvCommand
+    }
+
+    pub fn execute(
+        &self,
+        action: EnvAction,
+        restart: bool,
+        overrides: AppConfigOverrides,
+    ) -> Result<()> {
+        let config = AppConfig::load(overrides, false, false)?;
+        config.require_app_control()?;
+        if restart {
+            config.require_health_check()?;
+        }
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        self.execute_action(action, restart, &config, &client)
+    }
+
+    fn execute_action(
+        &self,
+        action: EnvAction,
+        restart: bool,
+        config: &AppConfig,
+        client: &dyn RemoteClient,
+    ) -> Result<()> {
+        match action {
+            EnvAction::List { reveal } => return self.list(client, config, reveal),
+            EnvAction::Set { assignments } => self.set(client, config, &assignments)?,
+            EnvAction::Unset { keys } => self.unset(client, config, &keys)?,
+            EnvAction::Import { path } => self.import(client, config, &path)?,
+        }
+
+        if restart {
+            self.restart(client, config)?;
+        } else {
+            println!("Run `minion restart` or deploy again to apply the changes.");
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, client: &dyn RemoteClient, config: &AppConfig, reveal: bool) -> Result<()> {
+        let env_file = read_env_file(client, config)?;
+        if env_file.is_empty() {
+            println!("No environment variables set for {}", config.app_name);
+            return Ok(());
+        }
+
+        for line in format_entries(&env_file, reveal) {
+            println!("{}", line);
+        }
+
+        Ok(())
+    }
+
+    fn set(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        assignments: &[String],
+    ) -> Result<()> {
+        let updates = assignments
+            .iter()
+            .map(|assignment| parse_assignment(assignment))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut env_file = read_env_file(client, config)?;
+        for (key, value) in updates {
+            println!("Setting {}", key);
+            env_file.set(key, value);
+        }
+
+        write_env_file(client, config, &env_file)?;
+        println!("✓ Environment updated for {}", config.app_name);
+        Ok(())
+    }
+
+    fn unset(&self, client: &dyn RemoteClient, config: &AppConfig, keys: &[String]) -> Result<()> {
+        for key in keys {
+            validate_env_key(key)?;
+        }
+
+        let mut env_file = read_env_file(client, config)?;
+        for key in keys {
+            if env_file.unset(key) {
+                println!("Removing {}", key);
+            } else {
+                println!("{} is not set", key);
+            }
+        }
+
+        write_env_file(client, config, &env_file)?;
+        println!("✓ Environment updated for {}", config.app_name);
+        Ok(())
+    }
+
+    fn import(&self, client: &dyn RemoteClient, config: &AppConfig, path: &str) -> Result<()> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| anyhow!("Failed to read {}: {}", path, error))?;
+        let imported = EnvFile::parse(&content).map_err(|error| anyhow!("{}: {}", path, error))?;
+        let count = imported.iter().count();
+
+        let mut env_file = read_env_file(client, config)?;
+        env_file.merge(imported);
+
+        write_env_file(client, config, &env_file)?;
+        println!(
+            "✓ Imported {} variables from {} into {}",
+            count, path, config.app_name
+        );
+        Ok(())
+    }
+
+    /// Replaces the app's containers with ones reading the new variables,
+    /// using the same health-gated cutover as a deploy.
+    fn restart(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+        let app_dir = app_dir(config);
+        let (output, status) = client.execute_command(&format!(
+            "test -f {}/docker-compose.yml",
+            shell_quote(&app_dir)
+        ))?;
+        if status != 0 {
+            println!(
+                "{} has not been deployed yet; the variables apply on the first deploy. {}",
+                config.app_name,
+                output.trim()
+            );
+            return Ok(());
+        }
+
+        println!("Recreating {} with the new environment...", config.app_name);
+        rollout::rolling_update(client, config, &app_dir)?;
+
+        println!("✓ {} restarted", config.app_name);
+        Ok(())
+    }
+}
+
+fn format_entries(env_file: &EnvFile, reveal: bool) -> Vec<String> {
+    env_file
+        .iter()
+        .map(|(key, value)| {
+            let value = if reveal { value.as_str() } else { HIDDEN_VALUE };
+            format!("{}={}", key, value)
+        })
+        .collect()
+}
+
+/// Path of the per-app variables file referenced by the compose `env_file`.
+pub(super) fn env_file_path(config: &AppConfig) -> String {
+    format!("{}/app.env", app_dir(config))
+}
+
+pub(super) fn read_env_file(client: &dyn RemoteClient, config: &AppConfig) -> Result<EnvFile> {
+    let command = format!("cat {} 2>/dev/null", shell_quote(&env_file_path(config)));
+    let (output, _) = client.execute_command(&command)?;
+    EnvFile::parse(&output)
+        .map_err(|error| anyhow!("Failed to parse {}: {}", env_file_path(config), error))
+}
+
+pub(super) fn write_env_file(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    env_file: &EnvFile,
+) -> Result<()> {
+    write_private_env(client, config, &env_file_path(config), env_file)
+}
+
+/// Writes `env_file` to `path` inside the app directory, readable only by the
+/// minion user.
+pub(super) fn write_private_env(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    path: &str,
+    env_file: &EnvFile,
+) -> Result<()> {
+    let app_dir = shell_quote(&app_dir(config));
+    let quoted_path = shell_quote(path);
+    let command = format!(
+        "sudo mkdir -p {} && sudo chown minion:minion {} && umask 077 && cat > {} << 'MINION_ENV' && chmod 600 {}\n{}MINION_ENV",
+        app_dir,
+        app_dir,
+        quoted_path,
+        quoted_path,
+        env_file.render()
+    );
+
+    let (output, status) = client.execute_command(&command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to write {}: {}", path, output));
+    }
+
+    Ok(())
+}
+
+fn app_dir(config: &AppConfig) -> String {
+    format!("/opt/minion/{}", config.app_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            host: "example.com".to_string(),
+            app_name: "my-app".to_string(),
+            app_port: "3000".to_string(),
+            ssh_user: "minion".to_string(),
+            healthcheck_type: "tcp".to_string(),
+            healthcheck_wait: "60".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn written_env(commands: &[String]) -> &str {
+        commands
+            .iter()
+            .find(|command| command.contains("<< 'MINION_ENV'"))
+            .expect("env write command not found")
+    }
+
+    #[test]
+    fn set_merges_with_existing_file_and_writes_private_file() {
+        let remote =
+            FakeRemoteClient::new().on_command("cat ", "EXISTING=1\nDATABASE_URL=old\n", 0);
+
+        EnvCommand::new()
+            .execute_action(
+                EnvAction::Set {
+                    assignments: vec!["DATABASE_URL=postgres://db/app".to_string()],
+                },
+                false,
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(commands[0], "cat '/opt/minion/my-app/app.env' 2>/dev/null");
+        let write = written_env(&commands);
+        assert!(write.contains("umask 077 && cat > '/opt/minion/my-app/app.env'"));
+        assert!(write.contains("chmod 600 '/opt/minion/my-app/app.env'"));
+        assert!(write.ends_with("\nDATABASE_URL='postgres://db/app'\nEXISTING='1'\nMINION_ENV"));
+        assert_eq!(commands.len(), 2);
+    }
+
+    #[test]
+    fn unset_removes_keys() {
+        let remote = FakeRemoteClient::new().on_command("cat ", "A=1\nB=2\n", 0);
+
+        EnvCommand::new()
+            .execute_action(
+                EnvAction::Unset {
+                    keys: vec!["A".to_string()],
+                },
+                false,
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        assert!(written_env(&remote.commands()).ends_with("\nB='2'\nMINION_ENV"));
+    }
+
+    #[test]
+    fn invalid_assignment_fails_before_remote_commands() {
+        let remote = FakeRemoteClient::new();
+
+        let error = EnvCommand::new()
+            .execute_action(
+                EnvAction::Set {
+                    assignments: vec!["NOT VALID".to_string()],
+                },
+                false,
+                &app_config(),
+                &remote,
+            )
+            .unwrap_err();
+
+        assert!(error.to_string().contains("KEY=VALUE"));
+        assert!(remote.commands().is_empty());
+    }
+
+    #[test]
+    fn import_merges_local_file() {
+        let remote = FakeRemoteClient::new().on_command("cat ", "A=1\n", 0);
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "# imported\nB=\"two\"\n").unwrap();
+
+        EnvCommand::new()
+            .execute_action(
+                EnvAction::Import {
+                    path: file.path().to_str().unwrap().to_string(),
+                },
+                false,
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        assert!(written_env(&remote.commands()).ends_with("\nA='1'\nB='two'\nMINION_ENV"));
+    }
+
+    #[test]
+    fn restart_rolls_the_deployed_app_over_to_new_containers() {
+        let remote = FakeRemoteClient::new()
+            .on_command("docker compose ps -q", "old1\n", 0)
+            .on_command("docker compose ps -q", "old1\nnew1\n", 0);
+
+        EnvCommand::new()
+            .execute_action(
+                EnvAction::Set {
+                    assignments: vec!["A=1".to_string()],
+                },
+                true,
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands[2],
+            "test -f '/opt/minion/my-app'/docker-compose.yml"
+        );
+        assert_eq!(
+            commands[4],
+            "cd /opt/minion/my-app && docker compose up -d --no-deps --no-recreate --scale my-app=2 my-app"
+        );
+        assert!(commands[6].starts_with("id=new1;"));
+        assert_eq!(commands[7], "docker stop old1 && docker rm old1");
+    }
+
+    #[test]
+    fn list_hides_values_unless_revealed() {
+        let env_file = EnvFile::parse("SECRET=hunter2\nA=1\n").unwrap();
+
+        assert_eq!(
+            format_entries(&env_file, false),
+            vec!["A=********", "SECRET=********"]
+        );
+        assert_eq!(
+            format_entries(&env_file, true),
+            vec!["A=1", "SECRET=hunter2"]
+        );
+    }
+
+    #[test]
+    fn list_only_reads_the_file() {
+        let remote = FakeRemoteClient::new().on_command("cat ", "SECRET=hunter2\n", 0);
+
+        EnvCommand::new()
+            .execute_action(
+                EnvAction::List { reveal: false },
+                true,
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        assert_eq!(remote.commands().len(), 1);
+    }
+}
@@ -0,0 +1,146 @@
+//! `PRE_DEPLOY` and `POST_DEPLOY` hooks for `minion deploy`.
+//!
+//! A hook runs in a one-off container of the web service, so it uses the
+//! release's image, volumes, `app.env` and `secrets.env`, and joins
+//! `traefik_network`. Traefik is told to ignore the container, so no traffic
+//! is routed to it while the hook runs.
+
+use anyhow::{anyhow, Result};
+
+use crate::utils::{shell_quote, AppConfig, RemoteClient};
+
+/// Runs `PRE_DEPLOY` with the compose file of the release about to go live.
+/// The running version is untouched, so a failure leaves it serving.
+pub(super) fn run_pre_deploy(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    release_dir: &str,
+) -> Result<()> {
+    run_hook(
+        client,
+        config,
+        app_dir,
+        &format!("{}/docker-compose.yml", release_dir),
+        "PRE_DEPLOY",
+        &config.pre_deploy,
+    )
+    .map_err(|error| anyhow!("{}. The previous version is still serving traffic", error))
+}
+
+/// Runs `POST_DEPLOY` once the new release is live.
+pub(super) fn run_post_deploy(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+) -> Result<()> {
+    run_hook(
+        client,
+        config,
+        app_dir,
+        &format!("{}/docker-compose.yml", app_dir),
+        "POST_DEPLOY",
+        &config.post_deploy,
+    )
+    .map_err(|error| anyhow!("{}. The new release is already live", error))
+}
+
+fn run_hook(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    compose_file: &str,
+    name: &str,
+    command: &str,
+) -> Result<()> {
+    if command.is_empty() {
+        return Ok(());
+    }
+
+    println!("Running {}: {}", name, command);
+    let status = client.execute_command_stream(&format!(
+        "docker compose --project-directory {} -f {} run --rm --no-deps -T --label traefik.enable=false {} sh -c {} 2>&1",
+        app_dir,
+        compose_file,
+        config.app_name,
+        shell_quote(command)
+    ))?;
+    if status != 0 {
+        return Err(anyhow!("{} failed with exit status {}", name, status));
+    }
+
+    println!("✓ {} finished", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            app_name: "my-app".to_string(),
+            pre_deploy: "bin/rails db:migrate".to_string(),
+            post_deploy: "bin/notify 'deployed'".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pre_deploy_runs_in_a_one_off_container_of_the_new_release() {
+        let remote = FakeRemoteClient::new();
+
+        run_pre_deploy(
+            &remote,
+            &app_config(),
+            "/opt/minion/my-app",
+            "/opt/minion/my-app/releases/r2",
+        )
+        .unwrap();
+
+        assert_eq!(
+            remote.streamed_commands(),
+            vec![
+                "docker compose --project-directory /opt/minion/my-app -f /opt/minion/my-app/releases/r2/docker-compose.yml run --rm --no-deps -T --label traefik.enable=false my-app sh -c 'bin/rails db:migrate' 2>&1"
+            ]
+        );
+    }
+
+    #[test]
+    fn post_deploy_uses_the_live_compose_file() {
+        let remote = FakeRemoteClient::new();
+
+        run_post_deploy(&remote, &app_config(), "/opt/minion/my-app").unwrap();
+
+        assert!(remote.streamed_commands()[0].starts_with(
+            "docker compose --project-directory /opt/minion/my-app -f /opt/minion/my-app/docker-compose.yml run"
+        ));
+        assert!(
+            remote.streamed_commands()[0].ends_with("sh -c 'bin/notify '\\''deployed'\\''' 2>&1")
+        );
+    }
+
+    #[test]
+    fn failing_pre_deploy_reports_the_exit_status() {
+        let remote = FakeRemoteClient::with_stream_responses(vec![3]);
+
+        let error = run_pre_deploy(&remote, &app_config(), "/opt/minion/my-app", "/r2")
+            .unwrap_err()
+            .to_string();
+
+        assert_eq!(
+            error,
+            "PRE_DEPLOY failed with exit status 3. The previous version is still serving traffic"
+        );
+    }
+
+    #[test]
+    fn empty_hooks_run_nothing() {
+        let remote = FakeRemoteClient::new();
+
+        run_post_deploy(&remote, &AppConfig::default(), "/opt/minion/my-app").unwrap();
+
+        assert!(remote.streamed_commands().is_empty());
+    }
+}
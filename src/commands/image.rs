@@ -0,0 +1,701 @@
+//! Gets the release image onto the VPS.
+//!
+//! With the default `local` image source the image is built here and
+//! uploaded. The VPS is first asked which layers its images are made of.
+//! When the new image starts with layers the VPS already has, usually the
+//! base image and dependencies, those layer files are cut out of the
+//! `docker save` archive: `docker load` only reads a layer file when it does
+//! not have that layer yet. That only works with Docker's classic image
+//! store, so when either side keeps its images in containerd the whole image
+//! is streamed right away. Anything unexpected falls back to streaming the
+//! whole image as well.
+//!
+//! `remote-build` uploads the build context and builds on the VPS instead,
+//! and `registry` pulls an image that was built elsewhere.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read};
+use tempfile::Builder;
+
+use crate::utils::{
+    filter_tar, read_tar_entry, shell_quote, AppConfig, LocalCommandRunner, ProgressReader,
+    RemoteClient,
+};
+
+const LAYERS_FORMAT: &str = "{{range .RootFS.Layers}}{{.}} {{end}}";
+const DRIVER_STATUS_FORMAT: &str = "{{.DriverStatus}}";
+/// Driver type `docker info` reports when images live in containerd.
+const CONTAINERD_SNAPSHOTTER: &str = "io.containerd.snapshotter";
+const DOCKERIGNORE_FILE: &str = ".dockerignore";
+
+/// Makes `image_name` available on the VPS using the configured image
+/// source. For the `local` source the image must already be built.
+pub(super) fn provide_image(
+    runner: &dyn LocalCommandRunner,
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    image_name: &str,
+) -> Result<()> {
+    match config.image_source.as_str() {
+        "remote-build" => build_on_vps(runner, client, app_dir, image_name),
+        "registry" => pull_from_registry(client, config, image_name),
+        _ => upload_image(runner, client, config, image_name),
+    }
+}
+
+/// Sends `image_name` to the VPS and loads it there.
+fn upload_image(
+    runner: &dyn LocalCommandRunner,
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    image_name: &str,
+) -> Result<()> {
+    let layers = local_layers(runner, image_name)?;
+    let shared = shared_layer_count(&layers, &remote_layers(client)?);
+    if shared == 0 {
+        return stream_image(runner, client, config, &docker_save(image_name));
+    }
+    if uses_containerd_store(runner, client)? {
+        println!("! The containerd image store cannot load partial images; sending the full image");
+        return stream_image(runner, client, config, &docker_save(image_name));
+    }
+
+    println!(
+        "✓ VPS already has {} of {} layers; sending the rest",
+        shared,
+        layers.len()
+    );
+    if let Err(error) = upload_missing_layers(runner, client, config, image_name, &layers, shared) {
+        println!(
+            "! Incremental upload failed, sending the full image instead: {}",
+            error
+        );
+        return stream_image(runner, client, config, &docker_save(image_name));
+    }
+
+    Ok(())
+}
+
+fn upload_missing_layers(
+    runner: &dyn LocalCommandRunner,
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    image_name: &str,
+    layers: &[String],
+    shared: usize,
+) -> Result<()> {
+    let saved = Builder::new().prefix("minion_").suffix(".tar").tempfile()?;
+    let saved_path = saved.path().to_string_lossy().to_string();
+    println!("Saving image to temporary file...");
+    let (output, status) = runner.execute("docker", &["save", "-o", &saved_path, image_name])?;
+    if status != 0 {
+        return Err(anyhow!("Failed to save Docker image: {}", output));
+    }
+
+    let manifest = read_tar_entry(BufReader::new(File::open(saved.path())?), "manifest.json")?
+        .ok_or_else(|| anyhow!("manifest.json not found in docker save output"))?;
+    let layer_files = manifest_layers(&String::from_utf8_lossy(&manifest))?;
+    if layer_files.len() != layers.len() {
+        return Err(anyhow!(
+            "docker save listed {} layers, expected {}",
+            layer_files.len(),
+            layers.len()
+        ));
+    }
+
+    let present = layer_files[..shared]
+        .iter()
+        .map(String::as_str)
+        .collect::<HashSet<_>>();
+    let filtered = Builder::new().prefix("minion_").suffix(".tar").tempfile()?;
+    filter_tar(
+        BufReader::new(File::open(saved.path())?),
+        BufWriter::new(File::create(filtered.path())?),
+        |name| !present.contains(name),
+    )?;
+    drop(saved);
+
+    let filtered_path = filtered.path().to_string_lossy().to_string();
+    stream_image(runner, client, config, &["cat".to_string(), filtered_path])
+}
+
+/// Pipes the archive written by `source` into `docker load` on the VPS,
+/// compressing it on the way, without holding it in memory.
+fn stream_image(
+    runner: &dyn LocalCommandRunner,
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    source: &[String],
+) -> Result<()> {
+    println!(
+        "Streaming Docker image to VPS ({} compression)...",
+        config.image_compression
+    );
+    let (command, args) = stream_command(source, &config.image_compression);
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let load_command = load_command(&config.image_compression);
+
+    let (save_output, save_status, load_output, load_status) =
+        pipe_to_vps(runner, client, &command, &args, &load_command)?;
+
+    if save_status != 0 {
+        return Err(anyhow!("Failed to save Docker image: {}", save_output));
+    }
+    if load_status != 0 {
+        return Err(anyhow!(
+            "Failed to load Docker image on VPS: {}",
+            load_output
+        ));
+    }
+
+    println!("✓ Docker image loaded on VPS");
+    Ok(())
+}
+
+/// Uploads the build context next to the app and runs `docker build` there.
+/// The image is built for the VPS's own platform.
+fn build_on_vps(
+    runner: &dyn LocalCommandRunner,
+    client: &dyn RemoteClient,
+    app_dir: &str,
+    image_name: &str,
+) -> Result<()> {
+    let build_dir = format!("{}/build", app_dir);
+    let excludes = fs::read_to_string(DOCKERIGNORE_FILE)
+        .map(|content| context_excludes(&content))
+        .unwrap_or_else(|_| context_excludes(""));
+    let archive = format!(
+        "COPYFILE_DISABLE=1 tar -czf - {} .",
+        excludes
+            .iter()
+            .map(|exclude| shell_quote(&format!("--exclude=./{}", exclude)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    let extract = format!(
+        "rm -rf {} && mkdir -p {} && tar -xzf - -C {} 2>&1",
+        build_dir, build_dir, build_dir
+    );
+
+    println!("Uploading build context to VPS...");
+    let (archive_output, archive_status, extract_output, extract_status) =
+        pipe_to_vps(runner, client, "bash", &["-c", &archive], &extract)?;
+    if archive_status != 0 {
+        return Err(anyhow!(
+            "Failed to archive build context: {}",
+            archive_output
+        ));
+    }
+    if extract_status != 0 {
+        return Err(anyhow!(
+            "Failed to unpack build context on VPS: {}",
+            extract_output
+        ));
+    }
+
+    println!("Building Docker image on VPS...");
+    let status = client.execute_command_stream(&format!(
+        "cd {} && docker build -t {} . 2>&1",
+        build_dir, image_name
+    ))?;
+    client.execute_command(&format!("rm -rf {}", build_dir))?;
+    if status != 0 {
+        return Err(anyhow!("Failed to build Docker image on VPS"));
+    }
+
+    println!("✓ Docker image built on VPS");
+    Ok(())
+}
+
+/// Pulls the configured registry image on the VPS and tags it as the
+/// release image. Credentials are passed to `docker login` on stdin.
+fn pull_from_registry(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    image_name: &str,
+) -> Result<()> {
+    let registry = registry_host(&config.image);
+    let server = registry.map(shell_quote).unwrap_or_default();
+
+    if let (Some(username), Some(password)) = (&config.registry_username, &config.registry_password)
+    {
+        println!(
+            "Logging in to {} as {}...",
+            registry.unwrap_or("Docker Hub"),
+            username
+        );
+        let (output, status) = client.execute_with_stdin(
+            &format!(
+                "docker login --username {} --password-stdin {} 2>&1",
+                shell_quote(username),
+                server
+            ),
+            &mut password.as_bytes(),
+        )?;
+        if status != 0 {
+            return Err(anyhow!("Failed to log in to the registry: {}", output));
+        }
+    }
+
+    println!("Pulling {} on VPS...", config.image);
+    let status = client
+        .execute_command_stream(&format!("docker pull {} 2>&1", shell_quote(&config.image)))?;
+
+    if config.registry_username.is_some() {
+        client.execute_command(&format!("docker logout {} >/dev/null 2>&1", server))?;
+    }
+    if status != 0 {
+        return Err(anyhow!("Failed to pull {}", config.image));
+    }
+
+    let (output, status) = client.execute_command(&format!(
+        "docker tag {} {}",
+        shell_quote(&config.image),
+        image_name
+    ))?;
+    if status != 0 {
+        return Err(anyhow!("Failed to tag {}: {}", config.image, output));
+    }
+
+    println!("✓ Pulled {}", config.image);
+    Ok(())
+}
+
+/// Runs `command` locally and streams its stdout into `remote_command` on
+/// the VPS. Returns the local stderr and status, then the remote output and
+/// status.
+fn pipe_to_vps(
+    runner: &dyn LocalCommandRunner,
+    client: &dyn RemoteClient,
+    command: &str,
+    args: &[&str],
+    remote_command: &str,
+) -> Result<(String, i32, String, i32)> {
+    let mut remote_result = None;
+    let mut send = |data: &mut dyn Read| -> Result<()> {
+        let mut progress = ProgressReader::new(data);
+        let result = client.execute_with_stdin(remote_command, &mut progress)?;
+        progress.finish();
+        remote_result = Some(result);
+        Ok(())
+    };
+    let (local_output, local_status) = runner.execute_piped(command, args, &mut send)?;
+    let (remote_output, remote_status) = remote_result.unwrap_or_default();
+
+    Ok((local_output, local_status, remote_output, remote_status))
+}
+
+/// Paths left out of the uploaded build context: `.git`, plus top-level
+/// names from `.dockerignore` that Docker would skip anyway. Patterns with
+/// wildcards or nested paths are left for `docker build` to apply, and a
+/// `.dockerignore` with exceptions (`!`) is not used at all.
+fn context_excludes(dockerignore: &str) -> Vec<String> {
+    let mut excludes = vec![".git".to_string()];
+    let patterns = dockerignore
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect::<Vec<_>>();
+    if patterns.iter().any(|pattern| pattern.starts_with('!')) {
+        return excludes;
+    }
+
+    for pattern in patterns {
+        let name = pattern
+            .trim_start_matches("./")
+            .trim_start_matches('/')
+            .trim_end_matches('/');
+        let simple = !name.is_empty()
+            && name != "."
+            && name != ".."
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-');
+        if simple && !excludes.iter().any(|exclude| exclude == name) {
+            excludes.push(name.to_string());
+        }
+    }
+
+    excludes
+}
+
+/// Registry server of an image reference, or `None` for Docker Hub.
+fn registry_host(image: &str) -> Option<&str> {
+    let (first, _) = image.split_once('/')?;
+    (first.contains('.') || first.contains(':') || first == "localhost").then_some(first)
+}
+
+fn docker_save(image_name: &str) -> Vec<String> {
+    vec![
+        "docker".to_string(),
+        "save".to_string(),
+        image_name.to_string(),
+    ]
+}
+
+/// Local command that writes the output of `source`, compressed as
+/// configured, to stdout.
+fn stream_command(source: &[String], compression: &str) -> (String, Vec<String>) {
+    let compressor = match compression {
+        "gzip" => "gzip -1 -c",
+        "zstd" => "zstd -T0 -3 -c -q",
+        _ => return (source[0].clone(), source[1..].to_vec()),
+    };
+
+    let source = source
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    (
+        "bash".to_string(),
+        vec![
+            "-o".to_string(),
+            "pipefail".to_string(),
+            "-c".to_string(),
+            format!("{} | {}", source, compressor),
+        ],
+    )
+}
+
+fn load_command(compression: &str) -> String {
+    match compression {
+        "gzip" => "set -o pipefail; (gzip -dc | docker load) 2>&1".to_string(),
+        "zstd" => "set -o pipefail; (zstd -dc -q | docker load) 2>&1".to_string(),
+        _ => "docker load 2>&1".to_string(),
+    }
+}
+
+fn local_layers(runner: &dyn LocalCommandRunner, image_name: &str) -> Result<Vec<String>> {
+    let (output, status) = runner.execute(
+        "docker",
+        &["image", "inspect", "--format", LAYERS_FORMAT, image_name],
+    )?;
+    if status != 0 {
+        return Err(anyhow!("Failed to inspect Docker image: {}", output));
+    }
+
+    Ok(output.split_whitespace().map(ToOwned::to_owned).collect())
+}
+
+/// Whether the local or the VPS Docker keeps images in containerd, whose
+/// `docker save` and `docker load` do not use per-layer tar files. A Docker
+/// that cannot be asked is assumed to use the classic store.
+fn uses_containerd_store(
+    runner: &dyn LocalCommandRunner,
+    client: &dyn RemoteClient,
+) -> Result<bool> {
+    let (local, _) = runner.execute("docker", &["info", "--format", DRIVER_STATUS_FORMAT])?;
+    if local.contains(CONTAINERD_SNAPSHOTTER) {
+        return Ok(true);
+    }
+
+    let (remote, _) = client.execute_command(&format!(
+        "docker info --format {} 2>/dev/null",
+        shell_quote(DRIVER_STATUS_FORMAT)
+    ))?;
+    Ok(remote.contains(CONTAINERD_SNAPSHOTTER))
+}
+
+/// Layer lists of every image on the VPS. A VPS that cannot be asked is
+/// treated as having no layers.
+fn remote_layers(client: &dyn RemoteClient) -> Result<Vec<Vec<String>>> {
+    let command = format!(
+        "docker image ls -q --no-trunc | sort -u | xargs -r docker image inspect --format {} 2>/dev/null",
+        shell_quote(LAYERS_FORMAT)
+    );
+    let (output, status) = client.execute_command(&command)?;
+    if status != 0 {
+        return Ok(Vec::new());
+    }
+
+    Ok(output
+        .lines()
+        .map(|line| line.split_whitespace().map(ToOwned::to_owned).collect())
+        .collect())
+}
+
+/// Layers are stacked, so a layer can only be reused when every layer below
+/// it matches too. Returns the longest such prefix shared with any image.
+fn shared_layer_count(layers: &[String], remote_images: &[Vec<String>]) -> usize {
+    remote_images
+        .iter()
+        .map(|remote| {
+            layers
+                .iter()
+                .zip(remote)
+                .take_while(|(local, remote)| local == remote)
+                .count()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Extracts the layer file paths of the first image in a `docker save`
+/// `manifest.json`, in stacking order.
+fn manifest_layers(manifest: &str) -> Result<Vec<String>> {
+    let start = manifest
+        .find("\"Layers\"")
+        .ok_or_else(|| anyhow!("manifest.json has no Layers"))?;
+    let rest = &manifest[start + "\"Layers\"".len()..];
+    let rest = rest
+        .trim_start()
+        .strip_prefix(':')
+        .map(str::trim_start)
+        .and_then(|rest| rest.strip_prefix('['))
+        .ok_or_else(|| anyhow!("manifest.json Layers is not a list"))?;
+
+    let mut layers = Vec::new();
+    let mut chars = rest.chars();
+    loop {
+        match chars.find(|c| !c.is_whitespace() && *c != ',') {
+            Some(']') => return Ok(layers),
+            Some('"') => {
+                let mut layer = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => layer.extend(chars.next()),
+                        Some(c) => layer.push(c),
+                        None => return Err(anyhow!("Unterminated string in manifest.json")),
+                    }
+                }
+                layers.push(layer);
+            }
+            _ => return Err(anyhow!("Invalid Layers list in manifest.json")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::{FakeLocalCommandRunner, FakeRemoteClient};
+
+    fn layers(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            image_compression: "gzip".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shared_layers_must_match_from_the_bottom() {
+        let local = layers(&["a", "b", "c"]);
+
+        assert_eq!(shared_layer_count(&local, &[]), 0);
+        assert_eq!(
+            shared_layer_count(&local, &[layers(&["a", "x", "c"]), layers(&["a", "b"])]),
+            2
+        );
+        assert_eq!(shared_layer_count(&local, &[layers(&["b", "c"])]), 0);
+        assert_eq!(
+            shared_layer_count(&local, &[layers(&["a", "b", "c", "d"])]),
+            3
+        );
+    }
+
+    #[test]
+    fn manifest_layers_reads_legacy_and_oci_layouts() {
+        let legacy = r#"[{"Config":"abc.json","RepoTags":["minion_app:1"],"Layers":["1a/layer.tar","2b/layer.tar"]}]"#;
+        let oci = "[{\"Config\":\"blobs/sha256/c\",\"Layers\": [\n \"blobs/sha256/aa\",\n \"blobs/sha256/bb\"\n]}]";
+
+        assert_eq!(
+            manifest_layers(legacy).unwrap(),
+            layers(&["1a/layer.tar", "2b/layer.tar"])
+        );
+        assert_eq!(
+            manifest_layers(oci).unwrap(),
+            layers(&["blobs/sha256/aa", "blobs/sha256/bb"])
+        );
+        assert!(manifest_layers("[{}]").is_err());
+    }
+
+    #[test]
+    fn stream_command_compresses_through_bash_pipeline() {
+        let (command, args) = stream_command(&docker_save("minion_app:1"), "zstd");
+
+        assert_eq!(command, "bash");
+        assert_eq!(
+            args[3],
+            "'docker' 'save' 'minion_app:1' | zstd -T0 -3 -c -q"
+        );
+        assert_eq!(
+            stream_command(&docker_save("minion_app:1"), "none"),
+            ("docker".to_string(), layers(&["save", "minion_app:1"]))
+        );
+    }
+
+    #[test]
+    fn vps_without_shared_layers_gets_the_full_image() {
+        let runner = FakeLocalCommandRunner::with_responses(vec![("sha256:a sha256:b ", 0)]);
+        let remote = FakeRemoteClient::new().on_command("xargs -r", "sha256:z \n", 0);
+
+        upload_image(&runner, &remote, &app_config(), "minion_app:1").unwrap();
+
+        let commands = runner.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(
+            commands[1].args[3],
+            "'docker' 'save' 'minion_app:1' | gzip -1 -c"
+        );
+        assert_eq!(remote.stdin_inputs().len(), 1);
+    }
+
+    #[test]
+    fn failed_incremental_upload_falls_back_to_the_full_image() {
+        // The fake runner does not write the saved archive, so reading its
+        // manifest fails and the upload starts over with the whole image.
+        let runner = FakeLocalCommandRunner::with_responses(vec![("sha256:a sha256:b ", 0)]);
+        let remote = FakeRemoteClient::new().on_command("xargs -r", "sha256:a \n", 0);
+
+        upload_image(&runner, &remote, &app_config(), "minion_app:1").unwrap();
+
+        let commands = runner.commands();
+        assert_eq!(commands[1].args[..2], ["info", "--format"]);
+        assert_eq!(commands[2].args[..2], ["save", "-o"]);
+        assert_eq!(commands[3].command, "bash");
+        assert_eq!(remote.stdin_inputs().len(), 1);
+    }
+
+    #[test]
+    fn containerd_image_store_skips_the_incremental_upload() {
+        let runner = FakeLocalCommandRunner::with_responses(vec![
+            ("sha256:a sha256:b ", 0),
+            ("[[Backing Filesystem extfs]]", 0),
+        ]);
+        let remote = FakeRemoteClient::new()
+            .on_command("xargs -r", "sha256:a \n", 0)
+            .on_command(
+                "docker info",
+                "[[driver-type io.containerd.snapshotter.v1]]\n",
+                0,
+            );
+
+        upload_image(&runner, &remote, &app_config(), "minion_app:1").unwrap();
+
+        let commands = runner.commands();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(
+            commands[1].args,
+            vec!["info", "--format", "{{.DriverStatus}}"]
+        );
+        assert_eq!(
+            commands[2].args[3],
+            "'docker' 'save' 'minion_app:1' | gzip -1 -c"
+        );
+        assert_eq!(
+            remote.commands()[1],
+            "docker info --format '{{.DriverStatus}}' 2>/dev/null"
+        );
+        assert_eq!(remote.stdin_inputs().len(), 1);
+    }
+
+    #[test]
+    fn context_excludes_skip_git_and_simple_dockerignore_names() {
+        assert_eq!(
+            context_excludes("# deps\nnode_modules/\n./target\n*.log\nsrc/tmp\n\n.git\n"),
+            layers(&[".git", "node_modules", "target"])
+        );
+        assert_eq!(
+            context_excludes("node_modules\n!node_modules/keep\n"),
+            layers(&[".git"])
+        );
+    }
+
+    #[test]
+    fn registry_host_defaults_to_docker_hub() {
+        assert_eq!(registry_host("ghcr.io/acme/app:1"), Some("ghcr.io"));
+        assert_eq!(registry_host("localhost:5000/app"), Some("localhost:5000"));
+        assert_eq!(registry_host("acme/app:1"), None);
+        assert_eq!(registry_host("nginx"), None);
+    }
+
+    #[test]
+    fn remote_build_uploads_context_and_builds_on_vps() {
+        let runner = FakeLocalCommandRunner::new();
+        let remote = FakeRemoteClient::new();
+        let config = AppConfig {
+            image_source: "remote-build".to_string(),
+            ..app_config()
+        };
+
+        provide_image(&runner, &remote, &config, "/opt/minion/app", "minion_app:1").unwrap();
+
+        let commands = runner.commands();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].command, "bash");
+        assert!(commands[0].args[1].starts_with("COPYFILE_DISABLE=1 tar -czf - '--exclude=./.git'"));
+        assert_eq!(
+            remote.stdin_inputs()[0].0,
+            "rm -rf /opt/minion/app/build && mkdir -p /opt/minion/app/build && tar -xzf - -C /opt/minion/app/build 2>&1"
+        );
+        assert_eq!(
+            remote.streamed_commands(),
+            vec!["cd /opt/minion/app/build && docker build -t minion_app:1 . 2>&1"]
+        );
+        assert!(remote
+            .commands()
+            .contains(&"rm -rf /opt/minion/app/build".to_string()));
+    }
+
+    #[test]
+    fn registry_login_sends_password_on_stdin_then_pulls_and_tags() {
+        let runner = FakeLocalCommandRunner::new();
+        let remote = FakeRemoteClient::new();
+        let config = AppConfig {
+            image_source: "registry".to_string(),
+            image: "ghcr.io/acme/app:1.2.3".to_string(),
+            registry_username: Some("deploy".to_string()),
+            registry_password: Some("s3cr3t".to_string()),
+            ..app_config()
+        };
+
+        provide_image(&runner, &remote, &config, "/opt/minion/app", "minion_app:1").unwrap();
+
+        assert!(runner.commands().is_empty());
+        let login = &remote.stdin_inputs()[0];
+        assert_eq!(
+            login.0,
+            "docker login --username 'deploy' --password-stdin 'ghcr.io' 2>&1"
+        );
+        assert_eq!(login.1, b"s3cr3t");
+        assert_eq!(
+            remote.streamed_commands(),
+            vec!["docker pull 'ghcr.io/acme/app:1.2.3' 2>&1"]
+        );
+        let commands = remote.commands();
+        assert!(!commands.iter().any(|command| command.contains("s3cr3t")));
+        assert!(commands.contains(&"docker logout 'ghcr.io' >/dev/null 2>&1".to_string()));
+        assert!(commands.contains(&"docker tag 'ghcr.io/acme/app:1.2.3' minion_app:1".to_string()));
+    }
+
+    #[test]
+    fn failed_registry_pull_is_reported() {
+        let remote = FakeRemoteClient::with_stream_responses(vec![1]);
+        let config = AppConfig {
+            image_source: "registry".to_string(),
+            image: "acme/app:1".to_string(),
+            ..app_config()
+        };
+
+        let error = provide_image(
+            &FakeLocalCommandRunner::new(),
+            &remote,
+            &config,
+            "/opt/minion/app",
+            "minion_app:1",
+        )
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "Failed to pull acme/app:1");
+        assert!(remote.stdin_inputs().is_empty());
+    }
+}
@@ -1,6 +1,5 @@
 use std::io::{self, Write};
-use crate::utils::{Config};
-use anyhow;
+use crate::utils::{select_environment, Manifest, MANIFEST_FILE};
 
 pub struct InitCommand;
 
@@ -28,8 +27,16 @@ impl InitCommand {
         })
     }
 
-    fn load_args() -> anyhow::Result<(String, String, String, String)> {
-        let config = Config::new(".minion")?;
+    /// Prompts for the basic settings. With an environment, the answers go
+    /// into its `[environments.<name>.*]` tables: all of them for a new
+    /// environment, which declares it, and only the changed ones otherwise.
+    fn load_args(environment: Option<&str>) -> anyhow::Result<(String, String, String, String)> {
+        let mut manifest = Manifest::migrate_project()?;
+        let is_new = environment.is_some_and(|environment| !manifest.environments().contains(&environment));
+        let config = match environment {
+            Some(environment) => manifest.defaults_for(environment),
+            None => manifest.values(),
+        };
         let existing_host = config.get("VPS_HOST");
         let existing_name = config.get("APP_NAME");
         let existing_url = config.get("APP_URL");
@@ -55,20 +62,23 @@ impl InitCommand {
             existing_port
         )?;
 
-        // Save both configs
-        let mut config = Config::new(".minion")?;
-        config.set("VPS_HOST".to_string(), host.clone());
-        config.set("APP_NAME".to_string(), name.clone());
-        config.set("APP_URL".to_string(), url.clone());
-        config.set("APP_PORT".to_string(), port.clone());
-        config.save()?;
+        for (key, value) in [("VPS_HOST", &host), ("APP_NAME", &name), ("APP_URL", &url), ("APP_PORT", &port)] {
+            if environment.is_none() || is_new || config.get(key) != Some(value) {
+                manifest.set_for(environment, key, value)?;
+            }
+        }
+        manifest.save()?;
 
         Ok((host, name, url, port))
     }
 
-    pub fn execute(&self) -> anyhow::Result<()> {
-        let (_host, _name, _url, _port) = Self::load_args()?;
-        println!("✓ Configuration saved successfully!");
+    pub fn execute(&self, environment: Option<String>) -> anyhow::Result<()> {
+        let environment = select_environment(environment)?;
+        let (_host, _name, _url, _port) = Self::load_args(environment.as_deref())?;
+        match environment {
+            Some(environment) => println!("✓ Environment {} saved to {}", environment, MANIFEST_FILE),
+            None => println!("✓ Configuration saved to {}", MANIFEST_FILE),
+        }
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,272 @@
+//! Traefik middlewares configured under `[middlewares]`.
+//!
+//! Middlewares are defined by labels on the app's web service and named
+//! `<app>-<kind>`. No kind contains a dash, so two apps can only produce the
+//! same name if they share their name. Deploys still check the compose files
+//! of the other apps on the VPS, which may be older or edited by hand, since
+//! Traefik would silently merge two definitions of one name.
+
+use anyhow::{anyhow, Result};
+
+use crate::utils::{list_items, AppConfig, RemoteClient};
+
+/// A middleware and the options set by its labels.
+#[derive(Debug)]
+struct Middleware {
+    name: String,
+    options: Vec<(&'static str, String)>,
+}
+
+/// Labels defining the app's middlewares and attaching them to each of
+/// `routers`, or an empty string when none are configured.
+pub(super) fn labels(config: &AppConfig, routers: &[String]) -> String {
+    let middlewares = middlewares(config);
+    if middlewares.is_empty() {
+        return String::new();
+    }
+
+    let mut labels = String::new();
+    for middleware in &middlewares {
+        for (option, value) in &middleware.options {
+            // Compose would otherwise interpolate the `$` in hashes and
+            // regex replacements.
+            labels.push_str(&format!(
+                "      - \"traefik.http.middlewares.{}.{}={}\"\n",
+                middleware.name,
+                option,
+                value.replace('$', "$$")
+            ));
+        }
+    }
+    let names = middlewares
+        .iter()
+        .map(|middleware| middleware.name.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    for router in routers {
+        labels.push_str(&format!(
+            "      - \"traefik.http.routers.{}.middlewares={}\"\n",
+            router, names
+        ));
+    }
+    labels
+}
+
+/// Fails when another app on the VPS defines a middleware under one of the
+/// names this app is about to use.
+pub(super) fn ensure_unique(client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+    let names = middlewares(config)
+        .into_iter()
+        .map(|middleware| middleware.name)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let (output, _) = client.execute_command(
+        "grep -H -o -E 'traefik[.]http[.]middlewares[.][^.]+' /opt/minion/*/docker-compose.yml 2>/dev/null || true",
+    )?;
+    for line in output.lines() {
+        let Some((file, label)) = line.split_once(':') else {
+            continue;
+        };
+        let app = file
+            .strip_prefix("/opt/minion/")
+            .and_then(|rest| rest.strip_suffix("/docker-compose.yml"))
+            .unwrap_or(file);
+        let name = label.trim_start_matches("traefik.http.middlewares.");
+        if app != config.app_name && names.iter().any(|own| own == name) {
+            return Err(anyhow!(
+                "Middleware {} is already defined by {} on the VPS. Rename one of the apps.",
+                name,
+                app
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The configured middlewares, in the order requests pass through them.
+fn middlewares(config: &AppConfig) -> Vec<Middleware> {
+    let settings = &config.middlewares;
+    let name = |kind: &str| format!("{}-{}", config.app_name, kind);
+    let mut middlewares = Vec::new();
+
+    let ranges = list_items(&settings.ip_allowlist).collect::<Vec<_>>();
+    if !ranges.is_empty() {
+        middlewares.push(Middleware {
+            name: name("allowlist"),
+            options: vec![("ipallowlist.sourcerange", ranges.join(","))],
+        });
+    }
+
+    if !settings.rate_limit.is_empty() {
+        let mut options = vec![("ratelimit.average", settings.rate_limit.clone())];
+        if !settings.rate_limit_burst.is_empty() {
+            options.push(("ratelimit.burst", settings.rate_limit_burst.clone()));
+        }
+        middlewares.push(Middleware {
+            name: name("ratelimit"),
+            options,
+        });
+    }
+
+    let pairs = config.www_pairs();
+    if !settings.www_redirect.is_empty() && !pairs.is_empty() {
+        // `[.]` rather than `\.`, as in `host_rules`.
+        let hosts = pairs
+            .iter()
+            .map(|host| host.replace('.', "[.]"))
+            .collect::<Vec<_>>()
+            .join("|");
+        let (regex, replacement) = if settings.www_redirect == "www" {
+            (
+                format!("^https?://({})/(.*)", hosts),
+                "https://www.${1}/${2}",
+            )
+        } else {
+            (
+                format!("^https?://www[.]({})/(.*)", hosts),
+                "https://${1}/${2}",
+            )
+        };
+        middlewares.push(Middleware {
+            name: name("redirect"),
+            options: vec![
+                ("redirectregex.regex", regex),
+                ("redirectregex.replacement", replacement.to_string()),
+                ("redirectregex.permanent", "true".to_string()),
+            ],
+        });
+    }
+
+    let mut headers = Vec::new();
+    if settings.security_headers == "true" {
+        headers.extend([
+            ("headers.frameDeny", "true".to_string()),
+            ("headers.contentTypeNosniff", "true".to_string()),
+            ("headers.browserXssFilter", "true".to_string()),
+            (
+                "headers.referrerPolicy",
+                "strict-origin-when-cross-origin".to_string(),
+            ),
+        ]);
+    }
+    if !settings.hsts_max_age.is_empty() && settings.hsts_max_age != "0" {
+        headers.push(("headers.stsSeconds", settings.hsts_max_age.clone()));
+    }
+    if !headers.is_empty() {
+        middlewares.push(Middleware {
+            name: name("headers"),
+            options: headers,
+        });
+    }
+
+    let users = list_items(&settings.basic_auth).collect::<Vec<_>>();
+    if !users.is_empty() {
+        middlewares.push(Middleware {
+            name: name("auth"),
+            options: vec![("basicauth.users", users.join(","))],
+        });
+    }
+
+    let prefixes = list_items(&settings.strip_prefix).collect::<Vec<_>>();
+    if !prefixes.is_empty() {
+        middlewares.push(Middleware {
+            name: name("stripprefix"),
+            options: vec![("stripprefix.prefixes", prefixes.join(","))],
+        });
+    }
+
+    middlewares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            app_name: "my-app".to_string(),
+            app_url: "example.com,www.example.com,api.example.com".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn no_middlewares_render_no_labels() {
+        assert_eq!(labels(&app_config(), &["my-app".to_string()]), "");
+    }
+
+    #[test]
+    fn middlewares_are_namespaced_by_app_and_chained_on_every_router() {
+        let mut config = app_config();
+        let middlewares = &mut config.middlewares;
+        middlewares.www_redirect = "www".to_string();
+        middlewares.basic_auth = "admin:$2y$05$abc/def".to_string();
+        middlewares.security_headers = "true".to_string();
+        middlewares.hsts_max_age = "31536000".to_string();
+        middlewares.strip_prefix = "/api".to_string();
+        middlewares.ip_allowlist = "10.0.0.0/8, 203.0.113.7".to_string();
+        middlewares.rate_limit = "20".to_string();
+        middlewares.rate_limit_burst = "50".to_string();
+
+        assert_eq!(
+            labels(&config, &["my-app".to_string(), "my-app-api".to_string()])
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>(),
+            vec![
+                "- \"traefik.http.middlewares.my-app-allowlist.ipallowlist.sourcerange=10.0.0.0/8,203.0.113.7\"",
+                "- \"traefik.http.middlewares.my-app-ratelimit.ratelimit.average=20\"",
+                "- \"traefik.http.middlewares.my-app-ratelimit.ratelimit.burst=50\"",
+                "- \"traefik.http.middlewares.my-app-redirect.redirectregex.regex=^https?://(example[.]com)/(.*)\"",
+                "- \"traefik.http.middlewares.my-app-redirect.redirectregex.replacement=https://www.$${1}/$${2}\"",
+                "- \"traefik.http.middlewares.my-app-redirect.redirectregex.permanent=true\"",
+                "- \"traefik.http.middlewares.my-app-headers.headers.frameDeny=true\"",
+                "- \"traefik.http.middlewares.my-app-headers.headers.contentTypeNosniff=true\"",
+                "- \"traefik.http.middlewares.my-app-headers.headers.browserXssFilter=true\"",
+                "- \"traefik.http.middlewares.my-app-headers.headers.referrerPolicy=strict-origin-when-cross-origin\"",
+                "- \"traefik.http.middlewares.my-app-headers.headers.stsSeconds=31536000\"",
+                "- \"traefik.http.middlewares.my-app-auth.basicauth.users=admin:$$2y$$05$$abc/def\"",
+                "- \"traefik.http.middlewares.my-app-stripprefix.stripprefix.prefixes=/api\"",
+                "- \"traefik.http.routers.my-app.middlewares=my-app-allowlist,my-app-ratelimit,my-app-redirect,my-app-headers,my-app-auth,my-app-stripprefix\"",
+                "- \"traefik.http.routers.my-app-api.middlewares=my-app-allowlist,my-app-ratelimit,my-app-redirect,my-app-headers,my-app-auth,my-app-stripprefix\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn non_www_redirect_strips_the_www_label() {
+        let mut config = app_config();
+        config.middlewares.www_redirect = "non-www".to_string();
+
+        let labels = labels(&config, &["my-app".to_string()]);
+
+        assert!(labels.contains("redirectregex.regex=^https?://www[.](example[.]com)/(.*)\""));
+        assert!(labels.contains("redirectregex.replacement=https://$${1}/$${2}\""));
+    }
+
+    #[test]
+    fn names_defined_by_other_apps_stop_the_deploy() {
+        let mut config = app_config();
+        config.middlewares.rate_limit = "20".to_string();
+        let remote = FakeRemoteClient::with_responses(vec![(
+            "/opt/minion/my-app/docker-compose.yml:traefik.http.middlewares.my-app-ratelimit\n\
+/opt/minion/blog/docker-compose.yml:traefik.http.middlewares.blog-auth\n",
+            0,
+        )]);
+        ensure_unique(&remote, &config).unwrap();
+
+        let remote = FakeRemoteClient::with_responses(vec![(
+            "/opt/minion/legacy/docker-compose.yml:traefik.http.middlewares.my-app-ratelimit\n",
+            0,
+        )]);
+        assert_eq!(
+            ensure_unique(&remote, &config).unwrap_err().to_string(),
+            "Middleware my-app-ratelimit is already defined by legacy on the VPS. Rename one of the apps."
+        );
+    }
+}
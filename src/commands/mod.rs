@@ -1,9 +1,34 @@
+mod addons;
+mod apps;
+mod backup;
+mod certs;
 mod control;
 mod deploy;
+mod destroy;
+mod env;
+mod hooks;
+mod image;
 mod init;
+mod middlewares;
+mod offsite;
+mod processes;
+mod release;
+mod rollout;
+mod routes;
+mod secrets;
 mod setup;
+mod tunnel;
 
+pub use addons::{AddonsAction, AddonsCommand};
+pub use apps::AppsCommand;
+pub use backup::{BackupAction, BackupCommand};
+pub use certs::{CertsAction, CertsCommand};
 pub use control::{ControlAction, ControlCommand};
 pub use deploy::{DeployCommand, DeployOptions};
+pub use destroy::{DestroyCommand, DestroyOptions};
+pub use env::{EnvAction, EnvCommand};
 pub use init::InitCommand;
+pub use release::{ReleaseAction, ReleaseCommand};
+pub use secrets::{SecretsAction, SecretsCommand};
 pub use setup::SetupCommand;
+pub use tunnel::TunnelCommand;
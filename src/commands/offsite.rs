@@ -0,0 +1,411 @@
+//! Scheduled off-server backups for `minion backup schedule`.
+//!
+//! A cron entry of the `minion` user runs `/opt/minion/<app>/backup.sh`, which
+//! archives the volumes like `minion backup` and uploads the archive with the
+//! MinIO client (`mc`) running in a container, so any S3-compatible storage
+//! works and nothing besides Docker is installed on the VPS. The credentials
+//! live in the client's config file under `/opt/minion/<app>/.mc`, readable by
+//! the `minion` user only.
+
+use anyhow::{anyhow, Result};
+
+use super::backup::archive_pattern;
+use crate::utils::{shell_quote, AppConfig, RemoteClient};
+
+const BACKUP_SCRIPT: &str = include_str!("../resources/backup.sh");
+const MC_IMAGE: &str = "minio/mc";
+/// `mc` alias the credentials are stored under.
+const MC_ALIAS: &str = "target";
+
+/// Uploads the backup script and the `mc` config, then replaces the app's
+/// backup line in the crontab.
+pub(super) fn install(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    volumes: &[String],
+) -> Result<()> {
+    write_file(
+        client,
+        &format!(
+            "mkdir -p {dir}/.mc && chmod 700 {dir}/.mc && umask 077 && cat > {dir}/.mc/config.json",
+            dir = app_dir
+        ),
+        &mc_config(config),
+    )?;
+    write_file(
+        client,
+        &format!(
+            "cat > {dir}/backup.sh && chmod 700 {dir}/backup.sh",
+            dir = app_dir
+        ),
+        &render_script(config, app_dir, volumes)?,
+    )?;
+
+    let entry = format!(
+        "{} {}/backup.sh >> {}/logs/backup.log 2>&1 {}",
+        config.backup_schedule,
+        app_dir,
+        app_dir,
+        cron_tag(config)
+    );
+    let (output, status) = client.execute_command(&format!(
+        "mkdir -p {}/logs && ({}; printf '%s\\n' {}) | crontab -",
+        app_dir,
+        other_cron_lines(config),
+        shell_quote(&entry)
+    ))?;
+    if status != 0 {
+        return Err(anyhow!(
+            "Failed to update the crontab on the VPS. Is cron installed? {}",
+            output.trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs the installed backup script once, streaming its output.
+pub(super) fn run_now(client: &dyn RemoteClient, app_dir: &str) -> Result<()> {
+    let status = client.execute_command_stream(&format!("{}/backup.sh 2>&1", app_dir))?;
+    if status != 0 {
+        return Err(anyhow!("Backup upload failed with exit status {}", status));
+    }
+
+    Ok(())
+}
+
+/// Removes the cron entry, the script and the stored credentials.
+pub(super) fn remove(client: &dyn RemoteClient, config: &AppConfig, app_dir: &str) -> Result<()> {
+    // The mc container runs as root and may leave root-owned files behind.
+    let (output, status) = client.execute_command(&format!(
+        "({}) | crontab - && rm -f {dir}/backup.sh && sudo rm -rf {dir}/.mc",
+        other_cron_lines(config),
+        dir = app_dir
+    ))?;
+    if status != 0 {
+        return Err(anyhow!(
+            "Failed to remove the scheduled backup: {}",
+            output.trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Bucket and prefix the archives are uploaded to, e.g. `backups/my-app/`.
+pub(super) fn upload_location(config: &AppConfig) -> String {
+    let prefix = config.backup_s3_prefix.trim_matches('/');
+    let prefix = if prefix.is_empty() {
+        config.app_name.as_str()
+    } else {
+        prefix
+    };
+    format!("{}/{}/", config.backup_s3_bucket, prefix)
+}
+
+fn render_script(config: &AppConfig, app_dir: &str, volumes: &[String]) -> Result<String> {
+    Ok(BACKUP_SCRIPT
+        .replace("{{app_name}}", &config.app_name)
+        .replace("{{app_dir}}", &shell_quote(app_dir))
+        .replace(
+            "{{target}}",
+            &shell_quote(&format!("{}/{}", MC_ALIAS, upload_location(config))),
+        )
+        .replace("{{mc_image}}", MC_IMAGE)
+        .replace(
+            "{{volumes}}",
+            &volumes
+                .iter()
+                .map(|volume| shell_quote(volume))
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+        .replace("{{pattern}}", &shell_quote(&archive_pattern(config)))
+        .replace("{{keep}}", &config.backup_s3_keep_count()?.to_string()))
+}
+
+fn mc_config(config: &AppConfig) -> String {
+    format!(
+        "{{\n  \"version\": \"10\",\n  \"aliases\": {{\n    \"{}\": {{\n      \"url\": {},\n      \"accessKey\": {},\n      \"secretKey\": {},\n      \"api\": \"s3v4\",\n      \"path\": \"auto\"\n    }}\n  }}\n}}\n",
+        MC_ALIAS,
+        json_string(config.backup_s3_endpoint.trim_end_matches('/')),
+        json_string(config.backup_s3_access_key_id.as_deref().unwrap_or_default()),
+        json_string(
+            config
+                .backup_s3_secret_access_key
+                .as_deref()
+                .unwrap_or_default()
+        )
+    )
+}
+
+/// Credentials are written through stdin so they never show up in a
+/// command line on the VPS.
+fn write_file(client: &dyn RemoteClient, command: &str, content: &str) -> Result<()> {
+    let (output, status) = client.execute_with_stdin(command, &mut content.as_bytes())?;
+    if status != 0 {
+        return Err(anyhow!("Failed to execute command {}: {}", command, output));
+    }
+
+    Ok(())
+}
+
+fn other_cron_lines(config: &AppConfig) -> String {
+    format!(
+        "crontab -l 2>/dev/null | grep -v -F {}",
+        shell_quote(&cron_tag(config))
+    )
+}
+
+/// Marks the app's backup line, kept apart from the `# minion:<app>:` lines
+/// `minion deploy` rewrites for scheduled processes.
+pub(super) fn cron_tag(config: &AppConfig) -> String {
+    format!("# minion-backup:{}:", config.app_name)
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::backup::restore_command;
+    use crate::utils::test_support::FakeRemoteClient;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+    use testcontainers::{
+        core::{IntoContainerPort, WaitFor},
+        runners::SyncRunner,
+        Container, GenericImage, ImageExt,
+    };
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            app_name: "my-app".to_string(),
+            app_volumes: "data:/app/data".to_string(),
+            backup_schedule: "30 2 * * *".to_string(),
+            backup_s3_endpoint: "https://s3.example.com/".to_string(),
+            backup_s3_bucket: "backups".to_string(),
+            backup_s3_keep: "14".to_string(),
+            backup_s3_access_key_id: Some("AKIA".to_string()),
+            backup_s3_secret_access_key: Some("se\"cr/et+".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn install_writes_credentials_through_stdin_and_schedules_the_script() {
+        let remote = FakeRemoteClient::new();
+
+        install(
+            &remote,
+            &app_config(),
+            "/opt/minion/my-app",
+            &["data".to_string()],
+        )
+        .unwrap();
+
+        let inputs = remote
+            .stdin_inputs()
+            .into_iter()
+            .map(|(command, input)| (command, String::from_utf8(input).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs[0].0,
+            "mkdir -p /opt/minion/my-app/.mc && chmod 700 /opt/minion/my-app/.mc && umask 077 && cat > /opt/minion/my-app/.mc/config.json"
+        );
+        assert!(inputs[0].1.contains("\"url\": \"https://s3.example.com\","));
+        assert!(inputs[0].1.contains("\"secretKey\": \"se\\\"cr/et+\","));
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.contains("AKIA")));
+
+        let script = &inputs[1].1;
+        assert!(script.contains("target='target/backups/my-app/'\n"));
+        assert!(script.contains("-czf \"$app_dir/backups/$name\" 'data'\n"));
+        assert!(script.contains("| sort | head -n -14 |"));
+
+        assert_eq!(
+            remote.commands()[2],
+            "mkdir -p /opt/minion/my-app/logs && (crontab -l 2>/dev/null | grep -v -F '# minion-backup:my-app:'; printf '%s\\n' '30 2 * * * /opt/minion/my-app/backup.sh >> /opt/minion/my-app/logs/backup.log 2>&1 # minion-backup:my-app:') | crontab -"
+        );
+    }
+
+    #[test]
+    fn upload_location_defaults_to_the_app_name() {
+        let mut config = app_config();
+        assert_eq!(upload_location(&config), "backups/my-app/");
+
+        config.backup_s3_prefix = "/prod/db/".to_string();
+        assert_eq!(upload_location(&config), "backups/prod/db/");
+    }
+
+    #[test]
+    fn remove_drops_the_cron_entry_and_credentials() {
+        let remote = FakeRemoteClient::new();
+
+        remove(&remote, &app_config(), "/opt/minion/my-app").unwrap();
+
+        assert_eq!(
+            remote.commands(),
+            vec![
+                "(crontab -l 2>/dev/null | grep -v -F '# minion-backup:my-app:') | crontab - && rm -f /opt/minion/my-app/backup.sh && sudo rm -rf /opt/minion/my-app/.mc"
+            ]
+        );
+    }
+
+    /// A MinIO container with a `backups` bucket, and its endpoint.
+    fn start_minio() -> (Container<GenericImage>, String) {
+        let minio = GenericImage::new("minio/minio", "latest")
+            .with_exposed_port(9000.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("API:"))
+            .with_env_var("MINIO_ROOT_USER", "minion")
+            .with_env_var("MINIO_ROOT_PASSWORD", "minion-secret")
+            .with_cmd(["server", "/data"])
+            .start()
+            .unwrap();
+        let endpoint = format!(
+            "http://127.0.0.1:{}",
+            minio.get_host_port_ipv4(9000).unwrap()
+        );
+        mc(&endpoint, &[], &["mb", "minio/backups"]);
+        (minio, endpoint)
+    }
+
+    /// Runs the `mc` image with the MinIO at `endpoint` as alias `minio`.
+    fn mc(endpoint: &str, docker_args: &[&str], args: &[&str]) -> String {
+        let output = Command::new("docker")
+            .args(["run", "--rm", "--network", "host", "-e"])
+            .arg(format!(
+                "MC_HOST_minio=http://minion:minion-secret@{}",
+                endpoint.trim_start_matches("http://")
+            ))
+            .args(docker_args)
+            .arg(MC_IMAGE)
+            .args(["--config-dir", "/tmp/mc"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    /// An app directory with a `data` volume, the `mc` config and the
+    /// rendered script for `endpoint`, plus a PATH with a `sudo` stand-in.
+    fn scheduled_app_dir(endpoint: &str) -> (tempfile::TempDir, String) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let app_dir = dir.path().to_str().unwrap();
+        fs::create_dir_all(dir.path().join("volumes/data")).unwrap();
+        fs::write(dir.path().join("volumes/data/file"), "data").unwrap();
+        fs::create_dir_all(dir.path().join(".mc")).unwrap();
+        fs::create_dir_all(dir.path().join("bin")).unwrap();
+        let sudo = dir.path().join("bin/sudo");
+        fs::write(&sudo, "#!/bin/sh\nexec \"$@\"\n").unwrap();
+        fs::set_permissions(&sudo, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = AppConfig {
+            backup_s3_endpoint: endpoint.to_string(),
+            backup_s3_keep: "2".to_string(),
+            backup_s3_access_key_id: Some("minion".to_string()),
+            backup_s3_secret_access_key: Some("minion-secret".to_string()),
+            ..app_config()
+        };
+        fs::write(dir.path().join(".mc/config.json"), mc_config(&config)).unwrap();
+        fs::write(
+            dir.path().join("backup.sh"),
+            render_script(&config, app_dir, &["data".to_string()]).unwrap(),
+        )
+        .unwrap();
+
+        let path = format!(
+            "{}:{}",
+            dir.path().join("bin").display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        (dir, path)
+    }
+
+    fn run_script(dir: &Path, path: &str, script: &str) {
+        let output = Command::new("sh")
+            .args(["-c", script])
+            .current_dir(dir)
+            .env("PATH", path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    }
+
+    /// Runs the rendered script locally against a MinIO container, with a
+    /// `sudo` stand-in, and checks upload and retention.
+    #[test]
+    #[ignore = "requires Docker and pulls the MinIO images"]
+    fn script_uploads_and_prunes_archives_in_minio() {
+        let (_minio, endpoint) = start_minio();
+        let (dir, path) = scheduled_app_dir(&endpoint);
+
+        for _ in 0..3 {
+            run_script(dir.path(), &path, "sh backup.sh");
+            std::thread::sleep(std::time::Duration::from_millis(1100));
+        }
+
+        let listing = mc(&endpoint, &[], &["ls", "minio/backups/my-app/"]);
+        assert_eq!(listing.lines().count(), 2, "{}", listing);
+        assert!(fs::read_dir(dir.path().join("backups"))
+            .unwrap()
+            .next()
+            .is_none());
+    }
+
+    /// Uploads an archive with the script, downloads it again as `minion
+    /// backup restore` users would, and restores it over changed volumes.
+    #[test]
+    #[ignore = "requires Docker and pulls the MinIO images"]
+    fn archive_uploaded_to_minio_restores_the_volumes() {
+        let (_minio, endpoint) = start_minio();
+        let (dir, path) = scheduled_app_dir(&endpoint);
+        run_script(dir.path(), &path, "sh backup.sh");
+
+        fs::write(dir.path().join("volumes/data/file"), "changed").unwrap();
+        fs::write(dir.path().join("volumes/data/extra"), "new").unwrap();
+
+        let listing = mc(&endpoint, &[], &["ls", "minio/backups/my-app/"]);
+        let name = listing.split_whitespace().last().unwrap().to_string();
+        let backups = format!("{}:/backups", dir.path().join("backups").display());
+        // Download as the current user, so the archive is not owned by root.
+        let id = |flag: &str| {
+            let output = Command::new("id").arg(flag).output().unwrap();
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+        let user = format!("{}:{}", id("-u"), id("-g"));
+        mc(
+            &endpoint,
+            &["-v", &backups, "--user", &user],
+            &["cp", &format!("minio/backups/my-app/{}", name), "/backups/"],
+        );
+
+        let app_dir = dir.path().to_str().unwrap();
+        run_script(dir.path(), &path, &restore_command(app_dir, &name));
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("volumes/data/file")).unwrap(),
+            "data"
+        );
+        assert!(!dir.path().join("volumes/data/extra").exists());
+    }
+}
@@ -0,0 +1,297 @@
+//! Extra processes that share the app image: workers, sidecars with their
+//! own router, and scheduled jobs.
+//!
+//! Every process becomes a service in the app's compose project. Scheduled
+//! processes sit in the `scheduled` compose profile, so `docker compose up`
+//! never starts them, and the minion user's crontab runs them with
+//! `docker compose run` instead.
+
+use anyhow::{anyhow, Result};
+
+use super::deploy::{host_rules, tls_labels};
+use crate::utils::{shell_quote, AppConfig, ProcessConfig, RemoteClient};
+
+const SCHEDULED_PROFILE: &str = "scheduled";
+
+/// Renders the compose services for `config.processes`, each preceded by a
+/// newline so the result can follow the web service directly.
+pub(super) fn render_services(config: &AppConfig, release: &str, volumes_section: &str) -> String {
+    config
+        .processes
+        .iter()
+        .map(|process| render_service(config, process, release, volumes_section))
+        .collect()
+}
+
+fn render_service(
+    config: &AppConfig,
+    process: &ProcessConfig,
+    release: &str,
+    volumes_section: &str,
+) -> String {
+    let mut service = format!(
+        "\n  {}:\n    image: minion_{}:{}\n    command: {}\n",
+        process.name,
+        config.app_name,
+        release,
+        yaml_string(&process.command)
+    );
+
+    if process.is_scheduled() {
+        service.push_str(&format!(
+            "    restart: \"no\"\n    profiles:\n      - {}\n",
+            SCHEDULED_PROFILE
+        ));
+    } else {
+        service.push_str(&format!(
+            "    restart: unless-stopped\n    deploy:\n      replicas: {}\n",
+            process.replicas
+        ));
+    }
+
+    service.push_str(
+        "    env_file:\n      - app.env\n      - secrets.env\n    networks:\n      - traefik_network\n",
+    );
+
+    if process.is_routed() {
+        let router = router(config, process);
+        let urls = process
+            .url
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .collect::<Vec<_>>();
+        let port = if process.port.is_empty() {
+            &config.app_port
+        } else {
+            &process.port
+        };
+        service.push_str(&format!(
+            "    labels:\n      - \"traefik.enable=true\"\n      - \"traefik.http.routers.{router}.rule={}\"\n      - \"traefik.http.routers.{router}.entrypoints=websecure\"\n{}      - \"traefik.http.services.{router}.loadbalancer.server.port={}\"\n",
+            host_rules(&urls),
+            tls_labels(config, &router, &urls),
+            port,
+            router = router
+        ));
+    }
+
+    if !volumes_section.is_empty() {
+        service.push_str(volumes_section);
+        service.push('\n');
+    }
+
+    service
+}
+
+/// Name of the router, and of its service, in front of a routed process.
+pub(super) fn router(config: &AppConfig, process: &ProcessConfig) -> String {
+    format!("{}-{}", config.app_name, process.name)
+}
+
+/// Points the minion user's crontab at the app's scheduled processes,
+/// replacing whatever an earlier deploy installed.
+pub(super) fn install_schedules(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+) -> Result<()> {
+    let tag = schedule_tag(config);
+    let entries = config
+        .processes
+        .iter()
+        .filter(|process| process.is_scheduled())
+        .map(|process| {
+            shell_quote(&format!(
+                "{} cd {} && docker compose run --rm --no-deps -T {} >> {} 2>&1 {}",
+                process.schedule,
+                app_dir,
+                process.name,
+                schedule_log_path(app_dir, &process.name),
+                tag
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let keep_others = format!("crontab -l 2>/dev/null | grep -v -F {}", shell_quote(&tag));
+    let command = if entries.is_empty() {
+        // Only touch the crontab when an earlier deploy scheduled something.
+        format!(
+            "if crontab -l 2>/dev/null | grep -q -F {}; then ({}) | crontab -; fi",
+            shell_quote(&tag),
+            keep_others
+        )
+    } else {
+        println!("Scheduling {} job(s)...", entries.len());
+        format!(
+            "mkdir -p {}/logs && ({}; printf '%s\\n' {}) | crontab -",
+            app_dir,
+            keep_others,
+            entries.join(" ")
+        )
+    };
+
+    let (output, status) = client.execute_command(&command)?;
+    if status != 0 {
+        return Err(anyhow!(
+            "Failed to update the crontab on the VPS. Is cron installed? {}",
+            output.trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Looks up a process by name. `web` refers to the app's main service and
+/// returns `None`.
+pub(super) fn find_process<'a>(
+    config: &'a AppConfig,
+    name: &str,
+) -> Result<Option<&'a ProcessConfig>> {
+    if name == "web" {
+        return Ok(None);
+    }
+
+    config
+        .processes
+        .iter()
+        .find(|process| process.name == name)
+        .map(Some)
+        .ok_or_else(|| {
+            let known = std::iter::once("web")
+                .chain(config.processes.iter().map(|process| process.name.as_str()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            anyhow!("Unknown process {}. Known processes: {}", name, known)
+        })
+}
+
+/// Output of every run of a scheduled process is appended to this file.
+pub(super) fn schedule_log_path(app_dir: &str, name: &str) -> String {
+    format!("{}/logs/{}.log", app_dir, name)
+}
+
+/// Marks the app's crontab lines. The trailing colon keeps `my-app` from
+/// matching the lines of `my-app-2`.
+pub(super) fn schedule_tag(config: &AppConfig) -> String {
+    format!("# minion:{}:", config.app_name)
+}
+
+pub(super) fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    fn process(name: &str, command: &str) -> ProcessConfig {
+        ProcessConfig {
+            name: name.to_string(),
+            command: command.to_string(),
+            replicas: "1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn app_config(processes: Vec<ProcessConfig>) -> AppConfig {
+        AppConfig {
+            app_name: "my-app".to_string(),
+            app_port: "3000".to_string(),
+            cert_resolver: "letsencrypt".to_string(),
+            processes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn worker_gets_replicas_and_no_router() {
+        let mut worker = process("worker", "bundle exec sidekiq -q \"default\"");
+        worker.replicas = "3".to_string();
+
+        let services = render_services(&app_config(vec![worker]), "r1", "");
+
+        assert_eq!(
+            services,
+            "\n  worker:\n    image: minion_my-app:r1\n    command: \"bundle exec sidekiq -q \\\"default\\\"\"\n    restart: unless-stopped\n    deploy:\n      replicas: 3\n    env_file:\n      - app.env\n      - secrets.env\n    networks:\n      - traefik_network\n"
+        );
+    }
+
+    #[test]
+    fn routed_process_gets_its_own_router_and_port() {
+        let mut admin = process("admin", "./admin");
+        admin.url = "admin.example.com, ops.example.com".to_string();
+        admin.port = "9000".to_string();
+
+        let services = render_services(
+            &app_config(vec![admin]),
+            "r1",
+            "    volumes:\n      - /opt/minion/my-app/volumes/data:/data",
+        );
+
+        assert!(services.contains(
+            "traefik.http.routers.my-app-admin.rule=Host(`admin.example.com`) || Host(`ops.example.com`)"
+        ));
+        assert!(
+            services.contains("traefik.http.services.my-app-admin.loadbalancer.server.port=9000")
+        );
+        assert!(services.ends_with("    volumes:\n      - /opt/minion/my-app/volumes/data:/data\n"));
+    }
+
+    #[test]
+    fn scheduled_process_is_kept_out_of_compose_up() {
+        let mut nightly = process("nightly", "rake cleanup");
+        nightly.schedule = "0 3 * * *".to_string();
+
+        let services = render_services(&app_config(vec![nightly]), "r1", "");
+
+        assert!(services.contains("    restart: \"no\"\n    profiles:\n      - scheduled\n"));
+        assert!(!services.contains("replicas"));
+    }
+
+    #[test]
+    fn schedules_replace_the_apps_crontab_lines() {
+        let mut nightly = process("nightly", "rake cleanup");
+        nightly.schedule = "0 3 * * *".to_string();
+        let remote = FakeRemoteClient::new();
+
+        install_schedules(
+            &remote,
+            &app_config(vec![process("worker", "work"), nightly]),
+            "/opt/minion/my-app",
+        )
+        .unwrap();
+
+        assert_eq!(
+            remote.commands(),
+            vec![
+                "mkdir -p /opt/minion/my-app/logs && (crontab -l 2>/dev/null | grep -v -F '# minion:my-app:'; printf '%s\\n' '0 3 * * * cd /opt/minion/my-app && docker compose run --rm --no-deps -T nightly >> /opt/minion/my-app/logs/nightly.log 2>&1 # minion:my-app:') | crontab -"
+            ]
+        );
+    }
+
+    #[test]
+    fn without_schedules_only_stale_crontab_lines_are_removed() {
+        let remote = FakeRemoteClient::new();
+
+        install_schedules(&remote, &app_config(Vec::new()), "/opt/minion/my-app").unwrap();
+
+        assert!(remote.commands()[0]
+            .starts_with("if crontab -l 2>/dev/null | grep -q -F '# minion:my-app:'; then"));
+    }
+
+    #[test]
+    fn find_process_accepts_web_and_declared_names() {
+        let config = app_config(vec![process("worker", "work")]);
+
+        assert_eq!(find_process(&config, "web").unwrap(), None);
+        assert_eq!(
+            find_process(&config, "worker").unwrap().unwrap().name,
+            "worker"
+        );
+        assert_eq!(
+            find_process(&config, "mailer").unwrap_err().to_string(),
+            "Unknown process mailer. Known processes: web, worker"
+        );
+    }
+}
@@ -0,0 +1,486 @@
+//! Versioned releases kept under `/opt/minion/<app>/releases`.
+//!
+//! Every deploy stores its rendered compose file in `releases/<release>` and
+//! tags its image as `minion_<app>:<release>`, so rolling back only has to
+//! re-point the active compose file and run the usual cutover.
+
+use anyhow::{anyhow, Result};
+
+use super::rollout;
+use crate::utils::{list_items, AppConfig, AppConfigOverrides, RemoteClient, SshClient};
+
+const RELEASES_TO_KEEP: usize = 5;
+
+pub struct ReleaseCommand;
+
+#[derive(Debug, Clone)]
+pub enum ReleaseAction {
+    List,
+    Rollback { release: Option<String> },
+}
+
+impl ReleaseCommand {
+    pub fn new() -> Self {
+        ReleaseCommand
+    }
+
+    pub fn execute(&self, action: ReleaseAction, overrides: AppConfigOverrides) -> Result<()> {
+        let config = AppConfig::load(overrides, false, false)?;
+        config.require_app_control()?;
+        if let ReleaseAction::Rollback { .. } = action {
+            config.require_health_check()?;
+        }
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        self.execute_action(action, &config, &client)
+    }
+
+    fn execute_action(
+        &self,
+        action: ReleaseAction,
+        config: &AppConfig,
+        client: &dyn RemoteClient,
+    ) -> Result<()> {
+        match action {
+            ReleaseAction::List => self.list(client, config),
+            ReleaseAction::Rollback { release } => self.rollback(client, config, release),
+        }
+    }
+
+    fn list(&self, client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+        let app_dir = app_dir(config);
+        let releases = list_releases(client, &app_dir)?;
+        if releases.is_empty() {
+            println!(
+                "No releases found for {}. Run `minion deploy` first.",
+                config.app_name
+            );
+            return Ok(());
+        }
+
+        let current = current_release(client, &app_dir)?;
+        for release in releases {
+            if current.as_deref() == Some(release.as_str()) {
+                println!("* {} (current)", release);
+            } else {
+                println!("  {}", release);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rollback(
+        &self,
+        client: &dyn RemoteClient,
+        config: &AppConfig,
+        release: Option<String>,
+    ) -> Result<()> {
+        let app_dir = app_dir(config);
+        let releases = list_releases(client, &app_dir)?;
+        let current = current_release(client, &app_dir)?;
+
+        let target = match release {
+            Some(release) => {
+                if !releases.contains(&release) {
+                    return Err(anyhow!(
+                        "Release {} not found. Run `minion releases` to list available releases.",
+                        release
+                    ));
+                }
+                release
+            }
+            None => previous_release(&releases, current.as_deref())
+                .ok_or_else(|| anyhow!("No earlier release to roll back to"))?,
+        };
+
+        if current.as_deref() == Some(target.as_str()) {
+            return Err(anyhow!("Release {} is already live", target));
+        }
+
+        println!("Rolling back {} to release {}...", config.app_name, target);
+        activate_release(client, config, &app_dir, &target)?;
+        println!("✓ Rolled back to release {}", target);
+
+        Ok(())
+    }
+}
+
+pub(super) fn release_dir(app_dir: &str, release: &str) -> String {
+    format!("{}/releases/{}", app_dir, release)
+}
+
+/// Makes `release` the active compose file and cuts over to it. If the new
+/// containers never become healthy, the previous compose file is restored.
+/// If the app's first URL then does not answer through Traefik, the previous
+/// release is rolled out again.
+pub(super) fn activate_release(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    release: &str,
+) -> Result<()> {
+    let previous = current_release(client, app_dir)?;
+    run(
+        client,
+        &format!(
+            "cp {}/docker-compose.yml {}/docker-compose.yml",
+            release_dir(app_dir, release),
+            app_dir
+        ),
+    )?;
+
+    if let Err(error) = rollout::rolling_update(client, config, app_dir) {
+        if let Some(previous) = previous {
+            client.execute_command(&format!(
+                "cp {}/docker-compose.yml {}/docker-compose.yml",
+                release_dir(app_dir, &previous),
+                app_dir
+            ))?;
+        }
+        return Err(error);
+    }
+
+    if let Some(url) = list_items(&config.app_url).next() {
+        if let Err(error) = rollout::wait_public_url(client, config, app_dir, url) {
+            let Some(previous) = previous else {
+                return Err(error);
+            };
+
+            println!("Restoring release {}...", previous);
+            run(
+                client,
+                &format!(
+                    "cp {}/docker-compose.yml {}/docker-compose.yml",
+                    release_dir(app_dir, &previous),
+                    app_dir
+                ),
+            )?;
+            if let Err(restore_error) = rollout::rolling_update(client, config, app_dir) {
+                return Err(anyhow!(
+                    "{}\n\nRestoring release {} failed as well: {}",
+                    error,
+                    previous,
+                    restore_error
+                ));
+            }
+            return Err(anyhow!("{}\n\nRelease {} is live again.", error, previous));
+        }
+    }
+
+    run(
+        client,
+        &format!("echo {} > {}/current_release", release, app_dir),
+    )?;
+    prune_releases(client, config, app_dir, release)
+}
+
+fn prune_releases(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    current: &str,
+) -> Result<()> {
+    let releases = list_releases(client, app_dir)?;
+    if releases.len() <= RELEASES_TO_KEEP {
+        return Ok(());
+    }
+
+    let expired = releases
+        .iter()
+        .filter(|release| release.as_str() != current)
+        .take(releases.len() - RELEASES_TO_KEEP);
+    for release in expired {
+        client.execute_command(&format!(
+            "rm -rf {} && (docker image rm minion_{}:{} >/dev/null 2>&1 || true)",
+            release_dir(app_dir, release),
+            config.app_name,
+            release
+        ))?;
+    }
+
+    Ok(())
+}
+
+fn list_releases(client: &dyn RemoteClient, app_dir: &str) -> Result<Vec<String>> {
+    let (output, _) = client.execute_command(&format!("ls -1 {}/releases 2>/dev/null", app_dir))?;
+    let mut releases = output
+        .lines()
+        .map(str::trim)
+        .filter(|release| is_release_id(release))
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    releases.sort();
+
+    Ok(releases)
+}
+
+fn current_release(client: &dyn RemoteClient, app_dir: &str) -> Result<Option<String>> {
+    let (output, _) =
+        client.execute_command(&format!("cat {}/current_release 2>/dev/null", app_dir))?;
+    let release = output.trim();
+
+    Ok(is_release_id(release).then(|| release.to_string()))
+}
+
+fn previous_release(releases: &[String], current: Option<&str>) -> Option<String> {
+    let index = releases
+        .iter()
+        .position(|release| Some(release.as_str()) == current)?;
+
+    index
+        .checked_sub(1)
+        .and_then(|previous| releases.get(previous))
+        .cloned()
+}
+
+fn is_release_id(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|value| value.is_ascii_alphanumeric() || value == '-')
+}
+
+fn run(client: &dyn RemoteClient, command: &str) -> Result<()> {
+    let (output, status) = client.execute_command(command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to execute command {}: {}", command, output));
+    }
+
+    Ok(())
+}
+
+fn app_dir(config: &AppConfig) -> String {
+    format!("/opt/minion/{}", config.app_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    const RELEASES: &str = "20261001090000-abc1234\n20261002090000\n20261003090000-def5678\n";
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            host: "example.com".to_string(),
+            app_name: "my-app".to_string(),
+            app_port: "3000".to_string(),
+            ssh_user: "minion".to_string(),
+            healthcheck_type: "tcp".to_string(),
+            healthcheck_path: "/".to_string(),
+            healthcheck_wait: "60".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn list_reads_release_directories_and_current_marker() {
+        let remote = FakeRemoteClient::new()
+            .on_command("ls -1", RELEASES, 0)
+            .on_command("current_release", "20261002090000\n", 0);
+
+        ReleaseCommand::new()
+            .execute_action(ReleaseAction::List, &app_config(), &remote)
+            .unwrap();
+
+        assert_eq!(
+            remote.commands(),
+            vec![
+                "ls -1 /opt/minion/my-app/releases 2>/dev/null",
+                "cat /opt/minion/my-app/current_release 2>/dev/null",
+            ]
+        );
+    }
+
+    #[test]
+    fn rollback_without_release_picks_the_one_before_current() {
+        let remote = FakeRemoteClient::new()
+            .on_command("ls -1", RELEASES, 0)
+            .on_command("current_release", "20261003090000-def5678\n", 0);
+
+        ReleaseCommand::new()
+            .execute_action(
+                ReleaseAction::Rollback { release: None },
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        let commands = remote.commands();
+        assert!(commands.contains(
+            &"cp /opt/minion/my-app/releases/20261002090000/docker-compose.yml /opt/minion/my-app/docker-compose.yml"
+                .to_string()
+        ));
+        assert!(commands.contains(&"cd /opt/minion/my-app && docker compose up -d".to_string()));
+        assert!(commands
+            .contains(&"echo 20261002090000 > /opt/minion/my-app/current_release".to_string()));
+        assert!(!commands
+            .iter()
+            .any(|command| command.contains("docker build")));
+    }
+
+    #[test]
+    fn rollback_to_unknown_release_fails_before_touching_compose() {
+        let remote = FakeRemoteClient::new().on_command("ls -1", RELEASES, 0);
+
+        let error = ReleaseCommand::new()
+            .execute_action(
+                ReleaseAction::Rollback {
+                    release: Some("20200101000000".to_string()),
+                },
+                &app_config(),
+                &remote,
+            )
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("Release 20200101000000 not found"));
+        assert!(!remote
+            .commands()
+            .iter()
+            .any(|command| command.starts_with("cp ")));
+    }
+
+    #[test]
+    fn rollback_to_current_release_is_rejected() {
+        let remote = FakeRemoteClient::new()
+            .on_command("ls -1", RELEASES, 0)
+            .on_command("current_release", "20261002090000\n", 0);
+
+        let error = ReleaseCommand::new()
+            .execute_action(
+                ReleaseAction::Rollback {
+                    release: Some("20261002090000".to_string()),
+                },
+                &app_config(),
+                &remote,
+            )
+            .unwrap_err();
+
+        assert!(error.to_string().contains("already live"));
+    }
+
+    #[test]
+    fn failed_activation_restores_previous_compose_file() {
+        let remote = FakeRemoteClient::new()
+            .on_command("current_release", "20261001090000-abc1234\n", 0)
+            .on_command("docker compose ps -q", "old1\n", 0)
+            .on_command("docker compose ps -q", "old1\nnew1\n", 0)
+            .on_command("id=new1;", "", 1);
+
+        let error = activate_release(
+            &remote,
+            &app_config(),
+            "/opt/minion/my-app",
+            "20261002090000",
+        )
+        .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains("previous version is still serving"));
+        let commands = remote.commands();
+        assert!(commands.contains(
+            &"cp /opt/minion/my-app/releases/20261001090000-abc1234/docker-compose.yml /opt/minion/my-app/docker-compose.yml"
+                .to_string()
+        ));
+        assert!(!commands
+            .iter()
+            .any(|command| command.contains("> /opt/minion/my-app/current_release")));
+    }
+
+    #[test]
+    fn unreachable_public_url_rolls_the_previous_release_out_again() {
+        let mut config = app_config();
+        config.app_url = "app.example.com".to_string();
+        let remote = FakeRemoteClient::new()
+            .on_command("current_release", "20261001090000-abc1234\n", 0)
+            .on_command("docker compose ps -q", "old1\n", 0)
+            .on_command("docker compose ps -q", "old1\nnew1\n", 0)
+            .on_command("--resolve", "502\n", 1)
+            .on_command("docker compose ps -q", "new1\n", 0)
+            .on_command("docker compose ps -q", "new1\nold2\n", 0);
+
+        let error = activate_release(&remote, &config, "/opt/minion/my-app", "20261002090000")
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.starts_with("https://app.example.com/ did not respond"));
+        assert!(error.ends_with("Release 20261001090000-abc1234 is live again."));
+        let commands = remote.commands();
+        let restored = commands
+            .iter()
+            .position(|command| {
+                command
+                    == "cp /opt/minion/my-app/releases/20261001090000-abc1234/docker-compose.yml /opt/minion/my-app/docker-compose.yml"
+            })
+            .unwrap();
+        assert!(commands[restored..].contains(&"docker stop new1 && docker rm new1".to_string()));
+        assert!(!commands
+            .iter()
+            .any(|command| command.contains("> /opt/minion/my-app/current_release")));
+    }
+
+    #[test]
+    fn basic_auth_refusing_the_public_probe_keeps_the_release() {
+        let mut config = app_config();
+        config.app_url = "app.example.com".to_string();
+        config.healthcheck_type = "http".to_string();
+        config.healthcheck_path = "/health".to_string();
+        config.middlewares.basic_auth = "admin:$2y$05$abc".to_string();
+        // Traefik answers 401 before the request reaches the app; only a
+        // probe that takes it as an answer passes.
+        let remote = FakeRemoteClient::new()
+            .on_command("current_release", "20261001090000-abc1234\n", 0)
+            .on_command("docker compose ps -q", "old1\n", 0)
+            .on_command("docker compose ps -q", "old1\nnew1\n", 0)
+            .on_command("case \"$code\" in 401) exit 0;; esac;", "", 0)
+            .on_command("--resolve", "401\n", 1);
+
+        activate_release(&remote, &config, "/opt/minion/my-app", "20261002090000").unwrap();
+
+        let commands = remote.commands();
+        assert!(commands
+            .contains(&"echo 20261002090000 > /opt/minion/my-app/current_release".to_string()));
+        assert!(!commands.iter().any(|command| command
+            .contains("cp /opt/minion/my-app/releases/20261001090000-abc1234/docker-compose.yml")));
+    }
+
+    #[test]
+    fn activation_prunes_oldest_releases_beyond_retention() {
+        let releases = "r1\nr2\nr3\nr4\nr5\nr6\nr7\n";
+        let remote = FakeRemoteClient::new().on_command("ls -1", releases, 0);
+
+        activate_release(&remote, &app_config(), "/opt/minion/my-app", "r7").unwrap();
+
+        let removed = remote
+            .commands()
+            .into_iter()
+            .filter(|command| command.starts_with("rm -rf"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            removed,
+            vec![
+                "rm -rf /opt/minion/my-app/releases/r1 && (docker image rm minion_my-app:r1 >/dev/null 2>&1 || true)",
+                "rm -rf /opt/minion/my-app/releases/r2 && (docker image rm minion_my-app:r2 >/dev/null 2>&1 || true)",
+            ]
+        );
+    }
+
+    #[test]
+    fn previous_release_requires_a_known_current_release() {
+        let releases = vec!["a".to_string(), "b".to_string()];
+
+        assert_eq!(
+            previous_release(&releases, Some("b")),
+            Some("a".to_string())
+        );
+        assert_eq!(previous_release(&releases, Some("a")), None);
+        assert_eq!(previous_release(&releases, None), None);
+    }
+}
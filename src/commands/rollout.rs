@@ -0,0 +1,495 @@
+//! Health-gated cutover for `minion deploy`.
+//!
+//! The new container is started next to the running one on
+//! `traefik_network`, probed from the VPS, and only then is the previous
+//! container retired. The web service always has a Docker health check, and
+//! Traefik leaves out containers that Docker does not report as healthy, so
+//! the candidate only receives traffic once its own check passes; the probe
+//! waits for the same verdict. Once the cutover is done, the release polls the
+//! public URL through Traefik before it is recorded as current.
+
+use anyhow::{anyhow, Result};
+
+use super::processes::yaml_string;
+use crate::utils::{list_items, shell_quote, AppConfig, RemoteClient};
+
+const HEALTH_POLL_INTERVAL_SECS: u64 = 2;
+const FAILED_LOG_LINES: u16 = 50;
+
+pub(super) fn rolling_update(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+) -> Result<()> {
+    let service = &config.app_name;
+    let previous = container_ids(client, app_dir, service)?;
+
+    if previous.is_empty() {
+        println!("Starting {}...", service);
+        run(client, &format!("cd {} && docker compose up -d", app_dir))?;
+
+        let container = format!(
+            "$(cd {} && docker compose ps -q {} | head -n 1)",
+            app_dir, service
+        );
+        if let Err(error) = wait_healthy(client, config, &container) {
+            let logs = recent_logs(
+                client,
+                &format!(
+                    "cd {} && docker compose logs --tail {} {} 2>&1",
+                    app_dir, FAILED_LOG_LINES, service
+                ),
+            )?;
+            return Err(anyhow!("{}\n\nRecent logs:\n{}", error, logs));
+        }
+
+        println!("✓ Container is healthy");
+        return Ok(());
+    }
+
+    println!("Starting new container alongside the running version...");
+    run(
+        client,
+        &format!(
+            "cd {} && docker compose up -d --no-deps --no-recreate --scale {}={} {}",
+            app_dir,
+            service,
+            previous.len() + 1,
+            service
+        ),
+    )?;
+
+    let candidate = container_ids(client, app_dir, service)?
+        .into_iter()
+        .find(|id| !previous.contains(id))
+        .ok_or_else(|| anyhow!("Failed to find the newly started container"))?;
+
+    println!("Waiting for the new container to become healthy...");
+    if let Err(error) = wait_healthy(client, config, &candidate) {
+        let logs = recent_logs(
+            client,
+            &format!("docker logs --tail {} {} 2>&1", FAILED_LOG_LINES, candidate),
+        )?;
+        client.execute_command(&format!("docker rm -f {}", candidate))?;
+        return Err(anyhow!(
+            "{}. The previous version is still serving traffic.\n\nRecent logs:\n{}",
+            error,
+            logs
+        ));
+    }
+    println!("✓ New container is healthy");
+
+    println!("Retiring previous container...");
+    let previous = previous.join(" ");
+    run(
+        client,
+        &format!("docker stop {} && docker rm {}", previous, previous),
+    )?;
+
+    // Bring the other processes up to date and drop the ones no longer
+    // declared. The web container already matches the new compose file, so
+    // compose leaves it alone.
+    println!("Updating other processes...");
+    run(
+        client,
+        &format!(
+            "cd {} && docker compose up -d --no-deps --remove-orphans $(docker compose config --services)",
+            app_dir
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// Polls `https://<url><path>` through Traefik on the VPS until it answers.
+/// DNS is bypassed and certificates are not verified, so a deploy does not
+/// fail while DNS or a Let's Encrypt certificate is still on its way.
+pub(super) fn wait_public_url(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    url: &str,
+) -> Result<()> {
+    // A wildcard URL is requested with a sample host in its place.
+    let url = &url.replacen('*', "minion-check", 1);
+    println!("Waiting for https://{} to respond...", url);
+    let (output, status) = client.execute_command(&public_probe_script(config, url)?)?;
+    if status == 0 {
+        return Ok(());
+    }
+
+    let logs = recent_logs(
+        client,
+        &format!(
+            "cd {} && docker compose logs --tail {} {} 2>&1",
+            app_dir, FAILED_LOG_LINES, config.app_name
+        ),
+    )?;
+    Err(anyhow!(
+        "https://{}{} did not respond with a {} status within {}s (last status: {}).\n\nRecent logs:\n{}",
+        url,
+        config.healthcheck_path,
+        accepted_statuses(config),
+        config.healthcheck_wait,
+        output.trim(),
+        logs
+    ))
+}
+
+/// Compose `healthcheck` for the web service, so Docker keeps tracking the
+/// app's health after the deploy. An `http` check uses curl or wget and a
+/// `tcp` check uses nc or bash, whichever the image has.
+pub(super) fn healthcheck_section(config: &AppConfig) -> String {
+    let test = if config.healthcheck_type == "http" {
+        let url = shell_quote(&format!(
+            "http://localhost:{}{}",
+            config.app_port, config.healthcheck_path
+        ));
+        format!(
+            "curl -fsS -o /dev/null {url} || wget -q -O /dev/null {url}",
+            url = url
+        )
+    } else {
+        format!(
+            "nc -z localhost {port} || bash -c '</dev/tcp/localhost/{port}'",
+            port = config.app_port
+        )
+    };
+    format!(
+        "    healthcheck:\n      test: [\"CMD-SHELL\", {}]\n      interval: {}s\n      timeout: {}s\n      retries: {}\n      start_period: {}s\n",
+        yaml_string(&test),
+        config.healthcheck_interval,
+        config.healthcheck_timeout,
+        config.healthcheck_retries,
+        config.healthcheck_wait
+    )
+}
+
+fn container_ids(client: &dyn RemoteClient, app_dir: &str, service: &str) -> Result<Vec<String>> {
+    let command = format!("cd {} && docker compose ps -q {}", app_dir, service);
+    let (output, status) = client.execute_command(&command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to list app containers: {}", output));
+    }
+
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+fn wait_healthy(client: &dyn RemoteClient, config: &AppConfig, container: &str) -> Result<()> {
+    let (_, status) = client.execute_command(&health_probe_script(config, container)?)?;
+    match status {
+        0 => Ok(()),
+        2 => Err(anyhow!("The new container exited before becoming healthy")),
+        3 => Err(anyhow!(
+            "The new container was reported unhealthy by its Docker health check"
+        )),
+        _ => Err(anyhow!(
+            "The new container did not pass the {} health check within {}s",
+            config.healthcheck_type,
+            config.healthcheck_wait
+        )),
+    }
+}
+
+/// Builds a script that polls `container` from the VPS until the configured
+/// probe succeeds (exit 0), the container stops (exit 2), Docker reports it
+/// unhealthy (exit 3), or the wait runs out (exit 1). While the container has
+/// a Docker health check, the probe only counts once Docker agrees.
+fn health_probe_script(config: &AppConfig, container: &str) -> Result<String> {
+    let attempts = (config.healthcheck_wait_secs()? / HEALTH_POLL_INTERVAL_SECS).max(1);
+    let probe = match config.healthcheck_type.as_str() {
+        "http" => format!(
+            "curl -fsS -o /dev/null --max-time 2 \"http://$ip:{}\"{}",
+            config.app_port,
+            shell_quote(&config.healthcheck_path)
+        ),
+        _ => format!("timeout 2 bash -c \"</dev/tcp/$ip/{}\"", config.app_port),
+    };
+
+    Ok(format!(
+        "id={}; for i in $(seq 1 {}); do \
+[ \"$(docker inspect -f '{{{{.State.Running}}}}' \"$id\" 2>/dev/null)\" = true ] || exit 2; \
+ip=$(docker inspect -f '{{{{(index .NetworkSettings.Networks \"traefik_network\").IPAddress}}}}' \"$id\"); \
+health=$(docker inspect -f '{{{{if .State.Health}}}}{{{{.State.Health.Status}}}}{{{{end}}}}' \"$id\"); \
+[ \"$health\" = unhealthy ] && exit 3; \
+if [ -n \"$ip\" ] && {{ [ -z \"$health\" ] || [ \"$health\" = healthy ]; }} && {} >/dev/null 2>&1; then exit 0; fi; \
+sleep {}; done; exit 1",
+        container, attempts, probe, HEALTH_POLL_INTERVAL_SECS
+    ))
+}
+
+/// Builds a script that requests the public URL through Traefik on the VPS
+/// until it answers with an accepted status (exit 0) or the wait runs out
+/// (exit 1, printing the last status).
+fn public_probe_script(config: &AppConfig, url: &str) -> Result<String> {
+    let attempts = (config.healthcheck_wait_secs()? / HEALTH_POLL_INTERVAL_SECS).max(1);
+    // Any answer from the app passes a tcp check; an http check wants the
+    // same statuses as the container probe.
+    let limit = if config.healthcheck_type == "http" {
+        400
+    } else {
+        500
+    };
+    // Traefik answers for the auth and allowlist middlewares before the
+    // request reaches the app, so their refusals mean the route is live.
+    let refusals = match middleware_refusals(config).as_slice() {
+        [] => String::new(),
+        codes => format!("case \"$code\" in {}) exit 0;; esac; ", codes.join("|")),
+    };
+
+    Ok(format!(
+        "for i in $(seq 1 {}); do \
+code=$(curl -ksS -o /dev/null -w '%{{http_code}}' --max-time 5 --resolve {} {} 2>/dev/null); \
+[ \"$code\" -ge 200 ] 2>/dev/null && [ \"$code\" -lt {} ] && exit 0; \
+{}sleep {}; done; echo \"$code\"; exit 1",
+        attempts,
+        shell_quote(&format!("{}:443:127.0.0.1", url)),
+        shell_quote(&format!("https://{}{}", url, config.healthcheck_path)),
+        limit,
+        refusals,
+        HEALTH_POLL_INTERVAL_SECS
+    ))
+}
+
+/// Statuses Traefik answers with on its own when `BASIC_AUTH` or
+/// `IP_ALLOWLIST` turn the probe away. A tcp check accepts them already.
+fn middleware_refusals(config: &AppConfig) -> Vec<&'static str> {
+    let mut codes = Vec::new();
+    if config.healthcheck_type != "http" {
+        return codes;
+    }
+    if list_items(&config.middlewares.basic_auth).next().is_some() {
+        codes.push("401");
+    }
+    if list_items(&config.middlewares.ip_allowlist)
+        .next()
+        .is_some()
+    {
+        codes.push("403");
+    }
+    codes
+}
+
+fn accepted_statuses(config: &AppConfig) -> String {
+    if config.healthcheck_type != "http" {
+        return "non-5xx".to_string();
+    }
+    match middleware_refusals(config).as_slice() {
+        [] => "2xx or 3xx".to_string(),
+        codes => format!("2xx, 3xx or {}", codes.join(" or ")),
+    }
+}
+
+fn recent_logs(client: &dyn RemoteClient, command: &str) -> Result<String> {
+    let (output, _) = client.execute_command(command)?;
+    Ok(output.trim_end().to_string())
+}
+
+fn run(client: &dyn RemoteClient, command: &str) -> Result<()> {
+    let (output, status) = client.execute_command(command)?;
+    if status != 0 {
+        return Err(anyhow!("Failed to execute command {}: {}", command, output));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+
+    const APP_DIR: &str = "/opt/minion/my-app";
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            app_name: "my-app".to_string(),
+            app_port: "3000".to_string(),
+            healthcheck_type: "tcp".to_string(),
+            healthcheck_path: "/".to_string(),
+            healthcheck_wait: "60".to_string(),
+            healthcheck_interval: "10".to_string(),
+            healthcheck_timeout: "5".to_string(),
+            healthcheck_retries: "3".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_deploy_starts_compose_and_waits_for_health() {
+        let remote = FakeRemoteClient::new();
+
+        rolling_update(&remote, &app_config(), APP_DIR).unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands[0],
+            "cd /opt/minion/my-app && docker compose ps -q my-app"
+        );
+        assert_eq!(commands[1], "cd /opt/minion/my-app && docker compose up -d");
+        assert!(commands[2].starts_with(
+            "id=$(cd /opt/minion/my-app && docker compose ps -q my-app | head -n 1);"
+        ));
+        assert_eq!(commands.len(), 3);
+    }
+
+    #[test]
+    fn rolling_update_starts_candidate_then_retires_previous_container() {
+        let remote = FakeRemoteClient::new()
+            .on_command("docker compose ps -q", "old1\n", 0)
+            .on_command("docker compose ps -q", "old1\nnew1\n", 0);
+
+        rolling_update(&remote, &app_config(), APP_DIR).unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(
+            commands[1],
+            "cd /opt/minion/my-app && docker compose up -d --no-deps --no-recreate --scale my-app=2 my-app"
+        );
+        assert!(commands[3].starts_with("id=new1;"));
+        assert_eq!(commands[4], "docker stop old1 && docker rm old1");
+        assert_eq!(
+            commands[5],
+            "cd /opt/minion/my-app && docker compose up -d --no-deps --remove-orphans $(docker compose config --services)"
+        );
+        assert_eq!(commands.len(), 6);
+    }
+
+    #[test]
+    fn candidate_is_not_routable_before_the_probe_succeeds() {
+        // Traefik skips containers whose Docker health is still `starting`,
+        // so every check type needs a Docker health check...
+        let mut http = app_config();
+        http.healthcheck_type = "http".to_string();
+        for config in [app_config(), http] {
+            assert!(healthcheck_section(&config).starts_with("    healthcheck:\n"));
+        }
+
+        // ...and the probe only passes once Docker reports it healthy.
+        let script = health_probe_script(&app_config(), "new1").unwrap();
+        assert!(script.contains("[ \"$health\" = healthy ]; } && timeout 2 bash"));
+    }
+
+    #[test]
+    fn unhealthy_candidate_is_removed_and_previous_container_keeps_serving() {
+        let remote = FakeRemoteClient::new()
+            .on_command("docker compose ps -q", "old1\n", 0)
+            .on_command("docker compose ps -q", "old1\nnew1\n", 0)
+            .on_command("id=new1;", "", 1)
+            .on_command("docker logs", "boom: database unreachable\n", 0);
+
+        let error = rolling_update(&remote, &app_config(), APP_DIR).unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.contains("did not pass the tcp health check within 60s"));
+        assert!(message.contains("previous version is still serving"));
+        assert!(message.contains("boom: database unreachable"));
+
+        let commands = remote.commands();
+        assert_eq!(commands[4], "docker logs --tail 50 new1 2>&1");
+        assert_eq!(commands[5], "docker rm -f new1");
+        assert!(!commands
+            .iter()
+            .any(|command| command.contains("docker stop old1")));
+    }
+
+    #[test]
+    fn first_deploy_failure_includes_compose_logs() {
+        let remote = FakeRemoteClient::new()
+            .on_command("id=$(", "", 2)
+            .on_command("docker compose logs", "panic at startup\n", 0);
+
+        let error = rolling_update(&remote, &app_config(), APP_DIR).unwrap_err();
+
+        assert!(error.to_string().contains("exited before becoming healthy"));
+        assert!(error.to_string().contains("panic at startup"));
+    }
+
+    #[test]
+    fn http_probe_requests_configured_path_on_app_port() {
+        let mut config = app_config();
+        config.healthcheck_type = "http".to_string();
+        config.healthcheck_path = "/health".to_string();
+        config.healthcheck_wait = "10".to_string();
+
+        let script = health_probe_script(&config, "abc").unwrap();
+
+        assert!(script.starts_with("id=abc; for i in $(seq 1 5); do"));
+        assert!(script.contains("curl -fsS -o /dev/null --max-time 2 \"http://$ip:3000\"'/health'"));
+        assert!(script.contains("\"traefik_network\""));
+    }
+
+    #[test]
+    fn tcp_probe_opens_app_port() {
+        let script = health_probe_script(&app_config(), "abc").unwrap();
+
+        assert!(script.contains("timeout 2 bash -c \"</dev/tcp/$ip/3000\""));
+        assert!(script.contains("[ \"$health\" = unhealthy ] && exit 3;"));
+    }
+
+    #[test]
+    fn container_reported_unhealthy_by_docker_fails_the_rollout() {
+        let remote = FakeRemoteClient::new().on_command("id=$(", "", 3);
+
+        let error = rolling_update(&remote, &app_config(), APP_DIR).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("The new container was reported unhealthy by its Docker health check"));
+    }
+
+    #[test]
+    fn http_healthcheck_is_rendered_for_compose() {
+        let mut config = app_config();
+        config.healthcheck_type = "http".to_string();
+        config.healthcheck_path = "/health".to_string();
+
+        assert_eq!(
+            healthcheck_section(&config),
+            "    healthcheck:\n      test: [\"CMD-SHELL\", \"curl -fsS -o /dev/null 'http://localhost:3000/health' || wget -q -O /dev/null 'http://localhost:3000/health'\"]\n      interval: 10s\n      timeout: 5s\n      retries: 3\n      start_period: 60s\n"
+        );
+    }
+
+    #[test]
+    fn tcp_healthcheck_is_rendered_for_compose() {
+        assert_eq!(
+            healthcheck_section(&app_config()),
+            "    healthcheck:\n      test: [\"CMD-SHELL\", \"nc -z localhost 3000 || bash -c '</dev/tcp/localhost/3000'\"]\n      interval: 10s\n      timeout: 5s\n      retries: 3\n      start_period: 60s\n"
+        );
+    }
+
+    #[test]
+    fn public_url_is_requested_through_traefik_on_the_vps() {
+        let remote = FakeRemoteClient::new();
+
+        wait_public_url(&remote, &app_config(), APP_DIR, "app.example.com").unwrap();
+
+        let commands = remote.commands();
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].starts_with("for i in $(seq 1 30); do"));
+        assert!(commands[0]
+            .contains("--resolve 'app.example.com:443:127.0.0.1' 'https://app.example.com/'"));
+        assert!(commands[0].contains("[ \"$code\" -lt 500 ]"));
+    }
+
+    #[test]
+    fn unreachable_public_url_fails_with_logs() {
+        let remote = FakeRemoteClient::new()
+            .on_command("for i in", "000\n", 1)
+            .on_command("docker compose logs", "listening on 127.0.0.1:3000\n", 0);
+
+        let error = wait_public_url(&remote, &app_config(), APP_DIR, "app.example.com")
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.starts_with(
+            "https://app.example.com/ did not respond with a non-5xx status within 60s (last status: 000)"
+        ));
+        assert!(error.contains("listening on 127.0.0.1:3000"));
+    }
+}
@@ -0,0 +1,234 @@
+//! Extra Traefik routers for the web service, set with `[routes.<name>]`.
+//!
+//! Each route gets a router and a service named `<app>-<route>`, so one
+//! container can answer a path prefix on another port or expose a second
+//! port, such as a websocket or metrics endpoint. Once a container defines
+//! several services Traefik no longer picks one for a router on its own, so
+//! the app's main router then names its service explicitly.
+//!
+//! Dashes are allowed in app names, so app `shop` with route `api` and app
+//! `shop-api` would both define `shop-api`, which Traefik merges without a
+//! word. Deploys check the other apps' compose files on the VPS for the
+//! routers and services the app is about to define.
+
+use anyhow::{anyhow, Result};
+
+use super::deploy::{host_rules, tls_labels};
+use super::processes;
+use crate::utils::{AppConfig, RemoteClient, RouteConfig};
+
+/// Names of the routers in front of the web service: the app's own router,
+/// followed by one per route.
+pub(super) fn routers(config: &AppConfig) -> Vec<String> {
+    std::iter::once(config.app_name.clone())
+        .chain(config.routes.iter().map(|route| router(config, route)))
+        .collect()
+}
+
+/// Labels for the routes' routers and services, or an empty string without
+/// routes.
+pub(super) fn labels(config: &AppConfig) -> String {
+    if config.routes.is_empty() {
+        return String::new();
+    }
+
+    let mut labels = format!(
+        "      - \"traefik.http.routers.{app}.service={app}\"\n",
+        app = config.app_name
+    );
+    for route in &config.routes {
+        let router = router(config, route);
+        let hosts = route.hosts(config);
+        labels.push_str(&format!(
+            "      - \"traefik.http.routers.{router}.rule={}\"\n      - \"traefik.http.routers.{router}.entrypoints=websecure\"\n",
+            rule(route, &hosts),
+            router = router
+        ));
+        if !route.priority.is_empty() {
+            labels.push_str(&format!(
+                "      - \"traefik.http.routers.{}.priority={}\"\n",
+                router, route.priority
+            ));
+        }
+        labels.push_str(&tls_labels(config, &router, &hosts));
+        labels.push_str(&format!(
+            "      - \"traefik.http.routers.{router}.service={router}\"\n      - \"traefik.http.services.{router}.loadbalancer.server.port={}\"\n",
+            if route.port.is_empty() {
+                &config.app_port
+            } else {
+                &route.port
+            },
+            router = router
+        ));
+    }
+    labels
+}
+
+/// Fails when another app on the VPS already defines a router or service
+/// named like one of the app's own, including those of routed processes.
+pub(super) fn ensure_unique(client: &dyn RemoteClient, config: &AppConfig) -> Result<()> {
+    let names = routers(config)
+        .into_iter()
+        .chain(
+            config
+                .processes
+                .iter()
+                .filter(|process| process.is_routed())
+                .map(|process| processes::router(config, process)),
+        )
+        .collect::<Vec<_>>();
+
+    let (output, _) = client.execute_command(
+        "grep -H -o -E 'traefik[.]http[.](routers|services)[.][^.]+' /opt/minion/*/docker-compose.yml 2>/dev/null || true",
+    )?;
+    for line in output.lines() {
+        let Some((file, label)) = line.split_once(':') else {
+            continue;
+        };
+        let Some((kind, name)) = label
+            .strip_prefix("traefik.http.")
+            .and_then(|label| label.split_once('.'))
+        else {
+            continue;
+        };
+        let app = file
+            .strip_prefix("/opt/minion/")
+            .and_then(|rest| rest.strip_suffix("/docker-compose.yml"))
+            .unwrap_or(file);
+        if app != config.app_name && names.iter().any(|own| own == name) {
+            let kind = if kind == "services" {
+                "Service"
+            } else {
+                "Router"
+            };
+            return Err(anyhow!(
+                "{} {} is already defined by {} on the VPS. Rename one of the apps or the route.",
+                kind,
+                name,
+                app
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn router(config: &AppConfig, route: &RouteConfig) -> String {
+    format!("{}-{}", config.app_name, route.name)
+}
+
+fn rule(route: &RouteConfig, hosts: &[&str]) -> String {
+    let hosts_rule = host_rules(hosts);
+    if route.path.is_empty() {
+        return hosts_rule;
+    }
+
+    let path_rule = format!("PathPrefix(`{}`)", route.path);
+    if hosts.len() > 1 {
+        format!("({}) && {}", hosts_rule, path_rule)
+    } else {
+        format!("{} && {}", hosts_rule, path_rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+    use crate::utils::ProcessConfig;
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            app_name: "my-app".to_string(),
+            app_url: "example.com,www.example.com".to_string(),
+            app_port: "3000".to_string(),
+            cert_resolver: "letsencrypt".to_string(),
+            routes: vec![
+                RouteConfig {
+                    name: "api".to_string(),
+                    path: "/api".to_string(),
+                    port: "4000".to_string(),
+                    priority: "10".to_string(),
+                    ..Default::default()
+                },
+                RouteConfig {
+                    name: "metrics".to_string(),
+                    host: "metrics.example.com".to_string(),
+                    port: "9090".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn routes_get_their_own_router_and_service() {
+        assert_eq!(
+            labels(&app_config()).lines().map(str::trim).collect::<Vec<_>>(),
+            vec![
+                "- \"traefik.http.routers.my-app.service=my-app\"",
+                "- \"traefik.http.routers.my-app-api.rule=(Host(`example.com`) || Host(`www.example.com`)) && PathPrefix(`/api`)\"",
+                "- \"traefik.http.routers.my-app-api.entrypoints=websecure\"",
+                "- \"traefik.http.routers.my-app-api.priority=10\"",
+                "- \"traefik.http.routers.my-app-api.tls.certresolver=letsencrypt\"",
+                "- \"traefik.http.routers.my-app-api.service=my-app-api\"",
+                "- \"traefik.http.services.my-app-api.loadbalancer.server.port=4000\"",
+                "- \"traefik.http.routers.my-app-metrics.rule=Host(`metrics.example.com`)\"",
+                "- \"traefik.http.routers.my-app-metrics.entrypoints=websecure\"",
+                "- \"traefik.http.routers.my-app-metrics.tls.certresolver=letsencrypt\"",
+                "- \"traefik.http.routers.my-app-metrics.service=my-app-metrics\"",
+                "- \"traefik.http.services.my-app-metrics.loadbalancer.server.port=9090\"",
+            ]
+        );
+        assert_eq!(
+            routers(&app_config()),
+            vec!["my-app", "my-app-api", "my-app-metrics"]
+        );
+    }
+
+    #[test]
+    fn no_routes_leave_the_main_router_alone() {
+        let mut config = app_config();
+        config.routes.clear();
+
+        assert_eq!(labels(&config), "");
+    }
+
+    #[test]
+    fn routers_must_not_clash_with_other_apps() {
+        let mut config = app_config();
+        config.processes = vec![ProcessConfig {
+            name: "admin".to_string(),
+            url: "admin.example.com".to_string(),
+            ..Default::default()
+        }];
+
+        let remote = FakeRemoteClient::with_responses(vec![(
+            "/opt/minion/my-app/docker-compose.yml:traefik.http.routers.my-app-api\n\
+/opt/minion/blog/docker-compose.yml:traefik.http.routers.blog\n\
+/opt/minion/blog/docker-compose.yml:traefik.http.services.blog\n",
+            0,
+        )]);
+        ensure_unique(&remote, &config).unwrap();
+        assert!(remote.commands()[0].contains("traefik[.]http[.](routers|services)"));
+
+        let remote = FakeRemoteClient::with_responses(vec![(
+            "/opt/minion/my-app-api/docker-compose.yml:traefik.http.routers.my-app-api\n",
+            0,
+        )]);
+        assert_eq!(
+            ensure_unique(&remote, &config).unwrap_err().to_string(),
+            "Router my-app-api is already defined by my-app-api on the VPS. Rename one of the apps or the route."
+        );
+
+        let remote = FakeRemoteClient::with_responses(vec![(
+            "/opt/minion/legacy/docker-compose.yml:traefik.http.services.my-app-admin\n",
+            0,
+        )]);
+        assert_eq!(
+            ensure_unique(&remote, &config).unwrap_err().to_string(),
+            "Service my-app-admin is already defined by legacy on the VPS. Rename one of the apps or the route."
+        );
+    }
+}
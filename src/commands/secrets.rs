@@ -0,0 +1,328 @@
+//! Encrypted app secrets committed next to the project.
+//!
+//! `.minion.secrets` is a dotenv file encrypted with sops for one or more age
+//! recipients. Deploys decrypt it, locally by default or on the VPS with
+//! `SECRETS_DECRYPT=remote`, into `/opt/minion/<app>/secrets.env`, which the
+//! compose file loads after `app.env`.
+
+use anyhow::{anyhow, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::env::write_private_env;
+use crate::utils::{
+    shell_quote, AppConfig, AppConfigOverrides, CommandExecutor, EnvFile, LocalCommandRunner,
+    RemoteClient,
+};
+
+const SOPS_CONFIG_FILE: &str = ".sops.yaml";
+const SOPS_DOTENV_ARGS: [&str; 4] = ["--input-type", "dotenv", "--output-type", "dotenv"];
+/// sops exits with this status when an edit leaves the file unchanged.
+const SOPS_FILE_UNCHANGED: i32 = 200;
+
+pub struct SecretsCommand;
+
+#[derive(Debug, Clone)]
+pub enum SecretsAction {
+    Edit,
+}
+
+/// Secrets prepared locally before a deploy touches the VPS.
+pub(super) enum Secrets {
+    /// The project has no secrets file.
+    None,
+    /// Decrypted on this machine.
+    Decrypted(EnvFile),
+    /// Left encrypted for the VPS to decrypt with its own age key.
+    Encrypted(String),
+}
+
+impl SecretsCommand {
+    pub fn new() -> Self {
+        SecretsCommand
+    }
+
+    pub fn execute(&self, action: SecretsAction, overrides: AppConfigOverrides) -> Result<()> {
+        let config = AppConfig::load(overrides, false, false)?;
+
+        match action {
+            SecretsAction::Edit => self.edit(&config),
+        }
+    }
+
+    fn edit(&self, config: &AppConfig) -> Result<()> {
+        if !Path::new(SOPS_CONFIG_FILE).exists() {
+            let recipient = local_recipient()?;
+            fs::write(SOPS_CONFIG_FILE, sops_config(&recipient))?;
+            println!(
+                "✓ Created {} for age recipient {}",
+                SOPS_CONFIG_FILE, recipient
+            );
+        }
+
+        let status = Command::new("sops")
+            .args(SOPS_DOTENV_ARGS)
+            .arg(&config.secrets_file)
+            .status()
+            .map_err(|error| anyhow!("Failed to run sops: {}. Is sops installed?", error))?;
+
+        match status.code() {
+            Some(0) => println!("✓ Saved {}", config.secrets_file),
+            Some(SOPS_FILE_UNCHANGED) => println!("{} is unchanged", config.secrets_file),
+            _ => return Err(anyhow!("sops exited with {}", status)),
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves the project's secrets file. With local decryption the file is
+/// decrypted and validated here, so a bad key fails the deploy before any
+/// image is built.
+pub(super) fn prepare_secrets(
+    runner: &dyn LocalCommandRunner,
+    config: &AppConfig,
+) -> Result<Secrets> {
+    if !Path::new(&config.secrets_file).exists() {
+        return Ok(Secrets::None);
+    }
+
+    if config.secrets_decrypt == "remote" {
+        return Ok(Secrets::Encrypted(config.secrets_file.clone()));
+    }
+
+    println!("Decrypting {}...", config.secrets_file);
+    let key_file = default_age_key_path().map(|path| path.to_string_lossy().to_string());
+    let env = match (&config.age_key, &key_file) {
+        (Some(key), _) => vec![("SOPS_AGE_KEY", key.as_str())],
+        (None, Some(path)) if Path::new(path).exists() => {
+            vec![("SOPS_AGE_KEY_FILE", path.as_str())]
+        }
+        _ => Vec::new(),
+    };
+
+    let mut args = SOPS_DOTENV_ARGS.to_vec();
+    args.extend(["--decrypt", config.secrets_file.as_str()]);
+    let (stdout, stderr, status) =
+        runner
+            .execute_with_env("sops", &args, &env)
+            .map_err(|error| {
+                anyhow!(
+                    "sops is required to decrypt {}: {}",
+                    config.secrets_file,
+                    error
+                )
+            })?;
+    if status != 0 {
+        return Err(anyhow!(
+            "Failed to decrypt {}: {}",
+            config.secrets_file,
+            stderr.trim()
+        ));
+    }
+
+    let secrets =
+        EnvFile::parse(&stdout).map_err(|error| anyhow!("{}: {}", config.secrets_file, error))?;
+    println!("✓ Decrypted {} secrets", secrets.iter().count());
+    Ok(Secrets::Decrypted(secrets))
+}
+
+/// Writes the app's `secrets.env`. Without a secrets file it is emptied, so
+/// removed secrets do not outlive the file they came from.
+pub(super) fn install_secrets(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    app_dir: &str,
+    secrets: &Secrets,
+) -> Result<()> {
+    let path = secrets_env_path(app_dir);
+    match secrets {
+        Secrets::None => write_private_env(client, config, &path, &EnvFile::default()),
+        Secrets::Decrypted(env_file) => {
+            println!("Writing secrets to VPS...");
+            write_private_env(client, config, &path, env_file)
+        }
+        Secrets::Encrypted(local_path) => {
+            println!("Decrypting secrets on VPS...");
+            let encrypted = format!("{}/secrets.enc.env", app_dir);
+            client.copy_file(local_path, &encrypted)?;
+
+            let staged = format!("{}.new", path);
+            let command = format!(
+                "umask 077 && (sops {} --decrypt {} > {} && mv {} {}); status=$?; rm -f {} {}; exit $status",
+                SOPS_DOTENV_ARGS.join(" "),
+                shell_quote(&encrypted),
+                shell_quote(&staged),
+                shell_quote(&staged),
+                shell_quote(&path),
+                shell_quote(&encrypted),
+                shell_quote(&staged)
+            );
+            let (output, status) = client.execute_command(&command)?;
+            if status != 0 {
+                return Err(anyhow!(
+                    "Failed to decrypt secrets on the VPS. Make sure sops is installed and the minion user has an age key in ~/.config/sops/age/keys.txt: {}",
+                    output.trim()
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+pub(super) fn secrets_env_path(app_dir: &str) -> String {
+    format!("{}/secrets.env", app_dir)
+}
+
+/// Location sops reads age identities from, honouring `SOPS_AGE_KEY_FILE`.
+pub(super) fn default_age_key_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("SOPS_AGE_KEY_FILE") {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("sops").join("age").join("keys.txt"))
+}
+
+fn local_recipient() -> Result<String> {
+    let key_path = default_age_key_path()
+        .filter(|path| path.exists())
+        .ok_or_else(|| anyhow!("No age key found. Run `minion setup` or `age-keygen -o ~/.config/sops/age/keys.txt` first."))?;
+    let key_path = key_path.to_string_lossy();
+
+    let (output, status) = CommandExecutor::new()
+        .execute("age-keygen", &["-y", &key_path])
+        .map_err(|error| anyhow!("Failed to run age-keygen: {}. Is age installed?", error))?;
+    let recipient = output.lines().next().unwrap_or_default().trim();
+    if status != 0 || !recipient.starts_with("age1") {
+        return Err(anyhow!(
+            "Failed to read the public key from {}: {}",
+            key_path,
+            output.trim()
+        ));
+    }
+
+    Ok(recipient.to_string())
+}
+
+fn sops_config(recipient: &str) -> String {
+    format!(
+        "creation_rules:\n  - path_regex: \\.minion(\\.[^/]+)?\\.secrets$\n    age: {}\n",
+        recipient
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::{FakeLocalCommandRunner, FakeRemoteClient};
+
+    fn app_config(secrets_file: &Path) -> AppConfig {
+        AppConfig {
+            app_name: "my-app".to_string(),
+            secrets_file: secrets_file.to_string_lossy().to_string(),
+            secrets_decrypt: "local".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn missing_secrets_file_runs_nothing_and_empties_remote_file() {
+        let runner = FakeLocalCommandRunner::new();
+        let remote = FakeRemoteClient::new();
+        let config = app_config(Path::new("/nonexistent/.minion.secrets"));
+
+        let secrets = prepare_secrets(&runner, &config).unwrap();
+        install_secrets(&remote, &config, "/opt/minion/my-app", &secrets).unwrap();
+
+        assert!(runner.commands().is_empty());
+        let commands = remote.commands();
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].contains("cat > '/opt/minion/my-app/secrets.env'"));
+        assert!(commands[0].ends_with("\nMINION_ENV"));
+    }
+
+    #[test]
+    fn local_decryption_passes_age_key_to_sops_and_writes_private_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let runner = FakeLocalCommandRunner::with_responses(vec![("API_TOKEN=s3cr3t\n", 0)]);
+        let remote = FakeRemoteClient::new();
+        let mut config = app_config(file.path());
+        config.age_key = Some("AGE-SECRET-KEY-1TEST".to_string());
+
+        let secrets = prepare_secrets(&runner, &config).unwrap();
+        install_secrets(&remote, &config, "/opt/minion/my-app", &secrets).unwrap();
+
+        let sops = &runner.commands()[0];
+        assert_eq!(sops.command, "sops");
+        assert_eq!(
+            sops.args[..5],
+            [
+                "--input-type",
+                "dotenv",
+                "--output-type",
+                "dotenv",
+                "--decrypt"
+            ]
+        );
+        assert_eq!(
+            sops.env,
+            vec![(
+                "SOPS_AGE_KEY".to_string(),
+                "AGE-SECRET-KEY-1TEST".to_string()
+            )]
+        );
+        let write = &remote.commands()[0];
+        assert!(write.contains("umask 077 && cat > '/opt/minion/my-app/secrets.env'"));
+        assert!(write.contains("chmod 600 '/opt/minion/my-app/secrets.env'"));
+        assert!(write.ends_with("\nAPI_TOKEN='s3cr3t'\nMINION_ENV"));
+    }
+
+    #[test]
+    fn failed_local_decryption_is_reported() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let runner = FakeLocalCommandRunner::with_responses(vec![("", 128)]);
+
+        let error = match prepare_secrets(&runner, &app_config(file.path())) {
+            Err(error) => error,
+            Ok(_) => panic!("decryption should fail"),
+        };
+
+        assert!(error.to_string().starts_with("Failed to decrypt"));
+    }
+
+    #[test]
+    fn remote_decryption_uploads_encrypted_file_and_runs_sops_on_vps() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let runner = FakeLocalCommandRunner::new();
+        let remote = FakeRemoteClient::new();
+        let mut config = app_config(file.path());
+        config.secrets_decrypt = "remote".to_string();
+
+        let secrets = prepare_secrets(&runner, &config).unwrap();
+        install_secrets(&remote, &config, "/opt/minion/my-app", &secrets).unwrap();
+
+        assert!(runner.commands().is_empty());
+        assert_eq!(
+            remote.copied_files()[0].1,
+            "/opt/minion/my-app/secrets.enc.env"
+        );
+        let command = &remote.commands()[0];
+        assert!(command.starts_with(
+            "umask 077 && (sops --input-type dotenv --output-type dotenv --decrypt '/opt/minion/my-app/secrets.enc.env' > '/opt/minion/my-app/secrets.env.new'"
+        ));
+        assert!(command.contains("rm -f '/opt/minion/my-app/secrets.enc.env'"));
+    }
+
+    #[test]
+    fn sops_config_covers_per_environment_secrets_files() {
+        let config = sops_config("age1example");
+
+        assert!(config.contains("path_regex: \\.minion(\\.[^/]+)?\\.secrets$"));
+        assert!(config.ends_with("    age: age1example\n"));
+    }
+}
@@ -1,6 +1,6 @@
 use std::io::{self, Write};
-use crate::utils::{SshClient, CommandExecutor};
-use std::path::Path;
+use super::secrets::default_age_key_path;
+use crate::utils::{SshClient, CommandExecutor, RemoteClient, DnsProvider, EnvFile, Manifest, dns_resolvers_config, select_environment};
 
 // Include the resource files at compile time
 const TRAEFIK_DOCKER_COMPOSE: &str = include_str!("../resources/docker-compose.traefik.yml");
@@ -13,45 +13,54 @@ impl SetupCommand {
         SetupCommand
     }
 
-    fn check_dependencies() -> Result<(), Box<dyn std::error::Error>> {
+    /// Checks local tools. Returns whether sops and age are available, which
+    /// is only needed for encrypted `.minion.secrets` files.
+    fn check_dependencies() -> Result<bool, Box<dyn std::error::Error>> {
         let cmd = CommandExecutor::new();
 
-        // TODO: Add sops and age support
-
-        // // Check if sops is installed
-        // match cmd.execute("sops", &["--version"]) {
-        //     Ok(_) => println!("✓ sops is installed"),
-        //     Err(_) => return Err("sops is not installed. Please install it first.".into()),
-        // }
-
-        // // Check if age is installed
-        // match cmd.execute("age", &["--version"]) {
-        //     Ok(_) => println!("✓ age is installed"),
-        //     Err(_) => return Err("age is not installed. Please install it first.".into()),
-        // }
-
         // Check if docker is installed
         match cmd.execute("docker", &["--version"]) {
             Ok(_) => println!("✓ docker is installed"),
             Err(_) => return Err("docker is not installed. Please install it first.".into()),
         }
 
-        Ok(())
+        // sops and age are optional; without them encrypted secrets are unavailable
+        let mut secrets_tools = true;
+        for tool in ["sops", "age"] {
+            match cmd.execute(tool, &["--version"]) {
+                Ok(_) => println!("✓ {} is installed", tool),
+                Err(_) => {
+                    println!("! {} is not installed; encrypted secrets (.minion.secrets) will be unavailable", tool);
+                    secrets_tools = false;
+                }
+            }
+        }
+
+        Ok(secrets_tools)
     }
 
-    fn _setup_keys() -> Result<(), Box<dyn std::error::Error>> {
-        let home = std::env::var("HOME")?;
-        let key_path = format!("{}/.config/sops/age/keys.txt", home);
+    fn setup_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let key_path = default_age_key_path().ok_or("Could not determine the sops age key location")?;
+        let key_path_str = key_path.to_string_lossy().to_string();
 
-        if !Path::new(&key_path).exists() {
+        if !key_path.exists() {
             println!("Generating age key pair...");
-            std::fs::create_dir_all(format!("{}/.config/sops/age", home))?;
-            let (output, _) = CommandExecutor::new().execute("age-keygen", &["-o", &key_path])?;
-            println!("✓ Generated age key pair: {}", output);
+            if let Some(parent) = key_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let (output, status) = CommandExecutor::new().execute("age-keygen", &["-o", &key_path_str])?;
+            if status != 0 {
+                return Err(format!("Failed to generate age key: {}", output).into());
+            }
+            println!("✓ Generated age key pair at {}", key_path_str);
         } else {
-            println!("✓ Age key pair already exists");
+            println!("✓ Age key pair already exists at {}", key_path_str);
         }
 
+        let (public_key, _) = CommandExecutor::new().execute("age-keygen", &["-y", &key_path_str])?;
+        println!("✓ Age public key: {}", public_key.trim());
+        println!("  Keep {} private; CI can pass its contents as MINION_AGE_KEY.", key_path_str);
+
         Ok(())
     }
 
@@ -98,7 +107,12 @@ impl SetupCommand {
         Ok(())
     }
 
-    fn setup_traefik(client: &SshClient, email: &str) -> Result<(), Box<dyn std::error::Error>> {
+    fn setup_traefik(
+        client: &SshClient,
+        email: &str,
+        providers: &[DnsProvider],
+        dns_env: &EnvFile,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         // Setup Traefik
         println!("Setting up Traefik...");
 
@@ -118,7 +132,9 @@ impl SetupCommand {
         }
         println!("✓ Docker permissions verified");
 
-        let traefik_config = TRAEFIK_CONFIG_TEMPLATE.replace("{{email}}", email);
+        let traefik_config = TRAEFIK_CONFIG_TEMPLATE
+            .replace("{{email}}", email)
+            .replace("{{dns_resolvers}}", &dns_resolvers_config(providers, email));
 
         let traefik_commands = [
             // Create directory structure
@@ -143,6 +159,15 @@ impl SetupCommand {
             }
         }
 
+        // DNS provider credentials, read by docker compose as the minion user
+        let write_dns_env = "sudo sh -c 'umask 077 && cat > /opt/traefik/dns.env' && sudo chown minion:minion /opt/traefik/dns.env";
+        let (output, status) =
+            client.execute_with_stdin(write_dns_env, &mut dns_env.render().as_bytes())?;
+        if status != 0 {
+            println!("Error: {}", output);
+            return Err("Failed to write the DNS provider credentials".into());
+        }
+
         // Verify files exist
         println!("Verifying configuration files...");
         let (ls_output, ls_status) = client.execute_command("ls -l /opt/traefik/config/traefik.yml /opt/traefik/docker-compose.yml")?;
@@ -214,13 +239,24 @@ impl SetupCommand {
         Ok(())
     }
 
-    fn load_args() -> Result<(String, String), Box<dyn std::error::Error>> {
-        print!("Enter VPS hostname or IP address: ");
+    /// Prompts for the VPS and the certificate email. The host defaults to the
+    /// one `minion.toml` sets for `environment`, if any.
+    fn load_args(environment: Option<&str>) -> Result<(String, String), Box<dyn std::error::Error>> {
+        let default_host = Manifest::open_project()?
+            .values_for(environment)?
+            .remove("VPS_HOST");
+        match &default_host {
+            Some(default_host) => print!("Enter VPS hostname or IP address [{}]: ", default_host),
+            None => print!("Enter VPS hostname or IP address: "),
+        }
         io::stdout().flush()?;
 
         let mut input_host = String::new();
         io::stdin().read_line(&mut input_host)?;
-        let host = input_host.trim().to_string();
+        let host = match (input_host.trim(), default_host) {
+            ("", Some(default_host)) => default_host,
+            (input, _) => input.to_string(),
+        };
 
         print!("Enter email address for SSL certificates: ");
         io::stdout().flush()?;
@@ -232,12 +268,70 @@ impl SetupCommand {
         Ok((host, email))
     }
 
-    pub fn execute(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Asks which DNS providers get a DNS-01 resolver and collects their
+    /// credentials, taking any already set in the local environment.
+    fn load_dns_providers() -> Result<(Vec<DnsProvider>, EnvFile), Box<dyn std::error::Error>> {
+        print!("Enter DNS providers for DNS-01 certificates (cloudflare, route53, rfc2136; comma-separated, empty for none): ");
+        io::stdout().flush()?;
+
+        let mut input_providers = String::new();
+        io::stdin().read_line(&mut input_providers)?;
+        let mut providers = Vec::new();
+        for name in input_providers
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let provider = DnsProvider::parse(name)?;
+            if !providers.contains(&provider) {
+                providers.push(provider);
+            }
+        }
+
+        let mut dns_env = EnvFile::default();
+        for credential in providers.iter().flat_map(|provider| provider.credentials()) {
+            if let Ok(value) = std::env::var(credential.key) {
+                println!("✓ Using {} from the environment", credential.key);
+                dns_env.set(credential.key.to_string(), value);
+                continue;
+            }
+
+            let optional = if credential.required {
+                ""
+            } else {
+                ", optional"
+            };
+            print!(
+                "Enter {} ({}{}): ",
+                credential.key, credential.description, optional
+            );
+            io::stdout().flush()?;
+
+            let mut input_value = String::new();
+            io::stdin().read_line(&mut input_value)?;
+            let value = input_value.trim().to_string();
+            if value.is_empty() {
+                if credential.required {
+                    return Err(format!("{} is required", credential.key).into());
+                }
+                continue;
+            }
+            dns_env.set(credential.key.to_string(), value);
+        }
+
+        Ok((providers, dns_env))
+    }
+
+    pub fn execute(&self, environment: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+        let environment = select_environment(environment)?;
+
         // Check local dependencies first
-        Self::check_dependencies()?;
-        //Self::setup_keys()?; // TODO: Enable once sops and age are supported.
+        if Self::check_dependencies()? {
+            Self::setup_keys()?;
+        }
 
-        let (host, email) = Self::load_args()?;
+        let (host, email) = Self::load_args(environment.as_deref())?;
+        let (providers, dns_env) = Self::load_dns_providers()?;
 
         println!("Testing SSH connection to {}...", host);
 
@@ -255,7 +349,7 @@ impl SetupCommand {
 
         // Reconnect and setup Traefik
         let client = SshClient::connect(&host, "minion", None)?;
-        Self::setup_traefik(&client, &email)?;
+        Self::setup_traefik(&client, &email, &providers, &dns_env)?;
 
         println!("✓ SSH connection established!");
         println!("✓ Initialization complete!");
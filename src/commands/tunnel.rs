@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+
+use super::addons;
+use crate::utils::{
+    shell_quote, AppConfig, AppConfigOverrides, PortForward, RemoteClient, SshClient,
+};
+
+pub struct TunnelCommand;
+
+/// `<local-port>:<container>:<port>`, or `<container>:<port>` to use the same
+/// port locally.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TunnelSpec {
+    local_port: u16,
+    container: String,
+    port: u16,
+}
+
+impl TunnelCommand {
+    pub fn new() -> Self {
+        TunnelCommand
+    }
+
+    pub fn execute(&self, specs: Vec<String>, overrides: AppConfigOverrides) -> Result<()> {
+        let specs = specs
+            .iter()
+            .map(|spec| parse_spec(spec))
+            .collect::<Result<Vec<_>>>()?;
+        let config = AppConfig::load(overrides, false, false)?;
+        config.require_app_control()?;
+
+        println!("Connecting to {} as {}...", config.host, config.ssh_user);
+        let client =
+            SshClient::connect_with_auth(&config.host, &config.ssh_user, &config.ssh_auth())?;
+
+        self.open(&specs, &config, &client)
+    }
+
+    fn open(
+        &self,
+        specs: &[TunnelSpec],
+        config: &AppConfig,
+        client: &dyn RemoteClient,
+    ) -> Result<()> {
+        let forwards = specs
+            .iter()
+            .map(|spec| resolve(client, config, spec))
+            .collect::<Result<Vec<_>>>()?;
+
+        for forward in &forwards {
+            println!(
+                "Forwarding localhost:{} to {}:{} ({})",
+                forward.local_port, forward.name, forward.port, forward.host
+            );
+        }
+        println!("Press Ctrl-C to stop");
+
+        client.forward_ports(&forwards)
+    }
+}
+
+fn parse_spec(spec: &str) -> Result<TunnelSpec> {
+    let parts = spec.split(':').collect::<Vec<_>>();
+    let (local_port, container, port) = match parts.as_slice() {
+        [local_port, container, port] => (*local_port, *container, *port),
+        [container, port] => (*port, *container, *port),
+        _ => {
+            return Err(anyhow!(
+                "Invalid tunnel {}. Use <local-port>:<container>:<port>",
+                spec
+            ))
+        }
+    };
+
+    let port_number = |value: &str| {
+        value
+            .parse::<u16>()
+            .ok()
+            .filter(|port| *port > 0)
+            .ok_or_else(|| anyhow!("Invalid port {} in tunnel {}", value, spec))
+    };
+    if container.is_empty() {
+        return Err(anyhow!("Missing container name in tunnel {}", spec));
+    }
+
+    Ok(TunnelSpec {
+        local_port: port_number(local_port)?,
+        container: container.to_string(),
+        port: port_number(port)?,
+    })
+}
+
+/// Looks up the container's address on `traefik_network`. `web`, the app's
+/// process names and add-ons such as `postgres` refer to the app's own
+/// containers; anything else is taken as a Docker container name.
+fn resolve(
+    client: &dyn RemoteClient,
+    config: &AppConfig,
+    spec: &TunnelSpec,
+) -> Result<PortForward> {
+    let service = if spec.container == "web" {
+        Some(config.app_name.as_str())
+    } else {
+        config
+            .processes
+            .iter()
+            .find(|process| process.name == spec.container)
+            .map(|process| process.name.as_str())
+            .or_else(|| addons::is_addon(&spec.container).then_some(spec.container.as_str()))
+    };
+    let container = match service {
+        Some(service) => format!(
+            "$(cd {} && docker compose ps -q {} | head -n 1)",
+            shell_quote(&format!("/opt/minion/{}", config.app_name)),
+            service
+        ),
+        None => shell_quote(&spec.container),
+    };
+
+    let (output, status) = client.execute_command(&format!(
+        "docker inspect -f '{{{{(index .NetworkSettings.Networks \"traefik_network\").IPAddress}}}}' {} 2>&1",
+        container
+    ))?;
+    let ip = output.trim();
+    if status != 0 || ip.is_empty() || ip.contains(char::is_whitespace) {
+        return Err(anyhow!(
+            "Could not find a running {} container on traefik_network. {}",
+            spec.container,
+            ip
+        ));
+    }
+
+    Ok(PortForward {
+        local_port: spec.local_port,
+        name: spec.container.clone(),
+        host: ip.to_string(),
+        port: spec.port,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::FakeRemoteClient;
+    use crate::utils::ProcessConfig;
+
+    fn app_config() -> AppConfig {
+        AppConfig {
+            host: "example.com".to_string(),
+            app_name: "my-app".to_string(),
+            processes: vec![ProcessConfig {
+                name: "admin".to_string(),
+                command: "./admin".to_string(),
+                replicas: "1".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn specs_accept_an_optional_local_port() {
+        assert_eq!(
+            parse_spec("15432:postgres:5432").unwrap(),
+            TunnelSpec {
+                local_port: 15432,
+                container: "postgres".to_string(),
+                port: 5432,
+            }
+        );
+        assert_eq!(parse_spec("redis:6379").unwrap().local_port, 6379);
+
+        for (spec, expected) in [
+            ("5432", "Invalid tunnel 5432"),
+            ("0:postgres:5432", "Invalid port 0"),
+            ("5432:postgres:db", "Invalid port db"),
+            ("5432::5432", "Missing container name"),
+        ] {
+            let error = parse_spec(spec).unwrap_err().to_string();
+            assert!(error.starts_with(expected), "{}", error);
+        }
+    }
+
+    #[test]
+    fn containers_are_resolved_on_traefik_network_before_forwarding() {
+        let remote =
+            FakeRemoteClient::with_responses(vec![("172.18.0.5\n", 0), ("172.18.0.7\n", 0)]);
+
+        TunnelCommand::new()
+            .open(
+                &[
+                    parse_spec("5432:shared-db:5432").unwrap(),
+                    parse_spec("9000:admin:9000").unwrap(),
+                ],
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        assert_eq!(
+            remote.commands(),
+            vec![
+                "docker inspect -f '{{(index .NetworkSettings.Networks \"traefik_network\").IPAddress}}' 'shared-db' 2>&1",
+                "docker inspect -f '{{(index .NetworkSettings.Networks \"traefik_network\").IPAddress}}' $(cd '/opt/minion/my-app' && docker compose ps -q admin | head -n 1) 2>&1",
+            ]
+        );
+        assert_eq!(
+            remote.forwards(),
+            vec![
+                PortForward {
+                    local_port: 5432,
+                    name: "shared-db".to_string(),
+                    host: "172.18.0.5".to_string(),
+                    port: 5432,
+                },
+                PortForward {
+                    local_port: 9000,
+                    name: "admin".to_string(),
+                    host: "172.18.0.7".to_string(),
+                    port: 9000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn addons_are_resolved_through_the_app_project() {
+        let remote = FakeRemoteClient::with_responses(vec![("172.18.0.9\n", 0)]);
+
+        TunnelCommand::new()
+            .open(
+                &[parse_spec("5432:postgres:5432").unwrap()],
+                &app_config(),
+                &remote,
+            )
+            .unwrap();
+
+        assert_eq!(
+            remote.commands(),
+            vec![
+                "docker inspect -f '{{(index .NetworkSettings.Networks \"traefik_network\").IPAddress}}' $(cd '/opt/minion/my-app' && docker compose ps -q postgres | head -n 1) 2>&1",
+            ]
+        );
+        assert_eq!(remote.forwards()[0].host, "172.18.0.9");
+    }
+
+    #[test]
+    fn missing_container_fails_before_forwarding() {
+        let remote =
+            FakeRemoteClient::with_responses(vec![("Error: No such object: shared-db\n", 1)]);
+
+        let error = TunnelCommand::new()
+            .open(
+                &[parse_spec("shared-db:5432").unwrap()],
+                &app_config(),
+                &remote,
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Could not find a running shared-db container on traefik_network. Error: No such object: shared-db"
+        );
+        assert!(remote.forwards().is_empty());
+    }
+}
@@ -3,7 +3,10 @@ mod commands;
 mod utils;
 
 use commands::{
-    ControlAction, ControlCommand, DeployCommand, DeployOptions, InitCommand, SetupCommand,
+    AddonsAction, AddonsCommand, AppsCommand, BackupAction, BackupCommand, CertsAction,
+    CertsCommand, ControlAction, ControlCommand, DeployCommand, DeployOptions, DestroyCommand,
+    DestroyOptions, EnvAction, EnvCommand, InitCommand, ReleaseAction, ReleaseCommand,
+    SecretsAction, SecretsCommand, SetupCommand, TunnelCommand,
 };
 use utils::AppConfigOverrides;
 
@@ -14,15 +17,25 @@ struct Cli {
     command: Commands,
 }
 
+// Parsed once per run, so the size of the `deploy` variant does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Sets up the minion environment on the VPS
-    Setup,
+    Setup {
+        /// Environment from minion.toml whose VPS host to offer, e.g. staging
+        #[arg(long = "env")]
+        environment: Option<String>,
+    },
     /// Initialize a new minion project
-    Init,
-    /// Deploy the current project. Use -y to skip prompts and use .minion defaults
+    Init {
+        /// Environment to add to or update in minion.toml, e.g. staging
+        #[arg(long = "env")]
+        environment: Option<String>,
+    },
+    /// Deploy the current project. Use -y to skip prompts and use minion.toml defaults
     Deploy {
-        /// Use defaults from .minion config
+        /// Use defaults from minion.toml
         #[arg(short, long)]
         yes: bool,
 
@@ -48,11 +61,71 @@ enum Commands {
         /// Docker build platform
         #[arg(long)]
         docker_platform: Option<String>,
+
+        /// Compression used while streaming the image to the VPS: none, gzip, or zstd
+        #[arg(long)]
+        image_compression: Option<String>,
+
+        /// Where the image comes from: local, remote-build, or registry
+        #[arg(long)]
+        image_source: Option<String>,
+
+        /// Registry image to pull when the image source is registry
+        #[arg(long)]
+        image: Option<String>,
+
+        /// Username for the image registry
+        #[arg(long)]
+        registry_username: Option<String>,
+
+        /// Health check used before cutting traffic over: http or tcp
+        #[arg(long)]
+        healthcheck_type: Option<String>,
+
+        /// HTTP path probed on the new container, e.g. /health
+        #[arg(long)]
+        healthcheck_path: Option<String>,
+
+        /// Seconds to wait for the new container to become healthy
+        #[arg(long)]
+        healthcheck_wait: Option<String>,
+
+        /// Seconds between Docker health checks of the running container
+        #[arg(long)]
+        healthcheck_interval: Option<String>,
+
+        /// Seconds before a single Docker health check counts as failed
+        #[arg(long)]
+        healthcheck_timeout: Option<String>,
+
+        /// Failed Docker health checks before the container is unhealthy
+        #[arg(long)]
+        healthcheck_retries: Option<String>,
+
+        /// Command run in a one-off app container before the cutover, e.g. migrations
+        #[arg(long)]
+        pre_deploy: Option<String>,
+
+        /// Command run in a one-off app container after the cutover
+        #[arg(long)]
+        post_deploy: Option<String>,
+
+        /// sops-encrypted dotenv file with app secrets
+        #[arg(long)]
+        secrets_file: Option<String>,
+
+        /// Where secrets are decrypted: local or remote
+        #[arg(long)]
+        secrets_decrypt: Option<String>,
     },
     /// Show container status and recent logs for the current app
     Status {
         #[command(flatten)]
         common: CommonArgs,
+
+        /// Only show one process: web or a name from PROCESSES
+        #[arg(long)]
+        process: Option<String>,
     },
     /// Show docker compose ps output for the current app
     Ps {
@@ -71,11 +144,19 @@ enum Commands {
         /// Number of log lines to show
         #[arg(long, default_value_t = 100)]
         tail: u16,
+
+        /// Only show logs of one process: web or a name from PROCESSES
+        #[arg(long)]
+        process: Option<String>,
     },
     /// Restart the current app
     Restart {
         #[command(flatten)]
         common: CommonArgs,
+
+        /// Only restart one process: web or a name from PROCESSES
+        #[arg(long)]
+        process: Option<String>,
     },
     /// Stop the current app
     Stop {
@@ -92,10 +173,303 @@ enum Commands {
         #[command(flatten)]
         common: CommonArgs,
     },
+    /// Run a command inside the running app container, e.g. minion exec -- bin/rails db:seed
+    Exec {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Run in one process instead: web or a name from PROCESSES
+        #[arg(long)]
+        process: Option<String>,
+
+        /// Command and arguments, after --
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Open an interactive shell inside the running app container
+    Shell {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Open the shell in one process instead: web or a name from PROCESSES
+        #[arg(long)]
+        process: Option<String>,
+    },
+    /// Forward local ports to containers on the VPS, e.g. minion tunnel 5432:postgres:5432
+    Tunnel {
+        /// Forwards as <local-port>:<container>:<port> or <container>:<port>
+        #[arg(required = true)]
+        forwards: Vec<String>,
+
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// List the releases kept on the VPS for the current app
+    Releases {
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Re-activate an earlier release without rebuilding
+    Rollback {
+        /// Release to activate. Defaults to the release before the current one
+        release: Option<String>,
+
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Back up the app's volumes, e.g. minion backup --stop --output backups
+    #[command(args_conflicts_with_subcommands = true)]
+    Backup {
+        #[command(subcommand)]
+        command: Option<BackupCommands>,
+
+        #[command(flatten)]
+        args: BackupArgs,
+    },
+    /// Restore the app's volumes from a backup and restart the app
+    Restore {
+        /// Local archive, or the name of a backup kept on the VPS
+        archive: String,
+
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// List every app on the VPS with its state, image age, volumes, and domains
+    Apps {
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Remove the current app from the VPS, keeping or archiving its volumes
+    Destroy {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Archive the volumes into the app's backups directory and delete them
+        #[arg(long)]
+        archive: bool,
+
+        /// Skip the typed confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Manage TLS certificates supplied instead of Let's Encrypt. Lists them by default
+    #[command(args_conflicts_with_subcommands = true)]
+    Certs {
+        #[command(subcommand)]
+        command: Option<CertsCommands>,
+
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Manage services that run next to the app, such as Postgres
+    Addons {
+        #[command(subcommand)]
+        command: AddonsCommands,
+    },
+    /// Manage environment variables passed to the app container
+    Env {
+        #[command(subcommand)]
+        command: EnvCommands,
+    },
+    /// Manage the sops-encrypted .minion.secrets file
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum EnvCommands {
+    /// List the app's variables. Values are hidden unless --reveal is passed
+    List {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Print variable values instead of hiding them
+        #[arg(long)]
+        reveal: bool,
+    },
+    /// Set one or more variables
+    Set {
+        /// Variables as KEY=VALUE
+        #[arg(required = true)]
+        assignments: Vec<String>,
+
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Recreate the app containers so the change takes effect
+        #[arg(long)]
+        restart: bool,
+    },
+    /// Remove one or more variables
+    Unset {
+        /// Variable names
+        #[arg(required = true)]
+        keys: Vec<String>,
+
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Recreate the app containers so the change takes effect
+        #[arg(long)]
+        restart: bool,
+    },
+    /// Merge variables from a local dotenv file
+    Import {
+        /// Path to a KEY=VALUE file
+        file: String,
+
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Recreate the app containers so the change takes effect
+        #[arg(long)]
+        restart: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AddonsCommands {
+    /// Create an add-on in the app's Compose project, e.g. minion addons create redis --persist
+    Create {
+        /// Add-on to create: postgres or redis
+        addon: String,
+
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Keep Redis data across restarts with AOF persistence in a Minion volume
+        #[arg(long)]
+        persist: bool,
+    },
+    /// List the app's add-ons
+    List {
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Open psql in the Postgres add-on
+    Psql {
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Download a pg_dump of the Postgres add-on
+    Dump {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Local file or directory for the dump. Defaults to the current directory
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Restore a pg_dump file into the Postgres add-on
+    Restore {
+        /// Local dump created with `minion addons dump`
+        dump: String,
+
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum BackupCommands {
+    /// List the backups kept on the VPS
+    List {
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Back up the volumes to S3-compatible storage on a schedule
+    Schedule {
+        #[command(flatten)]
+        common: CommonArgs,
+
+        /// Cron schedule, e.g. "0 3 * * *"
+        #[arg(long)]
+        schedule: Option<String>,
+
+        /// Run one backup right after scheduling it
+        #[arg(long)]
+        now: bool,
+
+        /// Remove the scheduled backup and its stored credentials
+        #[arg(long, conflicts_with_all = ["schedule", "now"])]
+        remove: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CertsCommands {
+    /// Upload a certificate and key and serve them to matching hosts
+    Add {
+        /// PEM certificate, including the intermediate chain
+        #[arg(long)]
+        cert: String,
+
+        /// PEM private key of the certificate
+        #[arg(long)]
+        key: String,
+
+        /// Domain the certificate is for, e.g. *.example.com. Can be repeated
+        #[arg(long = "domain", required = true)]
+        domains: Vec<String>,
+
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// List the added certificates with their expiry dates
+    List {
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// Remove an added certificate
+    Remove {
+        /// Certificate name shown by `minion certs list`
+        name: String,
+
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+}
+
+#[derive(Args, Debug, Clone, Default)]
+struct BackupArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Back up only this volume from APP_VOLUMES. Can be repeated
+    #[arg(long = "volume")]
+    volumes: Vec<String>,
+
+    /// Stop the app while archiving so files are consistent
+    #[arg(long)]
+    stop: bool,
+
+    /// Download the archive into this local directory instead of keeping it on the VPS
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Number of backups to keep on the VPS
+    #[arg(long)]
+    keep: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum SecretsCommands {
+    /// Open the secrets file in $EDITOR through sops, creating it if needed
+    Edit {
+        /// Edit the secrets of this environment from minion.toml
+        #[arg(long = "env")]
+        environment: Option<String>,
+    },
 }
 
 #[derive(Args, Debug, Clone, Default)]
 struct CommonArgs {
+    /// Environment from minion.toml to use, e.g. staging
+    #[arg(long = "env")]
+    environment: Option<String>,
+
     /// VPS hostname or IP address
     #[arg(long)]
     host: Option<String>,
@@ -129,11 +503,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Setup => {
-            SetupCommand::new().execute()?;
+        Commands::Setup { environment } => {
+            SetupCommand::new().execute(environment)?;
         }
-        Commands::Init => {
-            InitCommand::new().execute()?;
+        Commands::Init { environment } => {
+            InitCommand::new().execute(environment)?;
         }
         Commands::Deploy {
             yes,
@@ -143,17 +517,48 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             port,
             volumes,
             docker_platform,
+            image_compression,
+            image_source,
+            image,
+            registry_username,
+            healthcheck_type,
+            healthcheck_path,
+            healthcheck_wait,
+            healthcheck_interval,
+            healthcheck_timeout,
+            healthcheck_retries,
+            pre_deploy,
+            post_deploy,
+            secrets_file,
+            secrets_decrypt,
         } => {
             let mut overrides = overrides_from_common(common);
             overrides.app_url = url;
             overrides.app_port = port;
             overrides.app_volumes = volumes;
             overrides.docker_platform = docker_platform;
+            overrides.image_compression = image_compression;
+            overrides.image_source = image_source;
+            overrides.image = image;
+            overrides.registry_username = registry_username;
+            overrides.healthcheck_type = healthcheck_type;
+            overrides.healthcheck_path = healthcheck_path;
+            overrides.healthcheck_wait = healthcheck_wait;
+            overrides.healthcheck_interval = healthcheck_interval;
+            overrides.healthcheck_timeout = healthcheck_timeout;
+            overrides.healthcheck_retries = healthcheck_retries;
+            overrides.pre_deploy = pre_deploy;
+            overrides.post_deploy = post_deploy;
+            overrides.secrets_file = secrets_file;
+            overrides.secrets_decrypt = secrets_decrypt;
 
             DeployCommand::new().execute(DeployOptions { yes, ci, overrides })?;
         }
-        Commands::Status { common } => {
-            ControlCommand::new().execute(ControlAction::Status, overrides_from_common(common))?;
+        Commands::Status { common, process } => {
+            ControlCommand::new().execute(
+                ControlAction::Status { process },
+                overrides_from_common(common),
+            )?;
         }
         Commands::Ps { common } => {
             ControlCommand::new().execute(ControlAction::Ps, overrides_from_common(common))?;
@@ -162,14 +567,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             common,
             follow,
             tail,
+            process,
         } => {
             ControlCommand::new().execute(
-                ControlAction::Logs { follow, tail },
+                ControlAction::Logs {
+                    follow,
+                    tail,
+                    process,
+                },
                 overrides_from_common(common),
             )?;
         }
-        Commands::Restart { common } => {
-            ControlCommand::new().execute(ControlAction::Restart, overrides_from_common(common))?;
+        Commands::Restart { common, process } => {
+            ControlCommand::new().execute(
+                ControlAction::Restart { process },
+                overrides_from_common(common),
+            )?;
         }
         Commands::Stop { common } => {
             ControlCommand::new().execute(ControlAction::Stop, overrides_from_common(common))?;
@@ -180,6 +593,154 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Doctor { common } => {
             ControlCommand::new().execute(ControlAction::Doctor, overrides_from_common(common))?;
         }
+        Commands::Exec {
+            common,
+            process,
+            command,
+        } => {
+            ControlCommand::new().execute(
+                ControlAction::Exec { process, command },
+                overrides_from_common(common),
+            )?;
+        }
+        Commands::Shell { common, process } => {
+            ControlCommand::new().execute(
+                ControlAction::Shell { process },
+                overrides_from_common(common),
+            )?;
+        }
+        Commands::Tunnel { forwards, common } => {
+            TunnelCommand::new().execute(forwards, overrides_from_common(common))?;
+        }
+        Commands::Releases { common } => {
+            ReleaseCommand::new().execute(ReleaseAction::List, overrides_from_common(common))?;
+        }
+        Commands::Rollback { release, common } => {
+            ReleaseCommand::new().execute(
+                ReleaseAction::Rollback { release },
+                overrides_from_common(common),
+            )?;
+        }
+        Commands::Backup {
+            command: Some(BackupCommands::List { common }),
+            ..
+        } => {
+            BackupCommand::new().execute(BackupAction::List, overrides_from_common(common))?;
+        }
+        Commands::Backup {
+            command:
+                Some(BackupCommands::Schedule {
+                    common,
+                    schedule,
+                    now,
+                    remove,
+                }),
+            ..
+        } => {
+            let mut overrides = overrides_from_common(common);
+            overrides.backup_schedule = schedule;
+
+            BackupCommand::new().execute(BackupAction::Schedule { remove, now }, overrides)?;
+        }
+        Commands::Backup {
+            command: None,
+            args,
+        } => {
+            let mut overrides = overrides_from_common(args.common);
+            overrides.backup_keep = args.keep;
+
+            BackupCommand::new().execute(
+                BackupAction::Create {
+                    volumes: args.volumes,
+                    stop: args.stop,
+                    output: args.output,
+                },
+                overrides,
+            )?;
+        }
+        Commands::Restore { archive, common } => {
+            BackupCommand::new().execute(
+                BackupAction::Restore { archive },
+                overrides_from_common(common),
+            )?;
+        }
+        Commands::Apps { common } => {
+            AppsCommand::new().execute(overrides_from_common(common))?;
+        }
+        Commands::Destroy {
+            common,
+            archive,
+            yes,
+        } => {
+            DestroyCommand::new().execute(DestroyOptions {
+                archive,
+                yes,
+                overrides: overrides_from_common(common),
+            })?;
+        }
+        Commands::Certs { command, common } => {
+            let (action, common) = match command {
+                Some(CertsCommands::Add {
+                    cert,
+                    key,
+                    domains,
+                    common,
+                }) => (CertsAction::Add { cert, key, domains }, common),
+                Some(CertsCommands::List { common }) => (CertsAction::List, common),
+                Some(CertsCommands::Remove { name, common }) => {
+                    (CertsAction::Remove { name }, common)
+                }
+                None => (CertsAction::List, common),
+            };
+            CertsCommand::new().execute(action, overrides_from_common(common))?;
+        }
+        Commands::Addons { command } => {
+            let (action, common) = match command {
+                AddonsCommands::Create {
+                    addon,
+                    common,
+                    persist,
+                } => (AddonsAction::Create { addon, persist }, common),
+                AddonsCommands::List { common } => (AddonsAction::List, common),
+                AddonsCommands::Psql { common } => (AddonsAction::Psql, common),
+                AddonsCommands::Dump { common, output } => (AddonsAction::Dump { output }, common),
+                AddonsCommands::Restore { dump, common } => {
+                    (AddonsAction::Restore { dump }, common)
+                }
+            };
+            AddonsCommand::new().execute(action, overrides_from_common(common))?;
+        }
+        Commands::Env { command } => {
+            let (action, restart, common) = match command {
+                EnvCommands::List { common, reveal } => (EnvAction::List { reveal }, false, common),
+                EnvCommands::Set {
+                    assignments,
+                    common,
+                    restart,
+                } => (EnvAction::Set { assignments }, restart, common),
+                EnvCommands::Unset {
+                    keys,
+                    common,
+                    restart,
+                } => (EnvAction::Unset { keys }, restart, common),
+                EnvCommands::Import {
+                    file,
+                    common,
+                    restart,
+                } => (EnvAction::Import { path: file }, restart, common),
+            };
+            EnvCommand::new().execute(action, restart, overrides_from_common(common))?;
+        }
+        Commands::Secrets { command } => {
+            let (action, environment) = match command {
+                SecretsCommands::Edit { environment } => (SecretsAction::Edit, environment),
+            };
+            let overrides = AppConfigOverrides {
+                environment,
+                ..Default::default()
+            };
+            SecretsCommand::new().execute(action, overrides)?;
+        }
     }
 
     Ok(())
@@ -187,6 +748,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 fn overrides_from_common(common: CommonArgs) -> AppConfigOverrides {
     AppConfigOverrides {
+        environment: common.environment,
         host: common.host,
         app_name: common.app_name,
         ssh_user: common.ssh_user,
@@ -0,0 +1,21 @@
+# Run by `minion apps`. Prints one tab-separated record per fact about each
+# app under /opt/minion: its name, the state of every container, the age of the
+# running image in seconds, the domains routed to it, and the KiB used by its
+# volumes.
+for file in /opt/minion/*/docker-compose.yml; do
+    [ -f "$file" ] || continue
+    dir=$(dirname "$file")
+    app=$(basename "$dir")
+    printf 'app\t%s\n' "$app"
+    (cd "$dir" && docker compose ps -a --format '{{.State}}' 2>/dev/null) | awk '{ print "state\t" $0 }'
+    release=$(cat "$dir/current_release" 2>/dev/null || true)
+    created=$(docker image inspect -f '{{.Created}}' "minion_$app:$release" 2>/dev/null || true)
+    if [ -n "$created" ]; then
+        printf 'age\t%s\n' "$(( $(date +%s) - $(date -d "$created" +%s) ))"
+    fi
+    grep -o 'Host(`[^`]*`)' "$file" | awk -F '`' '{ print "domain\t" $2 }'
+    grep -o 'tls\.domains\[[0-9]*\]\.main=[^"]*' "$file" | cut -d= -f2 | awk '{ print "domain\t" $0 }'
+    if [ -d "$dir/volumes" ]; then
+        printf 'volumes\t%s\n' "$(sudo du -sk "$dir/volumes" 2>/dev/null | cut -f1)"
+    fi
+done
@@ -0,0 +1,27 @@
+#!/bin/sh
+# Installed by `minion backup schedule` for {{app_name}}. Archives the app's
+# volumes, uploads the archive to S3-compatible storage, and keeps the newest
+# {{keep}} archives there.
+set -eu
+
+app_dir={{app_dir}}
+target={{target}}
+name="{{app_name}}-$(date -u +%Y%m%d%H%M%S).tar.gz"
+
+mc() {
+    docker run --rm --network host \
+        -v "$app_dir/.mc:/config" -v "$app_dir/backups:/backups:ro" \
+        {{mc_image}} --config-dir /config --quiet "$@"
+}
+
+mkdir -p "$app_dir/backups"
+trap 'rm -f "$app_dir/backups/$name"' EXIT
+
+sudo tar -C "$app_dir/volumes" -czf "$app_dir/backups/$name" {{volumes}}
+sudo chown "$(id -u):$(id -g)" "$app_dir/backups/$name"
+
+mc cp "/backups/$name" "$target$name"
+mc ls "$target" | awk '{ print $NF }' | grep -E {{pattern}} | sort | head -n -{{keep}} |
+    while read -r expired; do mc rm "$target$expired"; done
+
+echo "Uploaded $name to $target"
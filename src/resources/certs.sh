@@ -0,0 +1,34 @@
+# Run by `minion certs` and `minion doctor`. Prints tab-separated records for
+# every certificate Traefik serves: those issued through ACME, stored base64
+# encoded in acme.json under their resolver's name, and those added with
+# `minion certs add`. A `cert` record starts each certificate.
+describe() {
+    pem=$(cat)
+    printf 'cert\t%s\t%s\n' "$1" "$2"
+    end=$(printf '%s\n' "$pem" | openssl x509 -noout -enddate 2>/dev/null | cut -d= -f2)
+    [ -n "$end" ] || return 0
+    printf 'expires\t%s\t%s\n' "$(date -u -d "$end" +%s)" "$(date -u -d "$end" +%Y-%m-%d)"
+    issuer=$(printf '%s\n' "$pem" | openssl x509 -noout -issuer -nameopt multiline)
+    printf 'issuer\t%s\t%s\n' \
+        "$(printf '%s\n' "$issuer" | sed -n 's/^ *organizationName *= //p' | head -n 1)" \
+        "$(printf '%s\n' "$issuer" | sed -n 's/^ *commonName *= //p' | head -n 1)"
+    printf '%s\n' "$pem" | openssl x509 -noout -ext subjectAltName 2>/dev/null |
+        tr ',' '\n' | sed -n 's/^ *DNS:/san\t/p'
+}
+
+sudo awk -F '"' '/^  "[^"]+": \{/ { resolver = $2 } /"certificate": "/ { print resolver "\t" $4 }' \
+    /opt/traefik/data/acme.json 2>/dev/null |
+    while IFS="$(printf '\t')" read -r resolver certificate; do
+        printf '%s' "$certificate" | base64 -d | describe "$resolver" ""
+    done
+
+for file in /opt/traefik/config/dynamic/certs-*.yml; do
+    [ -f "$file" ] || continue
+    name=$(basename "$file" .yml)
+    name=${name#certs-}
+    if [ -f "/opt/traefik/config/dynamic/certs/$name.crt" ]; then
+        describe custom "$name" < "/opt/traefik/config/dynamic/certs/$name.crt"
+    else
+        printf 'cert\tcustom\t%s\n' "$name"
+    fi
+done
@@ -0,0 +1,197 @@
+//! ACME certificate resolvers that `minion setup` configures in Traefik.
+//!
+//! `letsencrypt` answers HTTP-01 challenges on port 80. Each DNS provider gets
+//! a DNS-01 resolver of the same name, which also works for wildcard domains
+//! and servers that are not reachable on port 80. Traefik reads the provider
+//! credentials from `/opt/traefik/dns.env`. Apps using `custom` skip ACME and
+//! are served the certificates added with `minion certs add`.
+
+use anyhow::{anyhow, Result};
+
+/// Resolver using the HTTP-01 challenge, the default for every app.
+pub const HTTP_RESOLVER: &str = "letsencrypt";
+/// Not a resolver: routers only enable TLS and Traefik picks a matching
+/// certificate from its file provider.
+pub const CUSTOM_CERTS: &str = "custom";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsProvider {
+    Cloudflare,
+    Route53,
+    Rfc2136,
+}
+
+/// Environment variable a DNS provider reads its credentials from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsCredential {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+}
+
+const fn credential(key: &'static str, description: &'static str, required: bool) -> DnsCredential {
+    DnsCredential {
+        key,
+        description,
+        required,
+    }
+}
+
+const CLOUDFLARE_CREDENTIALS: &[DnsCredential] = &[credential(
+    "CF_DNS_API_TOKEN",
+    "Cloudflare API token with Zone:DNS:Edit permission",
+    true,
+)];
+const ROUTE53_CREDENTIALS: &[DnsCredential] = &[
+    credential("AWS_ACCESS_KEY_ID", "AWS access key ID", true),
+    credential("AWS_SECRET_ACCESS_KEY", "AWS secret access key", true),
+    credential("AWS_REGION", "AWS region, e.g. us-east-1", true),
+    credential("AWS_HOSTED_ZONE_ID", "Route53 hosted zone ID", false),
+];
+const RFC2136_CREDENTIALS: &[DnsCredential] = &[
+    credential(
+        "RFC2136_NAMESERVER",
+        "DNS server accepting updates, as host:port",
+        true,
+    ),
+    credential("RFC2136_TSIG_KEY", "TSIG key name", true),
+    credential("RFC2136_TSIG_SECRET", "TSIG secret", true),
+    credential(
+        "RFC2136_TSIG_ALGORITHM",
+        "TSIG algorithm, e.g. hmac-sha256.",
+        false,
+    ),
+];
+
+impl DnsProvider {
+    pub const ALL: &'static [DnsProvider] = &[
+        DnsProvider::Cloudflare,
+        DnsProvider::Route53,
+        DnsProvider::Rfc2136,
+    ];
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|provider| provider.name() == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown DNS provider {}. Available providers: {}",
+                    name,
+                    Self::names()
+                )
+            })
+    }
+
+    /// Provider code Traefik knows it by, also the name of its resolver.
+    pub fn name(self) -> &'static str {
+        match self {
+            DnsProvider::Cloudflare => "cloudflare",
+            DnsProvider::Route53 => "route53",
+            DnsProvider::Rfc2136 => "rfc2136",
+        }
+    }
+
+    pub fn credentials(self) -> &'static [DnsCredential] {
+        match self {
+            DnsProvider::Cloudflare => CLOUDFLARE_CREDENTIALS,
+            DnsProvider::Route53 => ROUTE53_CREDENTIALS,
+            DnsProvider::Rfc2136 => RFC2136_CREDENTIALS,
+        }
+    }
+
+    fn names() -> String {
+        Self::ALL
+            .iter()
+            .map(|provider| provider.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Checks that `name` is `letsencrypt`, `custom` or the resolver of a DNS
+/// provider.
+pub fn validate_cert_resolver(name: &str) -> Result<()> {
+    if name == HTTP_RESOLVER || name == CUSTOM_CERTS || DnsProvider::parse(name).is_ok() {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "CERT_RESOLVER must be {}, {} or one of the DNS providers: {}",
+        HTTP_RESOLVER,
+        CUSTOM_CERTS,
+        DnsProvider::names()
+    ))
+}
+
+/// `certificatesResolvers` entries for `traefik.yml`, one per provider.
+pub fn dns_resolvers_config(providers: &[DnsProvider], email: &str) -> String {
+    providers
+        .iter()
+        .map(|provider| {
+            format!(
+                "  {name}:\n    acme:\n      email: \"{email}\"\n      storage: /etc/traefik/acme.json\n      dnsChallenge:\n        provider: {name}\n",
+                name = provider.name(),
+                email = email
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolvers_are_letsencrypt_or_a_dns_provider() {
+        assert!(validate_cert_resolver("letsencrypt").is_ok());
+        assert!(validate_cert_resolver("rfc2136").is_ok());
+        assert!(validate_cert_resolver("custom").is_ok());
+        assert_eq!(
+            validate_cert_resolver("digitalocean")
+                .unwrap_err()
+                .to_string(),
+            "CERT_RESOLVER must be letsencrypt, custom or one of the DNS providers: cloudflare, route53, rfc2136"
+        );
+    }
+
+    #[test]
+    fn dns_resolvers_use_the_dns_challenge() {
+        assert_eq!(
+            dns_resolvers_config(
+                &[DnsProvider::Cloudflare, DnsProvider::Rfc2136],
+                "ops@example.com"
+            ),
+            "  cloudflare:\n    acme:\n      email: \"ops@example.com\"\n      storage: /etc/traefik/acme.json\n      dnsChallenge:\n        provider: cloudflare\n  \
+rfc2136:\n    acme:\n      email: \"ops@example.com\"\n      storage: /etc/traefik/acme.json\n      dnsChallenge:\n        provider: rfc2136\n"
+        );
+    }
+
+    #[test]
+    fn rfc2136_resolver_reads_the_nameserver_and_tsig_key_from_the_env() {
+        let provider = DnsProvider::parse("rfc2136").unwrap();
+        let keys = |required: bool| {
+            provider
+                .credentials()
+                .iter()
+                .filter(|credential| credential.required == required)
+                .map(|credential| credential.key)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            keys(true),
+            vec![
+                "RFC2136_NAMESERVER",
+                "RFC2136_TSIG_KEY",
+                "RFC2136_TSIG_SECRET"
+            ]
+        );
+        assert_eq!(keys(false), vec!["RFC2136_TSIG_ALGORITHM"]);
+        assert_eq!(
+            dns_resolvers_config(&[provider], "ops@example.com"),
+            "  rfc2136:\n    acme:\n      email: \"ops@example.com\"\n      storage: /etc/traefik/acme.json\n      dnsChallenge:\n        provider: rfc2136\n"
+        );
+    }
+}
@@ -1,13 +1,15 @@
 use anyhow::{anyhow, Result};
+use std::collections::HashMap;
 use std::env;
 use std::io::{self, Write};
+use std::net::IpAddr;
 
-use super::{Config, SshAuth};
-
-const CONFIG_FILE: &str = ".minion";
+use super::manifest::{process_key, route_key};
+use super::{validate_cert_resolver, Manifest, SshAuth, HTTP_RESOLVER};
 
 #[derive(Debug, Clone, Default)]
 pub struct AppConfigOverrides {
+    pub environment: Option<String>,
     pub host: Option<String>,
     pub app_name: Option<String>,
     pub app_url: Option<String>,
@@ -19,21 +21,169 @@ pub struct AppConfigOverrides {
     pub ssh_password: Option<String>,
     pub ssh_passphrase: Option<String>,
     pub docker_platform: Option<String>,
+    pub image_compression: Option<String>,
+    pub image_source: Option<String>,
+    pub image: Option<String>,
+    pub registry_username: Option<String>,
+    pub healthcheck_type: Option<String>,
+    pub healthcheck_path: Option<String>,
+    pub healthcheck_wait: Option<String>,
+    pub healthcheck_interval: Option<String>,
+    pub healthcheck_timeout: Option<String>,
+    pub healthcheck_retries: Option<String>,
+    pub pre_deploy: Option<String>,
+    pub post_deploy: Option<String>,
+    pub backup_keep: Option<String>,
+    pub backup_schedule: Option<String>,
+    pub secrets_file: Option<String>,
+    pub secrets_decrypt: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct AppConfig {
+    /// Environment selected with `--env`, whose overrides are already applied.
+    pub environment: Option<String>,
     pub host: String,
     pub app_name: String,
     pub app_url: String,
     pub app_port: String,
     pub app_volumes: String,
+    /// Traefik certificate resolver for the app's routers: `letsencrypt` or
+    /// a DNS provider set up by `minion setup`.
+    pub cert_resolver: String,
     pub ssh_user: String,
     pub ssh_key_path: Option<String>,
     pub ssh_private_key: Option<String>,
     pub ssh_password: Option<String>,
     pub ssh_passphrase: Option<String>,
     pub docker_platform: String,
+    pub image_compression: String,
+    pub image_source: String,
+    pub image: String,
+    pub registry_username: Option<String>,
+    pub registry_password: Option<String>,
+    pub healthcheck_type: String,
+    pub healthcheck_path: String,
+    pub healthcheck_wait: String,
+    pub healthcheck_interval: String,
+    pub healthcheck_timeout: String,
+    pub healthcheck_retries: String,
+    /// Command run in a one-off container of the new release before cutover.
+    pub pre_deploy: String,
+    /// Command run in a one-off container once the new release is live.
+    pub post_deploy: String,
+    /// Number of volume backups kept on the VPS.
+    pub backup_keep: String,
+    /// Cron schedule of the off-server backup installed by `minion backup schedule`.
+    pub backup_schedule: String,
+    pub backup_s3_endpoint: String,
+    pub backup_s3_bucket: String,
+    /// Key prefix for uploaded archives. Empty means `<app-name>/`.
+    pub backup_s3_prefix: String,
+    /// Number of uploaded archives kept under the prefix.
+    pub backup_s3_keep: String,
+    pub backup_s3_access_key_id: Option<String>,
+    pub backup_s3_secret_access_key: Option<String>,
+    pub secrets_file: String,
+    pub secrets_decrypt: String,
+    pub age_key: Option<String>,
+    pub processes: Vec<ProcessConfig>,
+    pub routes: Vec<RouteConfig>,
+    pub middlewares: MiddlewareConfig,
+}
+
+/// An extra process run from the app image next to the web service: a
+/// worker, a sidecar with its own router, or a scheduled job.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessConfig {
+    pub name: String,
+    pub command: String,
+    pub replicas: String,
+    /// Hosts routed to this process by Traefik. Empty for internal processes.
+    pub url: String,
+    /// Container port behind `url`. Empty means `APP_PORT`.
+    pub port: String,
+    /// Cron schedule. Scheduled processes only run when their schedule fires.
+    pub schedule: String,
+}
+
+/// An extra Traefik router for the web service, sending matching requests to
+/// one of its ports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteConfig {
+    pub name: String,
+    /// Hosts the route matches. Empty means the hosts of `APP_URL`.
+    pub host: String,
+    /// Path prefix the route matches, e.g. `/api`. Empty matches every path.
+    pub path: String,
+    /// Container port requests are sent to. Empty means `APP_PORT`.
+    pub port: String,
+    /// Traefik router priority. Empty leaves Traefik's default, which
+    /// prefers longer rules.
+    pub priority: String,
+}
+
+impl RouteConfig {
+    /// The route's hosts, falling back to those of `APP_URL`.
+    pub fn hosts<'a>(&'a self, config: &'a AppConfig) -> Vec<&'a str> {
+        let host = if self.host.is_empty() {
+            &config.app_url
+        } else {
+            &self.host
+        };
+        list_items(host).collect()
+    }
+}
+
+/// Traefik middlewares in front of the app's web router. Empty values leave
+/// a middleware out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MiddlewareConfig {
+    /// `www` redirects bare hosts in `APP_URL` to their `www.` host,
+    /// `non-www` the other way round.
+    pub www_redirect: String,
+    /// Comma-separated `user:hash` entries, as printed by `htpasswd -nB`.
+    pub basic_auth: String,
+    /// `true` adds common security headers to every response.
+    pub security_headers: String,
+    /// `Strict-Transport-Security` max-age in seconds.
+    pub hsts_max_age: String,
+    /// Comma-separated path prefixes removed before requests reach the app.
+    pub strip_prefix: String,
+    /// Comma-separated addresses or CIDR ranges allowed to reach the app.
+    pub ip_allowlist: String,
+    /// Average requests per second allowed from one client.
+    pub rate_limit: String,
+    pub rate_limit_burst: String,
+}
+
+impl MiddlewareConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl ProcessConfig {
+    pub fn is_routed(&self) -> bool {
+        !self.url.is_empty()
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        !self.schedule.is_empty()
+    }
+
+    pub fn replicas_u32(&self) -> Result<u32> {
+        self.replicas
+            .parse::<u32>()
+            .ok()
+            .filter(|replicas| *replicas > 0)
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} must be a positive number",
+                    process_key(&self.name, "REPLICAS")
+                )
+            })
+    }
 }
 
 impl AppConfig {
@@ -42,16 +192,29 @@ impl AppConfig {
         interactive: bool,
         save_interactive: bool,
     ) -> Result<Self> {
-        Self::load_from_file(CONFIG_FILE, overrides, interactive, save_interactive)
+        let manifest = Manifest::open_project()?;
+        Self::load_from_manifest(manifest, overrides, interactive, save_interactive)
     }
 
+    #[cfg(test)]
     fn load_from_file(
         file_path: &str,
         overrides: AppConfigOverrides,
         interactive: bool,
         save_interactive: bool,
     ) -> Result<Self> {
-        let config = Config::new(file_path)?;
+        let manifest = Manifest::load(file_path)?;
+        Self::load_from_manifest(manifest, overrides, interactive, save_interactive)
+    }
+
+    fn load_from_manifest(
+        mut manifest: Manifest,
+        overrides: AppConfigOverrides,
+        interactive: bool,
+        save_interactive: bool,
+    ) -> Result<Self> {
+        let environment = select_environment(overrides.environment)?;
+        let config = manifest.values_for(environment.as_deref())?;
         let mut host = pick(overrides.host, "MINION_VPS_HOST", &config, "VPS_HOST");
         let mut app_name = pick(overrides.app_name, "MINION_APP_NAME", &config, "APP_NAME");
         let mut app_url = pick(overrides.app_url, "MINION_APP_URL", &config, "APP_URL");
@@ -63,6 +226,9 @@ impl AppConfig {
             "APP_VOLUMES",
         );
 
+        let cert_resolver = pick(None, "MINION_CERT_RESOLVER", &config, "CERT_RESOLVER")
+            .unwrap_or_else(|| HTTP_RESOLVER.to_string());
+
         if interactive {
             host = Some(prompt_with_default(
                 "Enter VPS hostname or IP address",
@@ -101,23 +267,177 @@ impl AppConfig {
             "DOCKER_PLATFORM",
         )
         .unwrap_or_else(|| "linux/amd64".to_string());
+        let image_compression = pick(
+            overrides.image_compression,
+            "MINION_IMAGE_COMPRESSION",
+            &config,
+            "IMAGE_COMPRESSION",
+        )
+        .unwrap_or_else(|| "gzip".to_string());
+        let image_source = pick(
+            overrides.image_source,
+            "MINION_IMAGE_SOURCE",
+            &config,
+            "IMAGE_SOURCE",
+        )
+        .unwrap_or_else(|| "local".to_string());
+        let image = pick(overrides.image, "MINION_IMAGE", &config, "IMAGE");
+        let registry_username = pick(
+            overrides.registry_username,
+            "MINION_REGISTRY_USERNAME",
+            &config,
+            "REGISTRY_USERNAME",
+        );
+        let registry_password = pick_secret(None, "MINION_REGISTRY_PASSWORD");
+        let processes = load_processes(&config);
+        let routes = load_routes(&config);
+        let healthcheck_path = pick(
+            overrides.healthcheck_path,
+            "MINION_HEALTHCHECK_PATH",
+            &config,
+            "HEALTHCHECK_PATH",
+        );
+        let healthcheck_type = pick(
+            overrides.healthcheck_type,
+            "MINION_HEALTHCHECK_TYPE",
+            &config,
+            "HEALTHCHECK_TYPE",
+        )
+        .unwrap_or_else(|| {
+            if healthcheck_path.is_some() {
+                "http".to_string()
+            } else {
+                "tcp".to_string()
+            }
+        });
+        let healthcheck_wait = pick(
+            overrides.healthcheck_wait,
+            "MINION_HEALTHCHECK_WAIT",
+            &config,
+            "HEALTHCHECK_WAIT",
+        )
+        .unwrap_or_else(|| "60".to_string());
+        let healthcheck_interval = pick(
+            overrides.healthcheck_interval,
+            "MINION_HEALTHCHECK_INTERVAL",
+            &config,
+            "HEALTHCHECK_INTERVAL",
+        )
+        .unwrap_or_else(|| "10".to_string());
+        let healthcheck_timeout = pick(
+            overrides.healthcheck_timeout,
+            "MINION_HEALTHCHECK_TIMEOUT",
+            &config,
+            "HEALTHCHECK_TIMEOUT",
+        )
+        .unwrap_or_else(|| "5".to_string());
+        let healthcheck_retries = pick(
+            overrides.healthcheck_retries,
+            "MINION_HEALTHCHECK_RETRIES",
+            &config,
+            "HEALTHCHECK_RETRIES",
+        )
+        .unwrap_or_else(|| "3".to_string());
+        let pre_deploy = pick(
+            overrides.pre_deploy,
+            "MINION_PRE_DEPLOY",
+            &config,
+            "PRE_DEPLOY",
+        );
+        let post_deploy = pick(
+            overrides.post_deploy,
+            "MINION_POST_DEPLOY",
+            &config,
+            "POST_DEPLOY",
+        );
+        let backup_keep = pick(
+            overrides.backup_keep,
+            "MINION_BACKUP_KEEP",
+            &config,
+            "BACKUP_KEEP",
+        )
+        .unwrap_or_else(|| "7".to_string());
+        let backup_schedule = pick(
+            overrides.backup_schedule,
+            "MINION_BACKUP_SCHEDULE",
+            &config,
+            "BACKUP_SCHEDULE",
+        )
+        .unwrap_or_else(|| "0 3 * * *".to_string());
+        let backup_s3_endpoint = pick(
+            None,
+            "MINION_BACKUP_S3_ENDPOINT",
+            &config,
+            "BACKUP_S3_ENDPOINT",
+        );
+        let backup_s3_bucket = pick(None, "MINION_BACKUP_S3_BUCKET", &config, "BACKUP_S3_BUCKET");
+        let backup_s3_prefix = pick(None, "MINION_BACKUP_S3_PREFIX", &config, "BACKUP_S3_PREFIX");
+        let backup_s3_keep = pick(None, "MINION_BACKUP_S3_KEEP", &config, "BACKUP_S3_KEEP")
+            .unwrap_or_else(|| "30".to_string());
+        let backup_s3_access_key_id = pick_secret(None, "MINION_BACKUP_S3_ACCESS_KEY_ID");
+        let backup_s3_secret_access_key = pick_secret(None, "MINION_BACKUP_S3_SECRET_ACCESS_KEY");
+        let secrets_file = pick(
+            overrides.secrets_file,
+            "MINION_SECRETS_FILE",
+            &config,
+            "SECRETS_FILE",
+        )
+        .unwrap_or_else(|| ".minion.secrets".to_string());
+        let secrets_decrypt = pick(
+            overrides.secrets_decrypt,
+            "MINION_SECRETS_DECRYPT",
+            &config,
+            "SECRETS_DECRYPT",
+        )
+        .unwrap_or_else(|| "local".to_string());
+        let age_key = pick_secret(None, "MINION_AGE_KEY");
+        let middlewares = load_middlewares(&config);
 
         let app_config = AppConfig {
+            environment,
             host: host.unwrap_or_default(),
             app_name: app_name.unwrap_or_default(),
             app_url: app_url.unwrap_or_default(),
             app_port: app_port.unwrap_or_default(),
             app_volumes: app_volumes.unwrap_or_default(),
+            cert_resolver,
             ssh_user,
             ssh_key_path,
             ssh_private_key,
             ssh_password,
             ssh_passphrase,
             docker_platform,
+            image_compression,
+            image_source,
+            image: image.unwrap_or_default(),
+            registry_username,
+            registry_password,
+            healthcheck_type,
+            healthcheck_path: healthcheck_path.unwrap_or_else(|| "/".to_string()),
+            healthcheck_wait,
+            healthcheck_interval,
+            healthcheck_timeout,
+            healthcheck_retries,
+            pre_deploy: pre_deploy.unwrap_or_default(),
+            post_deploy: post_deploy.unwrap_or_default(),
+            backup_keep,
+            backup_schedule,
+            backup_s3_endpoint: backup_s3_endpoint.unwrap_or_default(),
+            backup_s3_bucket: backup_s3_bucket.unwrap_or_default(),
+            backup_s3_prefix: backup_s3_prefix.unwrap_or_default(),
+            backup_s3_keep,
+            backup_s3_access_key_id,
+            backup_s3_secret_access_key,
+            secrets_file,
+            secrets_decrypt,
+            age_key,
+            processes,
+            routes,
+            middlewares,
         };
 
         if interactive && save_interactive {
-            app_config.save_app_file(file_path)?;
+            app_config.save_manifest(&mut manifest)?;
         }
 
         Ok(app_config)
@@ -126,24 +446,139 @@ impl AppConfig {
     pub fn require_deploy(&self) -> Result<()> {
         self.require_app_control()?;
         require_value("APP_URL", "MINION_APP_URL", &self.app_url)?;
+        self.require_health_check()?;
+        self.validate_image_compression()?;
+        self.validate_image_source()?;
+        self.validate_secrets_decrypt()?;
+        self.validate_processes()?;
+        self.validate_routes()?;
+        self.validate_cert_resolver()?;
+        self.validate_middlewares()
+    }
+
+    /// Checks the settings needed to probe a freshly started container.
+    pub fn require_health_check(&self) -> Result<()> {
         require_value("APP_PORT", "MINION_APP_PORT", &self.app_port)?;
         self.app_port_u16()?;
+        self.validate_healthcheck()?;
         Ok(())
     }
 
     pub fn require_app_control(&self) -> Result<()> {
-        require_value("VPS_HOST", "MINION_VPS_HOST", &self.host)?;
+        self.require_host()?;
         require_value("APP_NAME", "MINION_APP_NAME", &self.app_name)?;
         self.validate_app_name()?;
         Ok(())
     }
 
+    /// Every host Traefik routes to the app, its routes or its processes.
+    pub fn hosts(&self) -> Vec<&str> {
+        let urls = std::iter::once(self.app_url.as_str())
+            .chain(self.routes.iter().map(|route| route.host.as_str()))
+            .chain(self.processes.iter().map(|process| process.url.as_str()))
+            .flat_map(list_items);
+        let mut hosts = Vec::new();
+        for url in urls {
+            if !hosts.contains(&url) {
+                hosts.push(url);
+            }
+        }
+        hosts
+    }
+
+    /// Bare hosts in `APP_URL` that are listed together with their `www.`
+    /// host, the pairs `WWW_REDIRECT` redirects between.
+    pub fn www_pairs(&self) -> Vec<&str> {
+        let urls = self
+            .app_url
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .collect::<Vec<_>>();
+        urls.iter()
+            .copied()
+            .filter(|url| !url.starts_with("www.") && !url.contains('*'))
+            .filter(|url| urls.contains(&format!("www.{}", url).as_str()))
+            .collect()
+    }
+
+    /// Checks the settings needed for commands that look at the whole VPS.
+    pub fn require_host(&self) -> Result<()> {
+        require_value("VPS_HOST", "MINION_VPS_HOST", &self.host)
+    }
+
     pub fn app_port_u16(&self) -> Result<u16> {
         self.app_port
             .parse::<u16>()
             .map_err(|_| anyhow!("APP_PORT must be a valid TCP port"))
     }
 
+    pub fn healthcheck_wait_secs(&self) -> Result<u64> {
+        self.healthcheck_wait
+            .parse::<u64>()
+            .map_err(|_| anyhow!("HEALTHCHECK_WAIT must be a number of seconds"))
+    }
+
+    pub fn backup_keep_count(&self) -> Result<usize> {
+        self.backup_keep
+            .parse::<usize>()
+            .ok()
+            .filter(|keep| *keep > 0)
+            .ok_or_else(|| anyhow!("BACKUP_KEEP must be a positive number"))
+    }
+
+    /// Checks the settings `minion backup schedule` needs to upload archives.
+    pub fn require_backup_upload(&self) -> Result<()> {
+        if self.backup_schedule.split_whitespace().count() != 5 {
+            return Err(anyhow!(
+                "BACKUP_SCHEDULE must be a cron schedule with five fields, e.g. \"0 3 * * *\""
+            ));
+        }
+        require_value(
+            "BACKUP_S3_ENDPOINT",
+            "MINION_BACKUP_S3_ENDPOINT",
+            &self.backup_s3_endpoint,
+        )?;
+        if !self.backup_s3_endpoint.starts_with("https://")
+            && !self.backup_s3_endpoint.starts_with("http://")
+        {
+            return Err(anyhow!(
+                "BACKUP_S3_ENDPOINT must start with https:// or http://"
+            ));
+        }
+        require_value(
+            "BACKUP_S3_BUCKET",
+            "MINION_BACKUP_S3_BUCKET",
+            &self.backup_s3_bucket,
+        )?;
+        if self.backup_s3_access_key_id.is_none() || self.backup_s3_secret_access_key.is_none() {
+            return Err(anyhow!(
+                "Set MINION_BACKUP_S3_ACCESS_KEY_ID and MINION_BACKUP_S3_SECRET_ACCESS_KEY to upload backups"
+            ));
+        }
+        self.backup_s3_keep_count()?;
+        Ok(())
+    }
+
+    pub fn backup_s3_keep_count(&self) -> Result<usize> {
+        self.backup_s3_keep
+            .parse::<usize>()
+            .ok()
+            .filter(|keep| *keep > 0)
+            .ok_or_else(|| anyhow!("BACKUP_S3_KEEP must be a positive number"))
+    }
+
+    /// Names of the volume directories under `/opt/minion/<app>/volumes`,
+    /// the local side of each `APP_VOLUMES` mapping.
+    pub fn volume_names(&self) -> Vec<String> {
+        self.app_volumes
+            .split(',')
+            .filter_map(|mapping| mapping.split_once(':'))
+            .map(|(name, _)| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
     pub fn ssh_auth(&self) -> SshAuth {
         SshAuth {
             password: self.ssh_password.clone(),
@@ -153,14 +588,324 @@ impl AppConfig {
         }
     }
 
-    fn save_app_file(&self, file_path: &str) -> Result<()> {
-        let mut config = Config::new(file_path)?;
-        config.set("VPS_HOST".to_string(), self.host.clone());
-        config.set("APP_NAME".to_string(), self.app_name.clone());
-        config.set("APP_URL".to_string(), self.app_url.clone());
-        config.set("APP_PORT".to_string(), self.app_port.clone());
-        config.set("APP_VOLUMES".to_string(), self.app_volumes.clone());
-        config.save()?;
+    /// Writes the prompted values back. With an environment selected, only
+    /// the values that differ from what the environment already resolves to
+    /// are written, into its `[environments.<name>.*]` tables.
+    fn save_manifest(&self, manifest: &mut Manifest) -> Result<()> {
+        let values = [
+            ("VPS_HOST", &self.host),
+            ("APP_NAME", &self.app_name),
+            ("APP_URL", &self.app_url),
+            ("APP_PORT", &self.app_port),
+            ("APP_VOLUMES", &self.app_volumes),
+        ];
+
+        match self.environment.as_deref() {
+            Some(environment) => {
+                let current = manifest.values_for(Some(environment))?;
+                for (key, value) in values {
+                    if current.get(key).map(String::as_str).unwrap_or_default() != value.as_str() {
+                        manifest.set_for(Some(environment), key, value)?;
+                    }
+                }
+            }
+            None => {
+                for (key, value) in values {
+                    manifest.set(key, value)?;
+                }
+            }
+        }
+
+        manifest.save()
+    }
+
+    fn validate_healthcheck(&self) -> Result<()> {
+        if self.healthcheck_type != "http" && self.healthcheck_type != "tcp" {
+            return Err(anyhow!("HEALTHCHECK_TYPE must be either http or tcp"));
+        }
+        if !self.healthcheck_path.starts_with('/') {
+            return Err(anyhow!("HEALTHCHECK_PATH must start with /"));
+        }
+        self.healthcheck_wait_secs()?;
+        for (key, value) in [
+            ("HEALTHCHECK_INTERVAL", &self.healthcheck_interval),
+            ("HEALTHCHECK_TIMEOUT", &self.healthcheck_timeout),
+            ("HEALTHCHECK_RETRIES", &self.healthcheck_retries),
+        ] {
+            if !value.parse::<u32>().is_ok_and(|value| value > 0) {
+                return Err(anyhow!("{} must be a positive number", key));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_image_compression(&self) -> Result<()> {
+        if !["none", "gzip", "zstd"].contains(&self.image_compression.as_str()) {
+            return Err(anyhow!(
+                "IMAGE_COMPRESSION must be one of none, gzip, or zstd"
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn validate_image_source(&self) -> Result<()> {
+        match self.image_source.as_str() {
+            "local" | "remote-build" => Ok(()),
+            "registry" => {
+                require_value("IMAGE", "MINION_IMAGE", &self.image)?;
+                if self.registry_username.is_some() != self.registry_password.is_some() {
+                    return Err(anyhow!(
+                        "Set both REGISTRY_USERNAME and MINION_REGISTRY_PASSWORD, or neither"
+                    ));
+                }
+                Ok(())
+            }
+            _ => Err(anyhow!(
+                "IMAGE_SOURCE must be one of local, remote-build, or registry"
+            )),
+        }
+    }
+
+    fn validate_secrets_decrypt(&self) -> Result<()> {
+        if self.secrets_decrypt != "local" && self.secrets_decrypt != "remote" {
+            return Err(anyhow!("SECRETS_DECRYPT must be either local or remote"));
+        }
+
+        Ok(())
+    }
+
+    fn validate_cert_resolver(&self) -> Result<()> {
+        validate_cert_resolver(&self.cert_resolver)?;
+        for url in self.hosts().into_iter().filter(|url| url.contains('*')) {
+            let domain = url.strip_prefix("*.").unwrap_or(url);
+            if domain.is_empty() || domain.contains('*') {
+                return Err(anyhow!(
+                    "Wildcard domain {} may only use * as its first label",
+                    url
+                ));
+            }
+            if self.cert_resolver == HTTP_RESOLVER {
+                return Err(anyhow!(
+                    "Wildcard domain {} needs a DNS-01 certificate resolver. Set CERT_RESOLVER to a DNS provider configured with `minion setup`.",
+                    url
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_processes(&self) -> Result<()> {
+        let mut names = Vec::new();
+        for process in &self.processes {
+            let name = process.name.as_str();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                return Err(anyhow!(
+                    "Process name {:?} may only contain lowercase letters, numbers, and dashes",
+                    name
+                ));
+            }
+            if name == "web" || name == self.app_name || names.contains(&name) {
+                return Err(anyhow!(
+                    "Process name {} is already used by another service",
+                    name
+                ));
+            }
+            names.push(name);
+
+            require_value(
+                &process_key(name, "COMMAND"),
+                &format!("MINION_{}", process_key(name, "COMMAND")),
+                &process.command,
+            )?;
+            if process.is_scheduled() {
+                if process.schedule.split_whitespace().count() != 5 {
+                    return Err(anyhow!(
+                        "{} must be a cron schedule with five fields, e.g. \"0 3 * * *\"",
+                        process_key(name, "SCHEDULE")
+                    ));
+                }
+                if process.is_routed() {
+                    return Err(anyhow!(
+                        "Process {} cannot have both a SCHEDULE and a URL",
+                        name
+                    ));
+                }
+            } else {
+                process.replicas_u32()?;
+            }
+            if process.is_routed() && !process.port.is_empty() {
+                process.port.parse::<u16>().map_err(|_| {
+                    anyhow!("{} must be a valid TCP port", process_key(name, "PORT"))
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_routes(&self) -> Result<()> {
+        let mut names = Vec::new();
+        for route in &self.routes {
+            let name = route.name.as_str();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                return Err(anyhow!(
+                    "Route name {:?} may only contain lowercase letters, numbers, and dashes",
+                    name
+                ));
+            }
+            // Routes and routed processes both get `<app>-<name>` routers.
+            if names.contains(&name) || self.processes.iter().any(|process| process.name == name) {
+                return Err(anyhow!(
+                    "Route name {} is already used by another route or process",
+                    name
+                ));
+            }
+            names.push(name);
+
+            if route.host.is_empty() && route.path.is_empty() {
+                return Err(anyhow!(
+                    "Route {} needs a {} or a {}",
+                    name,
+                    route_key(name, "HOST"),
+                    route_key(name, "PATH")
+                ));
+            }
+            if !route.path.is_empty()
+                && (!route.path.starts_with('/')
+                    || !route
+                        .path
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "/-_.~".contains(c)))
+            {
+                return Err(anyhow!(
+                    "{} must be a path starting with /, e.g. /api",
+                    route_key(name, "PATH")
+                ));
+            }
+            if !route.port.is_empty() && route.port.parse::<u16>().is_err() {
+                return Err(anyhow!(
+                    "{} must be a valid TCP port",
+                    route_key(name, "PORT")
+                ));
+            }
+            if !route.priority.is_empty()
+                && !route
+                    .priority
+                    .parse::<u32>()
+                    .is_ok_and(|priority| priority > 0)
+            {
+                return Err(anyhow!(
+                    "{} must be a positive number",
+                    route_key(name, "PRIORITY")
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_middlewares(&self) -> Result<()> {
+        let middlewares = &self.middlewares;
+        if middlewares.is_empty() {
+            return Ok(());
+        }
+        // Traefik splits label names on dots, so the middleware names built
+        // from the app name must not contain any.
+        if self.app_name.contains('.') {
+            return Err(anyhow!(
+                "APP_NAME may not contain dots when middlewares are configured"
+            ));
+        }
+
+        match middlewares.www_redirect.as_str() {
+            "" => {}
+            "www" | "non-www" if self.www_pairs().is_empty() => {
+                return Err(anyhow!(
+                    "WWW_REDIRECT needs a host and its www. host in APP_URL, e.g. example.com,www.example.com"
+                ))
+            }
+            "www" | "non-www" => {}
+            _ => return Err(anyhow!("WWW_REDIRECT must be either www or non-www")),
+        }
+
+        for entry in list_items(&middlewares.basic_auth) {
+            let valid = entry.split_once(':').is_some_and(|(user, hash)| {
+                !user.is_empty()
+                    && ["$2y$", "$2a$", "$2b$", "$apr1$", "{SHA}"]
+                        .iter()
+                        .any(|prefix| hash.starts_with(prefix))
+                    && !entry
+                        .chars()
+                        .any(|c| c.is_whitespace() || c == '"' || c == '\\')
+            });
+            if !valid {
+                return Err(anyhow!(
+                    "BASIC_AUTH entries must be user:hash pairs with a bcrypt, MD5 or SHA1 hash, e.g. from `htpasswd -nB user`"
+                ));
+            }
+        }
+
+        if !matches!(middlewares.security_headers.as_str(), "" | "true" | "false") {
+            return Err(anyhow!("SECURITY_HEADERS must be either true or false"));
+        }
+        if !middlewares.hsts_max_age.is_empty() && middlewares.hsts_max_age.parse::<u32>().is_err()
+        {
+            return Err(anyhow!("HSTS_MAX_AGE must be a number of seconds"));
+        }
+
+        for prefix in list_items(&middlewares.strip_prefix) {
+            if prefix.len() < 2
+                || !prefix.starts_with('/')
+                || !prefix
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/-_.~".contains(c))
+            {
+                return Err(anyhow!(
+                    "STRIP_PREFIX entry {} must be a path starting with /, e.g. /api",
+                    prefix
+                ));
+            }
+        }
+
+        for range in list_items(&middlewares.ip_allowlist) {
+            let (address, bits) = range.split_once('/').unwrap_or((range, ""));
+            let valid = match address.parse::<IpAddr>() {
+                Ok(address) => {
+                    let max_bits = if address.is_ipv4() { 32 } else { 128 };
+                    bits.is_empty() || bits.parse::<u8>().is_ok_and(|bits| bits <= max_bits)
+                }
+                Err(_) => false,
+            };
+            if !valid {
+                return Err(anyhow!(
+                    "IP_ALLOWLIST entry {} must be an IP address or CIDR range",
+                    range
+                ));
+            }
+        }
+
+        for (key, value) in [
+            ("RATE_LIMIT", &middlewares.rate_limit),
+            ("RATE_LIMIT_BURST", &middlewares.rate_limit_burst),
+        ] {
+            if !value.is_empty() && !value.parse::<u32>().is_ok_and(|value| value > 0) {
+                return Err(anyhow!("{} must be a positive number of requests", key));
+            }
+        }
+        if middlewares.rate_limit.is_empty() && !middlewares.rate_limit_burst.is_empty() {
+            return Err(anyhow!("RATE_LIMIT_BURST needs RATE_LIMIT"));
+        }
+
         Ok(())
     }
 
@@ -175,10 +920,33 @@ impl AppConfig {
     }
 }
 
+/// The environment chosen with `--env` or `MINION_ENVIRONMENT`, if any.
+pub fn select_environment(flag: Option<String>) -> Result<Option<String>> {
+    let environment = clean(flag).or_else(|| env_value("MINION_ENVIRONMENT"));
+    if let Some(environment) = &environment {
+        validate_environment(environment)?;
+    }
+    Ok(environment)
+}
+
+fn validate_environment(environment: &str) -> Result<()> {
+    if environment
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "Environment name {:?} may only contain lowercase letters, numbers, and dashes",
+        environment
+    ))
+}
+
 fn pick(
     override_value: Option<String>,
     env_key: &str,
-    config: &Config,
+    config: &HashMap<String, String>,
     config_key: &str,
 ) -> Option<String> {
     clean(override_value)
@@ -191,6 +959,75 @@ fn pick(
         })
 }
 
+/// Reads the processes listed in `PROCESSES` and their `PROCESS_<NAME>_*`
+/// settings.
+fn load_processes(config: &HashMap<String, String>) -> Vec<ProcessConfig> {
+    let names = pick(None, "MINION_PROCESSES", config, "PROCESSES").unwrap_or_default();
+    let setting = |name: &str, setting: &str| {
+        let key = process_key(name, setting);
+        pick(None, &format!("MINION_{}", key), config, &key).unwrap_or_default()
+    };
+
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| ProcessConfig {
+            name: name.to_string(),
+            command: setting(name, "COMMAND"),
+            replicas: Some(setting(name, "REPLICAS"))
+                .filter(|replicas| !replicas.is_empty())
+                .unwrap_or_else(|| "1".to_string()),
+            url: setting(name, "URL"),
+            port: setting(name, "PORT"),
+            schedule: setting(name, "SCHEDULE"),
+        })
+        .collect()
+}
+
+/// Reads the routes listed in `ROUTES` and their `ROUTE_<NAME>_*` settings.
+fn load_routes(config: &HashMap<String, String>) -> Vec<RouteConfig> {
+    let names = pick(None, "MINION_ROUTES", config, "ROUTES").unwrap_or_default();
+    let setting = |name: &str, setting: &str| {
+        let key = route_key(name, setting);
+        pick(None, &format!("MINION_{}", key), config, &key).unwrap_or_default()
+    };
+
+    list_items(&names)
+        .map(|name| RouteConfig {
+            name: name.to_string(),
+            host: setting(name, "HOST"),
+            path: setting(name, "PATH"),
+            port: setting(name, "PORT"),
+            priority: setting(name, "PRIORITY"),
+        })
+        .collect()
+}
+
+fn load_middlewares(config: &HashMap<String, String>) -> MiddlewareConfig {
+    let setting =
+        |key: &str| pick(None, &format!("MINION_{}", key), config, key).unwrap_or_default();
+
+    MiddlewareConfig {
+        www_redirect: setting("WWW_REDIRECT"),
+        basic_auth: setting("BASIC_AUTH"),
+        security_headers: setting("SECURITY_HEADERS"),
+        hsts_max_age: setting("HSTS_MAX_AGE"),
+        strip_prefix: setting("STRIP_PREFIX"),
+        ip_allowlist: setting("IP_ALLOWLIST"),
+        rate_limit: setting("RATE_LIMIT"),
+        rate_limit_burst: setting("RATE_LIMIT_BURST"),
+    }
+}
+
+/// Items of a comma-separated setting.
+pub fn list_items(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
 fn pick_secret(override_value: Option<String>, env_key: &str) -> Option<String> {
     clean_secret(override_value).or_else(|| clean_secret(env::var(env_key).ok()))
 }
@@ -255,17 +1092,54 @@ mod tests {
     static ENV_LOCK: Mutex<()> = Mutex::new(());
 
     const ENV_KEYS: &[&str] = &[
+        "MINION_ENVIRONMENT",
         "MINION_VPS_HOST",
         "MINION_APP_NAME",
         "MINION_APP_URL",
         "MINION_APP_PORT",
         "MINION_APP_VOLUMES",
+        "MINION_CERT_RESOLVER",
         "MINION_SSH_USER",
         "MINION_SSH_KEY_PATH",
         "MINION_SSH_PRIVATE_KEY",
         "MINION_SSH_PASSWORD",
         "MINION_SSH_PASSPHRASE",
         "MINION_DOCKER_PLATFORM",
+        "MINION_IMAGE_COMPRESSION",
+        "MINION_IMAGE_SOURCE",
+        "MINION_IMAGE",
+        "MINION_REGISTRY_USERNAME",
+        "MINION_REGISTRY_PASSWORD",
+        "MINION_HEALTHCHECK_TYPE",
+        "MINION_HEALTHCHECK_PATH",
+        "MINION_HEALTHCHECK_WAIT",
+        "MINION_HEALTHCHECK_INTERVAL",
+        "MINION_HEALTHCHECK_TIMEOUT",
+        "MINION_HEALTHCHECK_RETRIES",
+        "MINION_PRE_DEPLOY",
+        "MINION_POST_DEPLOY",
+        "MINION_BACKUP_KEEP",
+        "MINION_BACKUP_SCHEDULE",
+        "MINION_BACKUP_S3_ENDPOINT",
+        "MINION_BACKUP_S3_BUCKET",
+        "MINION_BACKUP_S3_PREFIX",
+        "MINION_BACKUP_S3_KEEP",
+        "MINION_BACKUP_S3_ACCESS_KEY_ID",
+        "MINION_BACKUP_S3_SECRET_ACCESS_KEY",
+        "MINION_SECRETS_FILE",
+        "MINION_SECRETS_DECRYPT",
+        "MINION_AGE_KEY",
+        "MINION_WWW_REDIRECT",
+        "MINION_BASIC_AUTH",
+        "MINION_SECURITY_HEADERS",
+        "MINION_HSTS_MAX_AGE",
+        "MINION_STRIP_PREFIX",
+        "MINION_IP_ALLOWLIST",
+        "MINION_RATE_LIMIT",
+        "MINION_RATE_LIMIT_BURST",
+        "MINION_PROCESSES",
+        "MINION_PROCESS_WORKER_REPLICAS",
+        "MINION_ROUTES",
     ];
 
     struct EnvGuard {
@@ -306,23 +1180,52 @@ mod tests {
 
     fn config_file(content: &str) -> NamedTempFile {
         let file = NamedTempFile::new().unwrap();
-        std::fs::write(file.path(), content).unwrap();
+        std::fs::write(file.path(), format!("version = 1\n{}", content)).unwrap();
         file
     }
 
     fn valid_config() -> AppConfig {
         AppConfig {
+            environment: None,
             host: "example.com".to_string(),
             app_name: "my-app".to_string(),
             app_url: "app.example.com".to_string(),
             app_port: "3000".to_string(),
             app_volumes: String::new(),
+            cert_resolver: "letsencrypt".to_string(),
             ssh_user: "minion".to_string(),
             ssh_key_path: None,
             ssh_private_key: None,
             ssh_password: None,
             ssh_passphrase: None,
             docker_platform: "linux/amd64".to_string(),
+            image_compression: "gzip".to_string(),
+            image_source: "local".to_string(),
+            image: String::new(),
+            registry_username: None,
+            registry_password: None,
+            healthcheck_type: "tcp".to_string(),
+            healthcheck_path: "/".to_string(),
+            healthcheck_wait: "60".to_string(),
+            healthcheck_interval: "10".to_string(),
+            healthcheck_timeout: "5".to_string(),
+            healthcheck_retries: "3".to_string(),
+            pre_deploy: String::new(),
+            post_deploy: String::new(),
+            backup_keep: "7".to_string(),
+            backup_schedule: "0 3 * * *".to_string(),
+            backup_s3_endpoint: String::new(),
+            backup_s3_bucket: String::new(),
+            backup_s3_prefix: String::new(),
+            backup_s3_keep: "30".to_string(),
+            backup_s3_access_key_id: None,
+            backup_s3_secret_access_key: None,
+            secrets_file: ".minion.secrets".to_string(),
+            secrets_decrypt: "local".to_string(),
+            age_key: None,
+            processes: Vec::new(),
+            routes: Vec::new(),
+            middlewares: MiddlewareConfig::default(),
         }
     }
 
@@ -345,10 +1248,10 @@ mod tests {
     }
 
     #[test]
-    fn cli_overrides_beat_env_and_minion_file() {
+    fn cli_overrides_beat_env_and_manifest() {
         let _guard = EnvGuard::new();
         env::set_var("MINION_VPS_HOST", "env-host");
-        let file = config_file("VPS_HOST=file-host\n");
+        let file = config_file("[server]\nhost = \"file-host\"\n");
 
         let config = AppConfig::load_from_file(
             file.path().to_str().unwrap(),
@@ -365,11 +1268,11 @@ mod tests {
     }
 
     #[test]
-    fn env_vars_beat_minion_file() {
+    fn env_vars_beat_manifest() {
         let _guard = EnvGuard::new();
         env::set_var("MINION_APP_NAME", "env-app");
         env::set_var("MINION_DOCKER_PLATFORM", "linux/arm64");
-        let file = config_file("APP_NAME=file-app\nDOCKER_PLATFORM=linux/amd64\n");
+        let file = config_file("[app]\nname = \"file-app\"\n[image]\nplatform = \"linux/amd64\"\n");
 
         let config = AppConfig::load_from_file(
             file.path().to_str().unwrap(),
@@ -384,10 +1287,10 @@ mod tests {
     }
 
     #[test]
-    fn minion_file_is_used_without_cli_or_env_values() {
+    fn manifest_is_used_without_cli_or_env_values() {
         let _guard = EnvGuard::new();
         let file = config_file(
-            "VPS_HOST=file-host\nAPP_NAME=file-app\nAPP_URL=file.example.com\nAPP_PORT=8080\nAPP_VOLUMES=data:/data\nDOCKER_PLATFORM=linux/arm64\n",
+            "[server]\nhost = \"file-host\"\n[app]\nname = \"file-app\"\nurl = [\"file.example.com\"]\nport = 8080\nvolumes = [\"data:/data\"]\n[image]\nplatform = \"linux/arm64\"\n",
         );
 
         let config = AppConfig::load_from_file(
@@ -520,6 +1423,455 @@ mod tests {
             .contains("APP_PORT"));
     }
 
+    #[test]
+    fn healthcheck_defaults_to_tcp_and_switches_to_http_with_a_path() {
+        let _guard = EnvGuard::new();
+        let file = config_file("");
+        let config = AppConfig::load_from_file(
+            file.path().to_str().unwrap(),
+            AppConfigOverrides::default(),
+            false,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(config.healthcheck_type, "tcp");
+        assert_eq!(config.healthcheck_path, "/");
+        assert_eq!(config.healthcheck_wait_secs().unwrap(), 60);
+
+        let file = config_file("[healthcheck]\npath = \"/health\"\nwait = 120\n");
+        let config = AppConfig::load_from_file(
+            file.path().to_str().unwrap(),
+            AppConfigOverrides::default(),
+            false,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(config.healthcheck_type, "http");
+        assert_eq!(config.healthcheck_path, "/health");
+        assert_eq!(config.healthcheck_wait_secs().unwrap(), 120);
+    }
+
+    #[test]
+    fn deploy_config_rejects_invalid_healthcheck_settings() {
+        let mut config = valid_config();
+        config.healthcheck_type = "udp".to_string();
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("HEALTHCHECK_TYPE"));
+
+        let mut config = valid_config();
+        config.healthcheck_path = "health".to_string();
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("HEALTHCHECK_PATH"));
+
+        let mut config = valid_config();
+        config.healthcheck_wait = "soon".to_string();
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("HEALTHCHECK_WAIT"));
+
+        let mut config = valid_config();
+        config.healthcheck_retries = "0".to_string();
+        assert_eq!(
+            config.require_deploy().unwrap_err().to_string(),
+            "HEALTHCHECK_RETRIES must be a positive number"
+        );
+    }
+
+    #[test]
+    fn environment_selects_its_overrides_from_cli_or_env() {
+        let _guard = EnvGuard::new();
+        let file = config_file(
+            "[server]\nhost = \"prod-host\"\n[app]\nname = \"my-app\"\n[environments.staging.server]\nhost = \"staging-host\"\n",
+        );
+        let load = |environment: Option<&str>| {
+            AppConfig::load_from_file(
+                file.path().to_str().unwrap(),
+                AppConfigOverrides {
+                    environment: environment.map(str::to_string),
+                    ..Default::default()
+                },
+                false,
+                false,
+            )
+        };
+
+        let base = load(None).unwrap();
+        assert_eq!(base.environment, None);
+        assert_eq!(
+            (base.host.as_str(), base.app_name.as_str()),
+            ("prod-host", "my-app")
+        );
+
+        let staging = load(Some("staging")).unwrap();
+        assert_eq!(staging.environment.as_deref(), Some("staging"));
+        assert_eq!(staging.host, "staging-host");
+        assert_eq!(staging.app_name, "my-app-staging");
+        assert_eq!(staging.secrets_file, ".minion.staging.secrets");
+
+        env::set_var("MINION_ENVIRONMENT", "staging");
+        assert_eq!(load(None).unwrap().host, "staging-host");
+
+        assert_eq!(
+            load(Some("Staging")).unwrap_err().to_string(),
+            "Environment name \"Staging\" may only contain lowercase letters, numbers, and dashes"
+        );
+    }
+
+    #[test]
+    fn secrets_default_to_local_decryption_of_minion_secrets() {
+        let _guard = EnvGuard::new();
+        env::set_var("MINION_AGE_KEY", "AGE-SECRET-KEY-1TEST");
+        let file = config_file("");
+
+        let config = AppConfig::load_from_file(
+            file.path().to_str().unwrap(),
+            AppConfigOverrides::default(),
+            false,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(config.secrets_file, ".minion.secrets");
+        assert_eq!(config.secrets_decrypt, "local");
+        assert_eq!(config.age_key, Some("AGE-SECRET-KEY-1TEST".to_string()));
+    }
+
+    #[test]
+    fn processes_are_loaded_from_process_keys() {
+        let _guard = EnvGuard::new();
+        env::set_var("MINION_PROCESS_WORKER_REPLICAS", "4");
+        let file = config_file(
+            "[processes.worker]\ncommand = \"bundle exec sidekiq\"\nreplicas = 2\n\n[processes.nightly-report]\ncommand = \"rake report\"\nschedule = \"0 3 * * *\"\n",
+        );
+
+        let config = AppConfig::load_from_file(
+            file.path().to_str().unwrap(),
+            AppConfigOverrides::default(),
+            false,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.processes,
+            vec![
+                ProcessConfig {
+                    name: "worker".to_string(),
+                    command: "bundle exec sidekiq".to_string(),
+                    replicas: "4".to_string(),
+                    ..Default::default()
+                },
+                ProcessConfig {
+                    name: "nightly-report".to_string(),
+                    command: "rake report".to_string(),
+                    replicas: "1".to_string(),
+                    schedule: "0 3 * * *".to_string(),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn deploy_config_rejects_invalid_processes() {
+        let worker = ProcessConfig {
+            name: "worker".to_string(),
+            command: "work".to_string(),
+            replicas: "1".to_string(),
+            ..Default::default()
+        };
+        let mut config = valid_config();
+        config.processes = vec![worker.clone()];
+        assert!(config.require_deploy().is_ok());
+
+        let cases = [
+            (
+                ProcessConfig {
+                    name: "Worker".to_string(),
+                    ..worker.clone()
+                },
+                "lowercase",
+            ),
+            (
+                ProcessConfig {
+                    name: "web".to_string(),
+                    ..worker.clone()
+                },
+                "already used",
+            ),
+            (
+                ProcessConfig {
+                    command: String::new(),
+                    ..worker.clone()
+                },
+                "PROCESS_WORKER_COMMAND is required",
+            ),
+            (
+                ProcessConfig {
+                    replicas: "0".to_string(),
+                    ..worker.clone()
+                },
+                "PROCESS_WORKER_REPLICAS",
+            ),
+            (
+                ProcessConfig {
+                    schedule: "@daily".to_string(),
+                    ..worker.clone()
+                },
+                "five fields",
+            ),
+            (
+                ProcessConfig {
+                    schedule: "0 3 * * *".to_string(),
+                    url: "jobs.example.com".to_string(),
+                    ..worker.clone()
+                },
+                "both a SCHEDULE and a URL",
+            ),
+            (
+                ProcessConfig {
+                    url: "admin.example.com".to_string(),
+                    port: "http".to_string(),
+                    ..worker.clone()
+                },
+                "PROCESS_WORKER_PORT",
+            ),
+        ];
+        for (process, expected) in cases {
+            config.processes = vec![process];
+            let error = config.require_deploy().unwrap_err().to_string();
+            assert!(
+                error.contains(expected),
+                "{} should contain {}",
+                error,
+                expected
+            );
+        }
+
+        config.processes = vec![worker.clone(), worker];
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("already used"));
+    }
+
+    #[test]
+    fn deploy_config_rejects_unknown_image_compression() {
+        let mut config = valid_config();
+        config.image_compression = "bzip2".to_string();
+
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("IMAGE_COMPRESSION"));
+    }
+
+    #[test]
+    fn registry_image_source_requires_image_and_complete_credentials() {
+        let mut config = valid_config();
+        config.image_source = "registry".to_string();
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("IMAGE is required"));
+
+        config.image = "ghcr.io/acme/app:1.2.3".to_string();
+        assert!(config.require_deploy().is_ok());
+
+        config.registry_username = Some("deploy".to_string());
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("MINION_REGISTRY_PASSWORD"));
+
+        config.registry_password = Some("token".to_string());
+        assert!(config.require_deploy().is_ok());
+
+        config.image_source = "ftp".to_string();
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("IMAGE_SOURCE"));
+    }
+
+    #[test]
+    fn deploy_config_rejects_unknown_secrets_decrypt_mode() {
+        let mut config = valid_config();
+        config.secrets_decrypt = "vault".to_string();
+
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .contains("SECRETS_DECRYPT"));
+    }
+
+    #[test]
+    fn wildcard_urls_need_a_dns_cert_resolver() {
+        let mut config = valid_config();
+        config.app_url = "*.preview.example.com".to_string();
+
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .starts_with(
+                "Wildcard domain *.preview.example.com needs a DNS-01 certificate resolver."
+            ));
+
+        config.cert_resolver = "rfc2136".to_string();
+        assert!(config.require_deploy().is_ok());
+
+        config.app_url = "a.*.example.com".to_string();
+        assert!(config.require_deploy().is_err());
+
+        config.cert_resolver = "digitalocean".to_string();
+        assert!(config
+            .require_deploy()
+            .unwrap_err()
+            .to_string()
+            .starts_with("CERT_RESOLVER must be"));
+    }
+
+    #[test]
+    fn routes_are_validated_and_add_their_hosts() {
+        let mut config = valid_config();
+        config.routes = vec![
+            RouteConfig {
+                name: "api".to_string(),
+                path: "/api".to_string(),
+                port: "4000".to_string(),
+                priority: "10".to_string(),
+                ..Default::default()
+            },
+            RouteConfig {
+                name: "metrics".to_string(),
+                host: "metrics.example.com, app.example.com".to_string(),
+                port: "9090".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert!(config.require_deploy().is_ok());
+        assert_eq!(
+            config.hosts(),
+            vec!["app.example.com", "metrics.example.com"]
+        );
+
+        type Change = fn(&mut AppConfig);
+        let cases: [(Change, &str); 5] = [
+            (
+                |config| config.routes[1].name = "api".to_string(),
+                "Route name api is already used by another route or process",
+            ),
+            (
+                |config| config.routes[0].path = String::new(),
+                "Route api needs a ROUTE_API_HOST or a ROUTE_API_PATH",
+            ),
+            (
+                |config| config.routes[0].path = "/api`)".to_string(),
+                "ROUTE_API_PATH must be a path starting with /",
+            ),
+            (
+                |config| config.routes[0].port = "70000".to_string(),
+                "ROUTE_API_PORT must be a valid TCP port",
+            ),
+            (
+                |config| config.routes[0].priority = "high".to_string(),
+                "ROUTE_API_PRIORITY must be a positive number",
+            ),
+        ];
+        for (change, message) in cases {
+            let mut broken = config.clone();
+            change(&mut broken);
+            assert!(
+                broken
+                    .require_deploy()
+                    .unwrap_err()
+                    .to_string()
+                    .starts_with(message),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn middlewares_are_validated() {
+        let mut config = valid_config();
+        config.app_url = "example.com,www.example.com".to_string();
+        config.middlewares = MiddlewareConfig {
+            www_redirect: "non-www".to_string(),
+            basic_auth: "admin:$2y$05$abc,ops:{SHA}xyz=".to_string(),
+            security_headers: "true".to_string(),
+            hsts_max_age: "0".to_string(),
+            strip_prefix: "/api,/v1".to_string(),
+            ip_allowlist: "10.0.0.0/8,2001:db8::/32,203.0.113.7".to_string(),
+            rate_limit: "20".to_string(),
+            rate_limit_burst: "40".to_string(),
+        };
+        assert!(config.require_deploy().is_ok());
+
+        type Change = fn(&mut AppConfig);
+        let cases: [(Change, &str); 7] = [
+            (
+                |config| config.app_url = "example.com".to_string(),
+                "WWW_REDIRECT needs a host and its www. host in APP_URL",
+            ),
+            (
+                |config| config.middlewares.basic_auth = "admin:secret".to_string(),
+                "BASIC_AUTH entries must be user:hash pairs",
+            ),
+            (
+                |config| config.middlewares.strip_prefix = "api".to_string(),
+                "STRIP_PREFIX entry api must be a path starting with /",
+            ),
+            (
+                |config| config.middlewares.ip_allowlist = "10.0.0.0/33".to_string(),
+                "IP_ALLOWLIST entry 10.0.0.0/33 must be an IP address or CIDR range",
+            ),
+            (
+                |config| config.middlewares.rate_limit = "0".to_string(),
+                "RATE_LIMIT must be a positive number of requests",
+            ),
+            (
+                |config| config.middlewares.rate_limit = String::new(),
+                "RATE_LIMIT_BURST needs RATE_LIMIT",
+            ),
+            (
+                |config| config.app_name = "my.app".to_string(),
+                "APP_NAME may not contain dots when middlewares are configured",
+            ),
+        ];
+        for (change, message) in cases {
+            let mut broken = config.clone();
+            change(&mut broken);
+            assert!(
+                broken
+                    .require_deploy()
+                    .unwrap_err()
+                    .to_string()
+                    .starts_with(message),
+                "{}",
+                message
+            );
+        }
+    }
+
     #[test]
     fn app_name_rejects_shell_unsafe_characters() {
         let mut config = valid_config();
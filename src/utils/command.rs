@@ -1,10 +1,30 @@
 use anyhow::Result;
-use std::process::Command;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
 
 pub struct CommandExecutor;
 
 pub trait LocalCommandRunner {
     fn execute(&self, command: &str, args: &[&str]) -> Result<(String, i32)>;
+
+    /// Runs `command` with extra environment variables and returns stdout and
+    /// stderr separately, for commands whose stdout is data rather than a log.
+    fn execute_with_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        env: &[(&str, &str)],
+    ) -> Result<(String, String, i32)>;
+
+    /// Runs `command` and hands its stdout to `consumer` while it is still
+    /// being produced. Returns stderr and the exit status.
+    fn execute_piped(
+        &self,
+        command: &str,
+        args: &[&str],
+        consumer: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<(String, i32)>;
 }
 
 impl CommandExecutor {
@@ -29,4 +49,58 @@ impl LocalCommandRunner for CommandExecutor {
 
         Ok((combined_output, exit_status))
     }
+
+    fn execute_with_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        env: &[(&str, &str)],
+    ) -> Result<(String, String, i32)> {
+        let output = Command::new(command)
+            .args(args)
+            .envs(env.iter().copied())
+            .output()?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let exit_status = output.status.code().unwrap_or(-1);
+
+        Ok((stdout, stderr, exit_status))
+    }
+
+    fn execute_piped(
+        &self,
+        command: &str,
+        args: &[&str],
+        consumer: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<(String, i32)> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Drain stderr on its own thread so a chatty command cannot block on
+        // a full pipe while stdout is being consumed.
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr_reader = thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            output
+        });
+
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        if let Err(error) = consumer(&mut stdout) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(error);
+        }
+        drop(stdout);
+
+        let exit_status = child.wait()?.code().unwrap_or(-1);
+        let stderr = stderr_reader.join().unwrap_or_default();
+
+        Ok((stderr, exit_status))
+    }
 }
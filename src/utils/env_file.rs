@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+/// `KEY=VALUE` variables in the format Docker Compose reads from `env_file`.
+///
+/// Values are written single-quoted, since Compose expands `$VAR` and cuts
+/// at ` #` in bare values but takes single-quoted ones literally.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvFile {
+    values: BTreeMap<String, String>,
+}
+
+impl EnvFile {
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut env_file = EnvFile::default();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) =
+                parse_assignment(line).map_err(|error| anyhow!("Line {}: {}", index + 1, error))?;
+            env_file.values.insert(key, value);
+        }
+
+        Ok(env_file)
+    }
+
+    pub fn set(&mut self, key: String, value: String) {
+        self.values.insert(key, value);
+    }
+
+    pub fn unset(&mut self, key: &str) -> bool {
+        self.values.remove(key).is_some()
+    }
+
+    pub fn merge(&mut self, other: EnvFile) {
+        self.values.extend(other.values);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.values.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        self.values
+            .iter()
+            .map(|(key, value)| format!("{}='{}'\n", key, value.replace('\'', "\\'")))
+            .collect()
+    }
+}
+
+/// Parses a single `KEY=VALUE` assignment, dropping one pair of matching
+/// surrounding quotes from the value. Inside single quotes `\'` stands for a
+/// quote, as written by [`EnvFile::render`].
+pub fn parse_assignment(assignment: &str) -> Result<(String, String)> {
+    let (key, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected KEY=VALUE, got {}", assignment))?;
+    let key = key.trim();
+    validate_env_key(key)?;
+
+    let value = value.trim();
+    let value = match value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+    {
+        Some(quoted) => quoted.replace("\\'", "'"),
+        None => value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value)
+            .to_string(),
+    };
+    if value.contains('\n') {
+        return Err(anyhow!("{} must not contain a newline", key));
+    }
+
+    Ok((key.to_string(), value))
+}
+
+pub fn validate_env_key(key: &str) -> Result<()> {
+    let mut chars = key.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_');
+    if valid_start && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "Invalid variable name {:?}: use letters, numbers, and underscores",
+        key
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_skips_comments_and_strips_quotes() {
+        let env_file = EnvFile::parse(
+            "# database\nDATABASE_URL=\"postgres://db/app\"\n\nexport SECRET_KEY='abc'\nEMPTY=\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            env_file.render(),
+            "DATABASE_URL='postgres://db/app'\nEMPTY=''\nSECRET_KEY='abc'\n"
+        );
+    }
+
+    #[test]
+    fn parse_reports_line_numbers() {
+        let error = EnvFile::parse("A=1\nnot an assignment\n").unwrap_err();
+
+        assert!(error.to_string().starts_with("Line 2:"));
+    }
+
+    #[test]
+    fn render_is_sorted_and_round_trips() {
+        let mut env_file = EnvFile::default();
+        env_file.set("B".to_string(), "two".to_string());
+        env_file.set("A".to_string(), "one=1".to_string());
+
+        let rendered = env_file.render();
+
+        assert_eq!(rendered, "A='one=1'\nB='two'\n");
+        assert_eq!(EnvFile::parse(&rendered).unwrap(), env_file);
+    }
+
+    #[test]
+    fn values_compose_would_change_are_quoted_and_round_trip() {
+        let mut env_file = EnvFile::default();
+        env_file.set("PASSWORD".to_string(), "a$b #c".to_string());
+        env_file.set("QUOTES".to_string(), "it's \"fine\"".to_string());
+        env_file.set("QUERY".to_string(), "a=1&b=${HOME}".to_string());
+        env_file.set("TRAILING".to_string(), "ends with \\".to_string());
+
+        let rendered = env_file.render();
+
+        assert_eq!(
+            rendered,
+            "PASSWORD='a$b #c'\nQUERY='a=1&b=${HOME}'\nQUOTES='it\\'s \"fine\"'\nTRAILING='ends with \\'\n"
+        );
+        assert_eq!(EnvFile::parse(&rendered).unwrap(), env_file);
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        assert!(parse_assignment("1ABC=value").is_err());
+        assert!(parse_assignment("MY-KEY=value").is_err());
+        assert!(parse_assignment("=value").is_err());
+        assert!(parse_assignment("_OK=value").is_ok());
+    }
+}
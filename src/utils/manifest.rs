@@ -0,0 +1,996 @@
+//! `minion.toml`, the project manifest.
+//!
+//! The file is read with `toml`, keeping the position of every key so errors
+//! can point at a line. Every value is checked against the schema below and
+//! mapped onto the flat keys `AppConfig` resolves, so `APP_PORT` in an old
+//! `.minion` file and `port` under `[app]` mean the same thing. Edits go
+//! through `toml_edit`, which keeps comments and formatting intact.
+
+use anyhow::{anyhow, Result};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use toml::{Spanned, Value};
+use toml_edit::{DocumentMut, Item};
+
+use super::Config;
+
+pub const MANIFEST_FILE: &str = "minion.toml";
+pub const LEGACY_CONFIG_FILE: &str = ".minion";
+const MANIFEST_VERSION: i64 = 1;
+const PROCESSES_TABLE: &str = "processes";
+const ROUTES_TABLE: &str = "routes";
+const ENVIRONMENTS_TABLE: &str = "environments";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Integer,
+    /// A list of strings, or one comma-separated string.
+    List,
+    Boolean,
+}
+
+/// Manifest fields and the flat keys they map to.
+const FIELDS: &[(&str, &str, &str, Kind)] = &[
+    ("app", "name", "APP_NAME", Kind::Text),
+    ("app", "url", "APP_URL", Kind::List),
+    ("app", "port", "APP_PORT", Kind::Integer),
+    ("app", "volumes", "APP_VOLUMES", Kind::List),
+    ("app", "cert_resolver", "CERT_RESOLVER", Kind::Text),
+    ("server", "host", "VPS_HOST", Kind::Text),
+    ("server", "ssh_user", "SSH_USER", Kind::Text),
+    ("server", "ssh_key_path", "SSH_KEY_PATH", Kind::Text),
+    ("image", "source", "IMAGE_SOURCE", Kind::Text),
+    ("image", "name", "IMAGE", Kind::Text),
+    ("image", "platform", "DOCKER_PLATFORM", Kind::Text),
+    ("image", "compression", "IMAGE_COMPRESSION", Kind::Text),
+    (
+        "image",
+        "registry_username",
+        "REGISTRY_USERNAME",
+        Kind::Text,
+    ),
+    ("healthcheck", "type", "HEALTHCHECK_TYPE", Kind::Text),
+    ("healthcheck", "path", "HEALTHCHECK_PATH", Kind::Text),
+    ("healthcheck", "wait", "HEALTHCHECK_WAIT", Kind::Integer),
+    (
+        "healthcheck",
+        "interval",
+        "HEALTHCHECK_INTERVAL",
+        Kind::Integer,
+    ),
+    (
+        "healthcheck",
+        "timeout",
+        "HEALTHCHECK_TIMEOUT",
+        Kind::Integer,
+    ),
+    (
+        "healthcheck",
+        "retries",
+        "HEALTHCHECK_RETRIES",
+        Kind::Integer,
+    ),
+    ("hooks", "pre_deploy", "PRE_DEPLOY", Kind::Text),
+    ("hooks", "post_deploy", "POST_DEPLOY", Kind::Text),
+    ("backup", "keep", "BACKUP_KEEP", Kind::Integer),
+    ("backup", "schedule", "BACKUP_SCHEDULE", Kind::Text),
+    ("backup", "s3_endpoint", "BACKUP_S3_ENDPOINT", Kind::Text),
+    ("backup", "s3_bucket", "BACKUP_S3_BUCKET", Kind::Text),
+    ("backup", "s3_prefix", "BACKUP_S3_PREFIX", Kind::Text),
+    ("backup", "s3_keep", "BACKUP_S3_KEEP", Kind::Integer),
+    ("secrets", "file", "SECRETS_FILE", Kind::Text),
+    ("secrets", "decrypt", "SECRETS_DECRYPT", Kind::Text),
+    ("middlewares", "www_redirect", "WWW_REDIRECT", Kind::Text),
+    ("middlewares", "basic_auth", "BASIC_AUTH", Kind::List),
+    (
+        "middlewares",
+        "security_headers",
+        "SECURITY_HEADERS",
+        Kind::Boolean,
+    ),
+    ("middlewares", "hsts_max_age", "HSTS_MAX_AGE", Kind::Integer),
+    ("middlewares", "strip_prefix", "STRIP_PREFIX", Kind::List),
+    ("middlewares", "ip_allowlist", "IP_ALLOWLIST", Kind::List),
+    ("middlewares", "rate_limit", "RATE_LIMIT", Kind::Integer),
+    (
+        "middlewares",
+        "rate_limit_burst",
+        "RATE_LIMIT_BURST",
+        Kind::Integer,
+    ),
+];
+
+/// Fields of a `[processes.<name>]` table and their `PROCESS_<NAME>_*` suffix.
+const PROCESS_FIELDS: &[(&str, &str, Kind)] = &[
+    ("command", "COMMAND", Kind::Text),
+    ("replicas", "REPLICAS", Kind::Integer),
+    ("url", "URL", Kind::List),
+    ("port", "PORT", Kind::Integer),
+    ("schedule", "SCHEDULE", Kind::Text),
+];
+
+/// Fields of a `[routes.<name>]` table and their `ROUTE_<NAME>_*` suffix.
+const ROUTE_FIELDS: &[(&str, &str, Kind)] = &[
+    ("host", "HOST", Kind::List),
+    ("path", "PATH", Kind::Text),
+    ("port", "PORT", Kind::Integer),
+    ("priority", "PRIORITY", Kind::Integer),
+];
+
+/// A parsed TOML node. Tables keep their keys with positions, so errors can
+/// name the line a key is on.
+enum Node {
+    Table(Vec<(Spanned<String>, Node)>),
+    Value(Value),
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a TOML value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Node, E> {
+        Ok(Node::Value(Value::Boolean(value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Node, E> {
+        Ok(Node::Value(Value::Integer(value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Node, E> {
+        i64::try_from(value)
+            .map(|value| Node::Value(Value::Integer(value)))
+            .map_err(|_| E::custom("integer out of range"))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Node, E> {
+        Ok(Node::Value(Value::Float(value)))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Node, E> {
+        Ok(Node::Value(Value::String(value.to_string())))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Node::Value(Value::Array(items)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key()? {
+            entries.push((key, map.next_value()?));
+        }
+        Ok(Node::Table(entries))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    table: String,
+    key: String,
+    value: Value,
+    /// Zero-based line of the key.
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    path: String,
+    content: String,
+    /// Tables, including implicit ones like `processes`, with the zero-based
+    /// line their name first appears on.
+    tables: Vec<(String, usize)>,
+    entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Loads the project's `minion.toml`. A project that still has a
+    /// `.minion` file is read from it without touching any files. Without
+    /// either file, an empty manifest is returned.
+    pub fn open_project() -> Result<Self> {
+        Self::open(MANIFEST_FILE, LEGACY_CONFIG_FILE, false)
+    }
+
+    /// Like `open_project`, but writes a `.minion` file's settings to
+    /// `minion.toml` and keeps the old file as `.minion.bak`. Only `init` and
+    /// `deploy` migrate.
+    pub fn migrate_project() -> Result<Self> {
+        Self::open(MANIFEST_FILE, LEGACY_CONFIG_FILE, true)
+    }
+
+    fn open(path: &str, legacy_path: &str, migrate: bool) -> Result<Self> {
+        if Path::new(path).exists() {
+            if Path::new(legacy_path).exists() {
+                println!(
+                    "! Both {} and {} exist; {} is ignored",
+                    path, legacy_path, legacy_path
+                );
+            }
+            return Self::load(path);
+        }
+
+        if !Path::new(legacy_path).exists() {
+            return Self::parse(path, &format!("version = {}\n", MANIFEST_VERSION));
+        }
+
+        let manifest = Self::from_legacy(&Config::new(legacy_path)?, path)?;
+        if !migrate {
+            return Ok(manifest);
+        }
+
+        manifest.save()?;
+        let backup = format!("{}.bak", legacy_path);
+        fs::rename(legacy_path, &backup)?;
+        println!(
+            "✓ Migrated {} to {} (the old file was kept as {})",
+            legacy_path, path, backup
+        );
+        Ok(manifest)
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| anyhow!("Failed to read {}: {}", path, error))?;
+        Self::parse(path, &content)
+    }
+
+    /// Converts a flat `.minion` file into an in-memory manifest for `path`.
+    pub fn from_legacy(config: &Config, path: &str) -> Result<Self> {
+        let mut manifest = Self::parse(path, &format!("version = {}\n", MANIFEST_VERSION))?;
+        for (_, _, flat_key, _) in FIELDS {
+            if let Some(value) = config.get(flat_key) {
+                manifest.set(flat_key, value)?;
+            }
+        }
+
+        manifest.migrate_tables(
+            config,
+            "PROCESSES",
+            PROCESSES_TABLE,
+            PROCESS_FIELDS,
+            process_key,
+        )?;
+        manifest.migrate_tables(config, "ROUTES", ROUTES_TABLE, ROUTE_FIELDS, route_key)?;
+
+        Ok(manifest)
+    }
+
+    /// Copies the named tables listed in `list_key`, e.g. `PROCESSES`, with
+    /// the settings `flat_key` finds for each of them.
+    fn migrate_tables(
+        &mut self,
+        config: &Config,
+        list_key: &str,
+        tables: &str,
+        fields: &[(&str, &str, Kind)],
+        flat_key: fn(&str, &str) -> String,
+    ) -> Result<()> {
+        let names = config.get(list_key).cloned().unwrap_or_default();
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let table = format!("{}.{}", tables, name);
+            for (key, setting, kind) in fields {
+                if let Some(value) = config.get(&flat_key(name, setting)) {
+                    self.set_field(&table, key, *kind, value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse(path: &str, content: &str) -> Result<Self> {
+        let root = toml::from_str::<Node>(content).map_err(|error| {
+            // `toml` hands dates to serde as a private one-key table, which
+            // no manifest field accepts.
+            let message = if error.message().contains("toml_private_datetime") {
+                "dates and times are not supported".to_string()
+            } else {
+                error.message().trim().replace('\n', "; ")
+            };
+            match error.span() {
+                Some(span) => anyhow!("{}:{}: {}", path, line_of(content, span.start) + 1, message),
+                None => anyhow!("{}: {}", path, message),
+            }
+        })?;
+
+        let mut manifest = Manifest {
+            path: path.to_string(),
+            content: content.to_string(),
+            tables: Vec::new(),
+            entries: Vec::new(),
+        };
+        if let Node::Table(entries) = root {
+            manifest.collect("", entries);
+        }
+        manifest.tables.sort_by_key(|(_, line)| *line);
+        manifest.entries.sort_by_key(|entry| entry.line);
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Records the values and nested tables of `table`.
+    fn collect(&mut self, table: &str, entries: Vec<(Spanned<String>, Node)>) {
+        for (key, node) in entries {
+            let line = line_of(&self.content, key.span().start);
+            let key = key.into_inner();
+            match node {
+                Node::Table(entries) => {
+                    let name = if table.is_empty() {
+                        key
+                    } else {
+                        format!("{}.{}", table, key)
+                    };
+                    if !self.tables.iter().any(|(existing, _)| *existing == name) {
+                        self.tables.push((name.clone(), line));
+                    }
+                    self.collect(&name, entries);
+                }
+                Node::Value(value) => self.entries.push(Entry {
+                    table: table.to_string(),
+                    key,
+                    value,
+                    line,
+                }),
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let error =
+            |line: usize, message: String| anyhow!("{}:{}: {}", self.path, line + 1, message);
+
+        for (table, line) in &self.tables {
+            if table != PROCESSES_TABLE
+                && table != ROUTES_TABLE
+                && table != ENVIRONMENTS_TABLE
+                && !is_known_table(table)
+            {
+                return Err(error(*line, format!("unknown table [{}]", table)));
+            }
+        }
+
+        let mut version = None;
+        for entry in &self.entries {
+            if entry.table.is_empty() && entry.key == "version" {
+                version = Some(entry);
+                continue;
+            }
+
+            let kind = field_kind(&entry.table, &entry.key).ok_or_else(|| {
+                let place = if entry.table.is_empty() {
+                    "at the top level".to_string()
+                } else {
+                    format!("in [{}]", entry.table)
+                };
+                error(entry.line, format!("unknown key `{}` {}", entry.key, place))
+            })?;
+            check_kind(&entry.value, kind).map_err(|expected| {
+                error(
+                    entry.line,
+                    format!(
+                        "{} must be {}",
+                        field_name(&entry.table, &entry.key),
+                        expected
+                    ),
+                )
+            })?;
+        }
+
+        match version {
+            Some(Entry {
+                value: Value::Integer(MANIFEST_VERSION),
+                ..
+            }) => Ok(()),
+            Some(entry) => Err(error(
+                entry.line,
+                format!(
+                    "unsupported manifest version; this minion understands version = {}",
+                    MANIFEST_VERSION
+                ),
+            )),
+            None => Err(anyhow!(
+                "{}: `version = {}` is required at the top of the file",
+                self.path,
+                MANIFEST_VERSION
+            )),
+        }
+    }
+
+    /// The manifest's values under their flat `.minion` key names. Lists are
+    /// joined with commas.
+    pub fn values(&self) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        let mut processes = Vec::new();
+        let mut routes = Vec::new();
+
+        for entry in &self.entries {
+            if let Some(flat_key) = FIELDS
+                .iter()
+                .find(|(table, key, _, _)| *table == entry.table && *key == entry.key)
+                .map(|(_, _, flat_key, _)| flat_key.to_string())
+            {
+                values.insert(flat_key, flat_value(&entry.value));
+            } else if let Some(name) = process_name(&entry.table) {
+                if let Some((_, setting, _)) =
+                    PROCESS_FIELDS.iter().find(|(key, _, _)| *key == entry.key)
+                {
+                    values.insert(process_key(name, setting), flat_value(&entry.value));
+                }
+            } else if let Some(name) = route_name(&entry.table) {
+                if let Some((_, setting, _)) =
+                    ROUTE_FIELDS.iter().find(|(key, _, _)| *key == entry.key)
+                {
+                    values.insert(route_key(name, setting), flat_value(&entry.value));
+                }
+            }
+        }
+
+        for (table, _) in &self.tables {
+            if let Some(name) = process_name(table) {
+                processes.push(name.to_string());
+            } else if let Some(name) = route_name(table) {
+                routes.push(name.to_string());
+            }
+        }
+        if !processes.is_empty() {
+            values.insert("PROCESSES".to_string(), processes.join(","));
+        }
+        if !routes.is_empty() {
+            values.insert("ROUTES".to_string(), routes.join(","));
+        }
+
+        values
+    }
+
+    /// Names of the environments declared under `[environments.<name>]`.
+    pub fn environments(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for (table, _) in &self.tables {
+            if let Some((name, _)) = environment_table(table) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        names
+    }
+
+    /// Like `values`, with the overrides of `environment` applied on top.
+    /// Unless the environment names the app itself, the app name gets the
+    /// environment as a suffix so its directory and containers on the VPS do
+    /// not collide with other environments. Secrets default to
+    /// `.minion.<environment>.secrets`.
+    pub fn values_for(&self, environment: Option<&str>) -> Result<HashMap<String, String>> {
+        let Some(environment) = environment else {
+            return Ok(self.values());
+        };
+
+        let environments = self.environments();
+        if !environments.contains(&environment) {
+            return Err(if environments.is_empty() {
+                anyhow!(
+                    "Unknown environment {}. {} defines no [environments.<name>] tables",
+                    environment,
+                    self.path
+                )
+            } else {
+                anyhow!(
+                    "Unknown environment {}. {} defines: {}",
+                    environment,
+                    self.path,
+                    environments.join(", ")
+                )
+            });
+        }
+
+        Ok(self.defaults_for(environment))
+    }
+
+    /// What `environment` resolves to without checking that it is declared,
+    /// so `init` can offer defaults for an environment it is about to add.
+    pub fn defaults_for(&self, environment: &str) -> HashMap<String, String> {
+        let mut values = self.values();
+        let mut overridden = Vec::new();
+        for entry in &self.entries {
+            let Some((name, table)) = environment_table(&entry.table) else {
+                continue;
+            };
+            if name != environment {
+                continue;
+            }
+            if let Some((_, _, flat_key, _)) = FIELDS
+                .iter()
+                .find(|(field_table, key, _, _)| *field_table == table && *key == entry.key)
+            {
+                values.insert(flat_key.to_string(), flat_value(&entry.value));
+                overridden.push(*flat_key);
+            }
+        }
+
+        if !overridden.contains(&"APP_NAME") {
+            if let Some(name) = values.get("APP_NAME").cloned() {
+                values.insert("APP_NAME".to_string(), format!("{}-{}", name, environment));
+            }
+        }
+        if !overridden.contains(&"SECRETS_FILE") {
+            values.insert(
+                "SECRETS_FILE".to_string(),
+                format!(".minion.{}.secrets", environment),
+            );
+        }
+
+        values
+    }
+
+    /// Sets the field behind a flat key such as `APP_PORT`, editing its line
+    /// in place or adding it to its table.
+    pub fn set(&mut self, flat_key: &str, value: &str) -> Result<()> {
+        self.set_for(None, flat_key, value)
+    }
+
+    /// Like `set`, but writes the value into an environment's overrides when
+    /// `environment` is given.
+    pub fn set_for(
+        &mut self,
+        environment: Option<&str>,
+        flat_key: &str,
+        value: &str,
+    ) -> Result<()> {
+        let (table, key, _, kind) = FIELDS
+            .iter()
+            .find(|(_, _, field_key, _)| *field_key == flat_key)
+            .ok_or_else(|| anyhow!("{} has no place in {}", flat_key, MANIFEST_FILE))?;
+        let table = match environment {
+            Some(environment) => format!("{}.{}.{}", ENVIRONMENTS_TABLE, environment, table),
+            None => table.to_string(),
+        };
+        self.set_field(&table, key, *kind, value)
+    }
+
+    fn set_field(&mut self, table: &str, key: &str, kind: Kind, value: &str) -> Result<()> {
+        let mut document = self
+            .content
+            .parse::<DocumentMut>()
+            .map_err(|error| anyhow!("{}: {}", self.path, error))?;
+
+        let mut item = document.as_item_mut();
+        for name in table.split('.') {
+            let mut new_table = toml_edit::Table::new();
+            new_table.set_implicit(true);
+            item = item
+                .as_table_like_mut()
+                .ok_or_else(|| anyhow!("{}: {} is not a table", self.path, table))?
+                .entry(name)
+                .or_insert(Item::Table(new_table));
+        }
+        let fields = item
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("{}: {} is not a table", self.path, table))?;
+
+        // Keep the comments around a replaced value.
+        let mut value = render_value(value, kind);
+        if let Some(existing) = fields.get(key).and_then(Item::as_value) {
+            *value.decor_mut() = existing.decor().clone();
+        }
+        fields.insert(key, Item::Value(value));
+
+        *self = Self::parse(&self.path, &document.to_string())?;
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, &self.content)
+            .map_err(|error| anyhow!("Failed to write {}: {}", self.path, error))
+    }
+}
+
+/// Flat key for one of a process's settings, e.g. `PROCESS_WORKER_COMMAND`.
+pub(super) fn process_key(name: &str, setting: &str) -> String {
+    format!(
+        "PROCESS_{}_{}",
+        name.to_ascii_uppercase().replace('-', "_"),
+        setting
+    )
+}
+
+fn process_name(table: &str) -> Option<&str> {
+    table
+        .strip_prefix(PROCESSES_TABLE)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .filter(|name| !name.contains('.'))
+}
+
+/// Flat key for one of a route's settings, e.g. `ROUTE_API_PATH`.
+pub(super) fn route_key(name: &str, setting: &str) -> String {
+    format!(
+        "ROUTE_{}_{}",
+        name.to_ascii_uppercase().replace('-', "_"),
+        setting
+    )
+}
+
+fn route_name(table: &str) -> Option<&str> {
+    table
+        .strip_prefix(ROUTES_TABLE)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .filter(|name| !name.contains('.'))
+}
+
+/// Splits `environments.<name>.<table>` into the environment name and the
+/// table it overrides, which is empty for `[environments.<name>]` itself.
+fn environment_table(table: &str) -> Option<(&str, &str)> {
+    let rest = table
+        .strip_prefix(ENVIRONMENTS_TABLE)
+        .and_then(|rest| rest.strip_prefix('.'))?;
+    Some(rest.split_once('.').unwrap_or((rest, "")))
+}
+
+fn is_known_table(table: &str) -> bool {
+    if let Some((_, inner)) = environment_table(table) {
+        return inner.is_empty() || FIELDS.iter().any(|(name, _, _, _)| *name == inner);
+    }
+
+    FIELDS.iter().any(|(name, _, _, _)| *name == table)
+        || process_name(table).is_some()
+        || route_name(table).is_some()
+}
+
+fn field_kind(table: &str, key: &str) -> Option<Kind> {
+    let table = match environment_table(table) {
+        Some((_, inner)) => inner,
+        None => table,
+    };
+    if process_name(table).is_some() {
+        return PROCESS_FIELDS
+            .iter()
+            .find(|(field, _, _)| *field == key)
+            .map(|(_, _, kind)| *kind);
+    }
+    if route_name(table).is_some() {
+        return ROUTE_FIELDS
+            .iter()
+            .find(|(field, _, _)| *field == key)
+            .map(|(_, _, kind)| *kind);
+    }
+
+    FIELDS
+        .iter()
+        .find(|(name, field, _, _)| *name == table && *field == key)
+        .map(|(_, _, _, kind)| *kind)
+}
+
+fn field_name(table: &str, key: &str) -> String {
+    format!("{}.{}", table, key)
+}
+
+fn check_kind(value: &Value, kind: Kind) -> std::result::Result<(), &'static str> {
+    match (kind, value) {
+        (Kind::Text, Value::String(_))
+        | (Kind::Integer, Value::Integer(_))
+        | (Kind::Boolean, Value::Boolean(_)) => Ok(()),
+        (Kind::List, Value::String(_)) => Ok(()),
+        (Kind::List, Value::Array(items)) if items.iter().all(Value::is_str) => Ok(()),
+        (Kind::Text, _) => Err("a string"),
+        (Kind::Integer, _) => Err("an integer"),
+        (Kind::Boolean, _) => Err("true or false"),
+        (Kind::List, _) => Err("a string or an array of strings"),
+    }
+}
+
+fn flat_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(flat_value).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+fn render_value(value: &str, kind: Kind) -> toml_edit::Value {
+    match kind {
+        Kind::Integer => match value.parse::<i64>() {
+            Ok(number) => number.into(),
+            Err(_) => value.into(),
+        },
+        Kind::Boolean => match value {
+            "true" => true.into(),
+            "false" => false.into(),
+            _ => value.into(),
+        },
+        Kind::List => value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect::<toml_edit::Array>()
+            .into(),
+        Kind::Text => value.into(),
+    }
+}
+
+/// Zero-based line of the byte `offset` in `content`.
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Manifest> {
+        Manifest::parse("minion.toml", content)
+    }
+
+    #[test]
+    fn values_map_tables_onto_flat_keys() {
+        let manifest = parse(
+            r#"version = 1
+
+[app]
+name = "my-app"   # trailing comment
+url = [
+  "app.example.com",
+  "www.example.com",  # with a trailing comma
+]
+port = 3000
+volumes = "data:/app/data"
+
+[healthcheck]
+path = '/health#live'
+
+[processes.worker]
+command = "bundle exec sidekiq"
+replicas = 2
+
+[processes.nightly-report]
+command = "rake report"
+schedule = "0 3 * * *"
+
+[routes.api]
+path = "/api"
+port = 4000
+priority = 10
+
+[middlewares]
+security_headers = true
+ip_allowlist = ["10.0.0.0/8", "203.0.113.7"]
+"#,
+        )
+        .unwrap();
+
+        let values = manifest.values();
+        assert_eq!(values["APP_NAME"], "my-app");
+        assert_eq!(values["APP_URL"], "app.example.com,www.example.com");
+        assert_eq!(values["APP_PORT"], "3000");
+        assert_eq!(values["APP_VOLUMES"], "data:/app/data");
+        assert_eq!(values["HEALTHCHECK_PATH"], "/health#live");
+        assert_eq!(values["PROCESSES"], "worker,nightly-report");
+        assert_eq!(values["PROCESS_WORKER_REPLICAS"], "2");
+        assert_eq!(values["PROCESS_NIGHTLY_REPORT_SCHEDULE"], "0 3 * * *");
+        assert_eq!(values["ROUTES"], "api");
+        assert_eq!(values["ROUTE_API_PATH"], "/api");
+        assert_eq!(values["ROUTE_API_PORT"], "4000");
+        assert_eq!(values["SECURITY_HEADERS"], "true");
+        assert_eq!(values["IP_ALLOWLIST"], "10.0.0.0/8,203.0.113.7");
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_line() {
+        let cases = [
+            (
+                "version = 1\n[app]\nprot = 3000\n",
+                "minion.toml:3: unknown key `prot` in [app]",
+            ),
+            (
+                "version = 1\n[app]\nport = \"3000\"\n",
+                "minion.toml:3: app.port must be an integer",
+            ),
+            (
+                "version = 1\n[middlewares]\nsecurity_headers = \"yes\"\n",
+                "minion.toml:3: middlewares.security_headers must be true or false",
+            ),
+            (
+                "version = 1\n\n[deploy]\n",
+                "minion.toml:3: unknown table [deploy]",
+            ),
+            (
+                "version = 1\nname = \"x\"\n",
+                "minion.toml:2: unknown key `name` at the top level",
+            ),
+            (
+                "version = 1\n[app]\nname = my-app\n",
+                "minion.toml:3: invalid string; expected",
+            ),
+            (
+                "version = 1\n[app]\nname = \"a\nport = 1\n",
+                "minion.toml:3: invalid basic string",
+            ),
+            (
+                "version = 1\n[app]\nport = 1\nport = 2\n",
+                "minion.toml:4: duplicate key `port`",
+            ),
+            (
+                "version = 1\n[app]\nname = 1979-05-27\n",
+                "minion.toml:3: dates and times are not supported",
+            ),
+            (
+                "version = 2\n",
+                "minion.toml:1: unsupported manifest version",
+            ),
+            ("[app]\nport = 1\n", "`version = 1` is required"),
+        ];
+
+        for (content, expected) in cases {
+            let error = parse(content).unwrap_err().to_string();
+            assert!(
+                error.starts_with(expected) || error.contains(expected),
+                "{}",
+                error
+            );
+        }
+        assert_eq!(
+            parse("version = 1\napp.port = 1\n").unwrap().values()["APP_PORT"],
+            "1"
+        );
+    }
+
+    #[test]
+    fn set_edits_existing_lines_and_keeps_comments() {
+        let mut manifest = parse(
+            "# deployment settings\nversion = 1\n\n[app]\nname = \"my-app\" # the app\nurl = [\n  \"old.example.com\",\n]\n",
+        )
+        .unwrap();
+
+        manifest
+            .set("APP_URL", "a.example.com, b.example.com")
+            .unwrap();
+        manifest.set("APP_PORT", "8080").unwrap();
+        manifest.set("VPS_HOST", "203.0.113.10").unwrap();
+        manifest.set("APP_NAME", "shop").unwrap();
+
+        assert_eq!(
+            manifest.content,
+            "# deployment settings\nversion = 1\n\n[app]\nname = \"shop\" # the app\nurl = [\"a.example.com\", \"b.example.com\"]\nport = 8080\n\n[server]\nhost = \"203.0.113.10\"\n"
+        );
+    }
+
+    #[test]
+    fn environments_override_the_base_and_namespace_the_app() {
+        let manifest = parse(
+            r#"version = 1
+
+[app]
+name = "my-app"
+url = ["app.example.com"]
+port = 3000
+
+[server]
+host = "203.0.113.10"
+
+[environments.staging.app]
+url = ["staging.example.com"]
+
+[environments.staging.server]
+host = "203.0.113.20"
+
+[environments.production]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.environments(), vec!["staging", "production"]);
+        assert_eq!(manifest.values()["APP_URL"], "app.example.com");
+
+        let staging = manifest.values_for(Some("staging")).unwrap();
+        assert_eq!(staging["APP_NAME"], "my-app-staging");
+        assert_eq!(staging["APP_URL"], "staging.example.com");
+        assert_eq!(staging["APP_PORT"], "3000");
+        assert_eq!(staging["VPS_HOST"], "203.0.113.20");
+        assert_eq!(staging["SECRETS_FILE"], ".minion.staging.secrets");
+
+        let production = manifest.values_for(Some("production")).unwrap();
+        assert_eq!(production["VPS_HOST"], "203.0.113.10");
+        assert_eq!(
+            manifest.values_for(Some("qa")).unwrap_err().to_string(),
+            "Unknown environment qa. minion.toml defines: staging, production"
+        );
+        assert!(parse("version = 1\n[environments.staging.deploy]\n")
+            .unwrap_err()
+            .to_string()
+            .starts_with("minion.toml:2: unknown table [environments.staging.deploy]"));
+    }
+
+    #[test]
+    fn set_for_writes_into_the_environment_tables() {
+        let mut manifest = parse(
+            "version = 1
+
+[app]
+name = \"my-app\"
+",
+        )
+        .unwrap();
+
+        manifest
+            .set_for(Some("staging"), "APP_NAME", "my-app-preview")
+            .unwrap();
+
+        assert_eq!(
+            manifest.content,
+            "version = 1\n\n[app]\nname = \"my-app\"\n\n[environments.staging.app]\nname = \"my-app-preview\"\n"
+        );
+        assert_eq!(
+            manifest.values_for(Some("staging")).unwrap()["APP_NAME"],
+            "my-app-preview"
+        );
+    }
+
+    #[test]
+    fn legacy_config_is_converted_with_processes_and_routes() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut file,
+            b"VPS_HOST=203.0.113.10\nAPP_NAME=my-app\nAPP_PORT=3000\nAPP_VOLUMES=\nPROCESSES=worker\nPROCESS_WORKER_COMMAND=bin/work \"fast\"\nROUTES=api\nROUTE_API_PATH=/api\nROUTE_API_PORT=4000\n",
+        )
+        .unwrap();
+        let legacy = Config::new(file.path().to_str().unwrap()).unwrap();
+
+        let manifest = Manifest::from_legacy(&legacy, "minion.toml").unwrap();
+
+        assert_eq!(
+            manifest.content,
+            "version = 1\n\n[app]\nname = \"my-app\"\nport = 3000\nvolumes = []\n\n[server]\nhost = \"203.0.113.10\"\n\n[processes.worker]\ncommand = 'bin/work \"fast\"'\n\n[routes.api]\npath = \"/api\"\nport = 4000\n"
+        );
+        let values = manifest.values();
+        assert_eq!(values["PROCESS_WORKER_COMMAND"], "bin/work \"fast\"");
+        assert_eq!(values["ROUTES"], "api");
+        assert_eq!(values["ROUTE_API_PATH"], "/api");
+        assert_eq!(values["ROUTE_API_PORT"], "4000");
+    }
+
+    #[test]
+    fn open_only_migrates_legacy_file_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("minion.toml");
+        let legacy = dir.path().join(".minion");
+        fs::write(&legacy, "APP_NAME=my-app\n").unwrap();
+
+        let path = path.to_str().unwrap();
+        let legacy_path = legacy.to_str().unwrap();
+
+        let manifest = Manifest::open(path, legacy_path, false).unwrap();
+        assert_eq!(manifest.values()["APP_NAME"], "my-app");
+        assert!(legacy.exists());
+        assert!(!Path::new(path).exists());
+
+        let manifest = Manifest::open(path, legacy_path, true).unwrap();
+        assert_eq!(manifest.values()["APP_NAME"], "my-app");
+        assert!(!legacy.exists());
+        assert!(dir.path().join(".minion.bak").exists());
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "version = 1\n\n[app]\nname = \"my-app\"\n"
+        );
+    }
+}
@@ -1,13 +1,30 @@
+mod acme;
 mod app_config;
 mod command;
 mod config;
+mod env_file;
+mod manifest;
+mod progress;
 mod remote;
 mod ssh;
+mod tar;
+mod terminal;
 #[cfg(test)]
 pub mod test_support;
+mod time;
 
-pub use app_config::{AppConfig, AppConfigOverrides};
+pub use acme::{
+    dns_resolvers_config, validate_cert_resolver, DnsProvider, CUSTOM_CERTS, HTTP_RESOLVER,
+};
+pub use app_config::{
+    list_items, select_environment, AppConfig, AppConfigOverrides, ProcessConfig, RouteConfig,
+};
 pub use command::{CommandExecutor, LocalCommandRunner};
 pub use config::Config;
-pub use remote::RemoteClient;
+pub use env_file::{parse_assignment, validate_env_key, EnvFile};
+pub use manifest::{Manifest, MANIFEST_FILE};
+pub use progress::ProgressReader;
+pub use remote::{shell_quote, PortForward, RemoteClient};
 pub use ssh::{SshAuth, SshClient};
+pub use tar::{filter_tar, read_tar_entry};
+pub use time::utc_timestamp;
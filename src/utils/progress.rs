@@ -0,0 +1,101 @@
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wraps a reader and reports how many bytes passed through it, and how fast,
+/// on a single updating terminal line.
+pub struct ProgressReader<R> {
+    inner: R,
+    bytes: u64,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            bytes: 0,
+            started: now,
+            last_report: now,
+        }
+    }
+
+    /// Prints the final totals and ends the progress line.
+    pub fn finish(&self) {
+        println!("\r{}", format_progress(self.bytes, self.started.elapsed()));
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes += read as u64;
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.last_report = Instant::now();
+            print!("\r{}", format_progress(self.bytes, self.started.elapsed()));
+            let _ = io::stdout().flush();
+        }
+
+        Ok(read)
+    }
+}
+
+fn format_progress(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64().max(0.001);
+    format!(
+        "  Sent {} ({}/s)    ",
+        format_bytes(bytes as f64),
+        format_bytes(bytes as f64 / seconds)
+    )
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_are_formatted_with_decimal_units() {
+        assert_eq!(format_bytes(512.0), "512 B");
+        assert_eq!(format_bytes(1_500.0), "1.5 KB");
+        assert_eq!(format_bytes(250_000_000.0), "250.0 MB");
+        assert_eq!(format_bytes(3_200_000_000_000.0), "3200.0 GB");
+    }
+
+    #[test]
+    fn progress_reports_total_and_throughput() {
+        let line = format_progress(20_000_000, Duration::from_secs(4));
+
+        assert_eq!(line.trim_end(), "  Sent 20.0 MB (5.0 MB/s)");
+    }
+
+    #[test]
+    fn reader_counts_bytes_passed_through() {
+        let mut reader = ProgressReader::new(&b"hello world"[..]);
+        let mut output = Vec::new();
+
+        reader.read_to_end(&mut output).unwrap();
+
+        assert_eq!(output, b"hello world");
+        assert_eq!(reader.bytes, 11);
+    }
+}
@@ -1,7 +1,31 @@
 use anyhow::Result;
+use std::io::Read;
 
 pub trait RemoteClient {
     fn execute_command(&self, command: &str) -> Result<(String, i32)>;
     fn execute_command_stream(&self, command: &str) -> Result<i32>;
+    /// Runs `command` with `input` streamed to its stdin, without buffering
+    /// the whole input in memory.
+    fn execute_with_stdin(&self, command: &str, input: &mut dyn Read) -> Result<(String, i32)>;
+    /// Runs `command` on a PTY wired to the local terminal until it exits.
+    fn execute_interactive(&self, command: &str) -> Result<i32>;
+    /// Forwards local ports to addresses reachable from the VPS until the
+    /// process is interrupted.
+    fn forward_ports(&self, forwards: &[PortForward]) -> Result<()>;
     fn copy_file(&self, local_path: &str, remote_path: &str) -> Result<()>;
+    fn download_file(&self, remote_path: &str, local_path: &str) -> Result<()>;
+}
+
+/// `localhost:<local_port>` forwarded to `<host>:<port>` as seen from the VPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortForward {
+    pub local_port: u16,
+    /// What the user asked for, e.g. a container name.
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+}
+
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
 }
@@ -1,13 +1,18 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::fs::File;
-use std::io::Read;
-use std::io::Write;
-use std::net::TcpStream;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
 use std::path::Path;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
 
-use ssh2::Session;
+use ssh2::{Channel, Session};
 
-use super::RemoteClient;
+use super::terminal::{self, RawMode};
+use super::{PortForward, RemoteClient};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 pub struct SshClient {
     session: Session,
@@ -69,6 +74,221 @@ impl SshClient {
     pub fn execute_command(&self, command: &str) -> Result<(String, i32)> {
         <Self as RemoteClient>::execute_command(self, command)
     }
+
+    /// Copies the remote output to stdout and local keystrokes to the
+    /// channel until the remote side closes. The session must be in
+    /// non-blocking mode, since either side may be idle for a long time.
+    fn pump_interactive(&self, channel: &mut Channel, input: &Receiver<Vec<u8>>) -> Result<()> {
+        let mut stdout = io::stdout();
+        let mut buffer = [0; 8192];
+        let mut input_open = true;
+
+        loop {
+            let mut idle = true;
+
+            match channel.read(&mut buffer) {
+                Ok(0) => {}
+                Ok(read) => {
+                    stdout.write_all(&buffer[..read])?;
+                    stdout.flush()?;
+                    idle = false;
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => return Err(error.into()),
+            }
+
+            while input_open {
+                match input.try_recv() {
+                    Ok(bytes) => {
+                        write_all_nonblocking(channel, &bytes)?;
+                        idle = false;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        input_open = false;
+                        retry_nonblocking(|| channel.send_eof())?;
+                    }
+                }
+            }
+
+            if channel.eof() {
+                return Ok(());
+            }
+            if idle {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    /// Accepts connections on the local listeners and relays each one over a
+    /// `direct-tcpip` channel. Everything runs on this thread with the session
+    /// in non-blocking mode, since a blocking read would hold the session for
+    /// every other connection.
+    fn pump_forwards(&self, listeners: &[(TcpListener, &PortForward)]) -> Result<()> {
+        let mut connections: Vec<ForwardedConnection> = Vec::new();
+        let mut buffer = [0; 16384];
+
+        loop {
+            let mut idle = true;
+
+            for (listener, forward) in listeners {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        idle = false;
+                        stream.set_nonblocking(true)?;
+                        match retry_nonblocking(|| {
+                            self.session
+                                .channel_direct_tcpip(&forward.host, forward.port, None)
+                        }) {
+                            Ok(channel) => connections.push(ForwardedConnection {
+                                stream,
+                                channel,
+                                local_open: true,
+                                eof_sent: false,
+                                to_remote: Vec::new(),
+                                to_local: Vec::new(),
+                            }),
+                            Err(error) => eprintln!(
+                                "Failed to connect to {}:{}: {}",
+                                forward.name, forward.port, error
+                            ),
+                        }
+                    }
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                    Err(error) => return Err(error.into()),
+                }
+            }
+
+            connections.retain_mut(|connection| match connection.pump(&mut buffer) {
+                Ok((active, open)) => {
+                    idle &= !active;
+                    open
+                }
+                Err(error) => {
+                    eprintln!("Forwarded connection failed: {}", error);
+                    false
+                }
+            });
+
+            if idle {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// A local connection accepted by `forward_ports` and its SSH channel.
+///
+/// Data read from one side waits in `to_remote` or `to_local` until the other
+/// side accepts it, so a slow client never holds up the other connections.
+struct ForwardedConnection {
+    stream: TcpStream,
+    channel: Channel,
+    local_open: bool,
+    eof_sent: bool,
+    to_remote: Vec<u8>,
+    to_local: Vec<u8>,
+}
+
+impl ForwardedConnection {
+    /// Moves whatever data is ready in either direction. Returns whether
+    /// anything happened and whether the connection is still open.
+    fn pump(&mut self, buffer: &mut [u8]) -> Result<(bool, bool)> {
+        let mut active = write_pending(&mut self.channel, &mut self.to_remote)?;
+        active |= write_pending(&mut self.stream, &mut self.to_local)?;
+
+        // Only read more from a side once the other side has taken everything
+        // it was given, which leaves backpressure to TCP and the SSH window.
+        if self.local_open && self.to_remote.is_empty() {
+            match self.stream.read(buffer) {
+                Ok(0) => {
+                    self.local_open = false;
+                    active = true;
+                }
+                Ok(read) => {
+                    self.to_remote.extend_from_slice(&buffer[..read]);
+                    write_pending(&mut self.channel, &mut self.to_remote)?;
+                    active = true;
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        if !self.local_open && !self.eof_sent && self.to_remote.is_empty() {
+            match self.channel.send_eof().map_err(io::Error::from) {
+                Ok(()) => {
+                    self.eof_sent = true;
+                    active = true;
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        if self.to_local.is_empty() {
+            match self.channel.read(buffer) {
+                Ok(0) => {}
+                Ok(read) => {
+                    self.to_local.extend_from_slice(&buffer[..read]);
+                    write_pending(&mut self.stream, &mut self.to_local)?;
+                    active = true;
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        if self.to_local.is_empty() && self.channel.eof() {
+            let _ = self.stream.shutdown(Shutdown::Write);
+            let _ = self.channel.close();
+            return Ok((true, false));
+        }
+
+        Ok((active, true))
+    }
+}
+
+/// Writes as much of `pending` as `writer` takes without blocking and drops
+/// the written bytes. Returns whether anything was written.
+fn write_pending(writer: &mut impl Write, pending: &mut Vec<u8>) -> Result<bool> {
+    let mut wrote = false;
+
+    while !pending.is_empty() {
+        match writer.write(pending) {
+            Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero).into()),
+            Ok(written) => {
+                pending.drain(..written);
+                wrote = true;
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(wrote)
+}
+
+fn write_all_nonblocking(writer: &mut impl Write, mut bytes: &[u8]) -> Result<()> {
+    while !bytes.is_empty() {
+        match writer.write(bytes) {
+            Ok(written) => bytes = &bytes[written..],
+            Err(error) if error.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(())
+}
+
+fn retry_nonblocking<T>(mut operation: impl FnMut() -> Result<T, ssh2::Error>) -> Result<T> {
+    loop {
+        match operation().map_err(io::Error::from) {
+            Ok(value) => return Ok(value),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(error) => return Err(error.into()),
+        }
+    }
 }
 
 impl RemoteClient for SshClient {
@@ -104,16 +324,92 @@ impl RemoteClient for SshClient {
         Ok(channel.exit_status()?)
     }
 
+    fn execute_with_stdin(&self, command: &str, input: &mut dyn Read) -> Result<(String, i32)> {
+        let mut channel = self.session.channel_session()?;
+        channel.exec(command)?;
+
+        if let Err(error) = io::copy(input, &mut channel) {
+            // The remote command usually explains why it stopped reading.
+            let mut output = String::new();
+            let _ = channel.read_to_string(&mut output);
+            return Err(anyhow!(
+                "Failed to stream data to the VPS: {}\n{}",
+                error,
+                output.trim()
+            ));
+        }
+        channel.send_eof()?;
+
+        let mut output = String::new();
+        channel.read_to_string(&mut output)?;
+
+        channel.wait_close()?;
+        let exit_status = channel.exit_status()?;
+
+        Ok((output, exit_status))
+    }
+
+    fn execute_interactive(&self, command: &str) -> Result<i32> {
+        let (columns, rows) = terminal::size();
+        let term = std::env::var("TERM").unwrap_or_else(|_| "xterm".to_string());
+
+        let mut channel = self.session.channel_session()?;
+        channel.request_pty(&term, None, Some((columns, rows, 0, 0)))?;
+        channel.exec(command)?;
+
+        let raw_mode = RawMode::enable()?;
+        let input = terminal::spawn_stdin_reader();
+        self.session.set_blocking(false);
+        let result = self.pump_interactive(&mut channel, &input);
+        self.session.set_blocking(true);
+        drop(raw_mode);
+        result?;
+
+        channel.wait_close()?;
+        Ok(channel.exit_status()?)
+    }
+
+    fn forward_ports(&self, forwards: &[PortForward]) -> Result<()> {
+        let listeners = forwards
+            .iter()
+            .map(|forward| {
+                let listener =
+                    TcpListener::bind(("127.0.0.1", forward.local_port)).map_err(|error| {
+                        anyhow!("Failed to listen on port {}: {}", forward.local_port, error)
+                    })?;
+                listener.set_nonblocking(true)?;
+                Ok((listener, forward))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.session.set_blocking(false);
+        let result = self.pump_forwards(&listeners);
+        self.session.set_blocking(true);
+        result
+    }
+
     fn copy_file(&self, local_path: &str, remote_path: &str) -> Result<()> {
         let mut local_file = File::open(local_path)?;
-        let mut contents = Vec::new();
-        local_file.read_to_end(&mut contents)?;
+        let size = local_file.metadata()?.len();
+
+        let mut remote_file = self
+            .session
+            .scp_send(Path::new(remote_path), 0o644, size, None)?;
+
+        io::copy(&mut local_file, &mut remote_file)?;
+        remote_file.send_eof()?;
+        remote_file.wait_eof()?;
+        remote_file.close()?;
+        remote_file.wait_close()?;
+
+        Ok(())
+    }
 
-        let mut remote_file =
-            self.session
-                .scp_send(Path::new(remote_path), 0o644, contents.len() as u64, None)?;
+    fn download_file(&self, remote_path: &str, local_path: &str) -> Result<()> {
+        let (mut remote_file, _) = self.session.scp_recv(Path::new(remote_path))?;
+        let mut local_file = File::create(local_path)?;
 
-        remote_file.write_all(&contents)?;
+        io::copy(&mut remote_file, &mut local_file)?;
         remote_file.send_eof()?;
         remote_file.wait_eof()?;
         remote_file.close()?;
@@ -224,6 +520,68 @@ mod tests {
         assert_eq!(output.trim(), "copied over ssh");
     }
 
+    #[test]
+    fn test_ssh_download_file() {
+        let image = OpenSshServerContainer::default();
+        let container = image.start().unwrap();
+
+        let port = container.get_host_port_ipv4(2222).unwrap();
+        let client =
+            SshClient::connect(&format!("localhost:{}", port), "testuser", Some("testpass"))
+                .expect("Failed to connect");
+        client
+            .execute_command("printf 'downloaded over ssh' > /tmp/minion-download-test.txt")
+            .expect("Failed to write remote file");
+
+        let dir = tempfile::tempdir().unwrap();
+        let local_path = dir.path().join("download.txt");
+        client
+            .download_file(
+                "/tmp/minion-download-test.txt",
+                local_path.to_str().unwrap(),
+            )
+            .expect("Failed to download file");
+
+        assert_eq!(
+            std::fs::read_to_string(local_path).unwrap(),
+            "downloaded over ssh"
+        );
+    }
+
+    #[test]
+    fn test_ssh_execute_with_stdin_streams_input() {
+        let image = OpenSshServerContainer::default();
+        let container = image.start().unwrap();
+
+        let port = container.get_host_port_ipv4(2222).unwrap();
+        let client =
+            SshClient::connect(&format!("localhost:{}", port), "testuser", Some("testpass"))
+                .expect("Failed to connect");
+
+        let input = vec![b'x'; 1024 * 1024];
+        let (output, status) = client
+            .execute_with_stdin("wc -c", &mut input.as_slice())
+            .expect("Failed to stream stdin");
+        assert_eq!(status, 0);
+        assert_eq!(output.trim(), "1048576");
+    }
+
+    #[test]
+    fn test_ssh_execute_interactive_allocates_pty() {
+        let image = OpenSshServerContainer::default();
+        let container = image.start().unwrap();
+
+        let port = container.get_host_port_ipv4(2222).unwrap();
+        let client =
+            SshClient::connect(&format!("localhost:{}", port), "testuser", Some("testpass"))
+                .expect("Failed to connect");
+
+        let status = client
+            .execute_interactive("test -t 0 && test -t 1")
+            .expect("Failed to run interactive command");
+        assert_eq!(status, 0);
+    }
+
     #[test]
     fn test_ssh_password_auth_failure() {
         let image = OpenSshServerContainer::default();
@@ -238,4 +596,47 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    /// Accepts `capacity` bytes and then reports that it would block.
+    struct SlowWriter {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Write for SlowWriter {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            let room = self.capacity - self.written.len();
+            if room == 0 {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let written = room.min(bytes.len());
+            self.written.extend_from_slice(&bytes[..written]);
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_pending_keeps_what_a_slow_writer_refuses() {
+        let mut writer = SlowWriter {
+            written: Vec::new(),
+            capacity: 3,
+        };
+        let mut pending = b"hello".to_vec();
+
+        assert!(write_pending(&mut writer, &mut pending).unwrap());
+        assert_eq!(writer.written, b"hel");
+        assert_eq!(pending, b"lo");
+
+        assert!(!write_pending(&mut writer, &mut pending).unwrap());
+        assert_eq!(pending, b"lo");
+
+        writer.capacity = 5;
+        assert!(write_pending(&mut writer, &mut pending).unwrap());
+        assert_eq!(writer.written, b"hello");
+        assert!(pending.is_empty());
+    }
 }
@@ -0,0 +1,248 @@
+//! Just enough of the tar format to pick files out of `docker save` output
+//! and to copy an archive while leaving some of its files out.
+
+use anyhow::{anyhow, Result};
+use std::io::{self, Read, Write};
+
+const BLOCK_SIZE: u64 = 512;
+
+struct Entry {
+    /// Raw header blocks, including pax or GNU long-name blocks that belong
+    /// to this entry.
+    headers: Vec<u8>,
+    name: String,
+    size: u64,
+}
+
+/// Returns the contents of the file called `name`, if the archive has one.
+pub fn read_tar_entry<R: Read>(mut reader: R, name: &str) -> Result<Option<Vec<u8>>> {
+    while let Some(entry) = next_entry(&mut reader)? {
+        if entry.name == name {
+            let mut contents = Vec::new();
+            (&mut reader).take(entry.size).read_to_end(&mut contents)?;
+            return Ok(Some(contents));
+        }
+        skip(&mut reader, padded(entry.size))?;
+    }
+
+    Ok(None)
+}
+
+/// Copies the archive from `reader` to `writer`, dropping every file for
+/// which `keep` returns false.
+pub fn filter_tar<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    keep: impl Fn(&str) -> bool,
+) -> Result<()> {
+    while let Some(entry) = next_entry(&mut reader)? {
+        let data_len = padded(entry.size);
+        if keep(&entry.name) {
+            writer.write_all(&entry.headers)?;
+            let copied = io::copy(&mut (&mut reader).take(data_len), &mut writer)?;
+            if copied != data_len {
+                return Err(anyhow!("Truncated tar entry {}", entry.name));
+            }
+        } else {
+            skip(&mut reader, data_len)?;
+        }
+    }
+
+    writer.write_all(&[0; 2 * BLOCK_SIZE as usize])?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn next_entry<R: Read>(reader: &mut R) -> Result<Option<Entry>> {
+    let mut headers = Vec::new();
+    let mut long_name = None;
+
+    loop {
+        let mut header = [0u8; BLOCK_SIZE as usize];
+        if !read_block(reader, &mut header)? || header.iter().all(|byte| *byte == 0) {
+            return Ok(None);
+        }
+        headers.extend_from_slice(&header);
+        let size = parse_size(&header)?;
+
+        match header[156] {
+            // pax extended header or GNU long name for the entry that follows
+            b'x' | b'L' => {
+                let mut data = vec![0u8; padded(size) as usize];
+                reader.read_exact(&mut data)?;
+                headers.extend_from_slice(&data);
+                let data = &data[..size as usize];
+                long_name = if header[156] == b'L' {
+                    Some(c_string(data))
+                } else {
+                    pax_path(data).or(long_name)
+                };
+            }
+            _ => {
+                let name = long_name.unwrap_or_else(|| header_name(&header));
+                return Ok(Some(Entry {
+                    headers,
+                    name,
+                    size,
+                }));
+            }
+        }
+    }
+}
+
+/// Reads one block, returning false at a clean end of input.
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(false),
+            0 => return Err(anyhow!("Truncated tar header")),
+            read => filled += read,
+        }
+    }
+
+    Ok(true)
+}
+
+fn skip<R: Read>(reader: &mut R, len: u64) -> Result<()> {
+    let skipped = io::copy(&mut reader.take(len), &mut io::sink())?;
+    if skipped != len {
+        return Err(anyhow!("Truncated tar archive"));
+    }
+
+    Ok(())
+}
+
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+fn header_name(header: &[u8]) -> String {
+    let name = c_string(&header[0..100]);
+    let prefix = if &header[257..262] == b"ustar" {
+        c_string(&header[345..500])
+    } else {
+        String::new()
+    };
+
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+fn parse_size(header: &[u8]) -> Result<u64> {
+    let field = &header[124..136];
+    // Sizes that do not fit in octal use the base-256 extension.
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(0u64, |size, byte| (size << 8) | u64::from(*byte)));
+    }
+
+    let octal = c_string(field);
+    let octal = octal.trim();
+    if octal.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(octal, 8).map_err(|_| anyhow!("Invalid tar entry size {:?}", octal))
+}
+
+fn pax_path(data: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(data).lines().find_map(|record| {
+        let (_, field) = record.split_once(' ')?;
+        field.strip_prefix("path=").map(ToOwned::to_owned)
+    })
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str, size: usize, typeflag: u8) -> Vec<u8> {
+        let mut header = vec![0u8; BLOCK_SIZE as usize];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        let size = format!("{:011o}\0", size);
+        header[124..136].copy_from_slice(size.as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header
+    }
+
+    fn file(name: &str, contents: &[u8]) -> Vec<u8> {
+        let mut entry = header(name, contents.len(), b'0');
+        entry.extend_from_slice(contents);
+        entry.resize(
+            BLOCK_SIZE as usize + padded(contents.len() as u64) as usize,
+            0,
+        );
+        entry
+    }
+
+    fn archive(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut archive = entries.concat();
+        archive.extend_from_slice(&[0; 1024]);
+        archive
+    }
+
+    #[test]
+    fn read_tar_entry_finds_file_by_name() {
+        let archive = archive(&[
+            file("blobs/sha256/aaa", b"layer"),
+            file("manifest.json", b"[{\"Layers\":[]}]"),
+        ]);
+
+        let contents = read_tar_entry(archive.as_slice(), "manifest.json").unwrap();
+
+        assert_eq!(contents, Some(b"[{\"Layers\":[]}]".to_vec()));
+        assert_eq!(read_tar_entry(archive.as_slice(), "missing").unwrap(), None);
+    }
+
+    #[test]
+    fn filter_tar_drops_rejected_files_and_keeps_the_rest_intact() {
+        let kept = file("manifest.json", b"{}");
+        let archive = archive(&[file("blobs/sha256/aaa", &[7; 1500]), kept.clone()]);
+        let mut filtered = Vec::new();
+
+        filter_tar(archive.as_slice(), &mut filtered, |name| {
+            name != "blobs/sha256/aaa"
+        })
+        .unwrap();
+
+        assert_eq!(filtered, [kept, vec![0; 1024]].concat());
+    }
+
+    #[test]
+    fn pax_path_applies_to_the_following_entry() {
+        let long_name = format!("{}/layer.tar", "d".repeat(120));
+        let record = format!("{} path={}\n", long_name.len() + 10, long_name);
+        let mut pax = header("PaxHeaders/0", record.len(), b'x');
+        pax.extend_from_slice(record.as_bytes());
+        pax.resize(BLOCK_SIZE as usize * 2, 0);
+        let archive = archive(&[[pax, file("short", b"data")].concat()]);
+
+        let contents = read_tar_entry(archive.as_slice(), &long_name).unwrap();
+        let mut filtered = Vec::new();
+        filter_tar(archive.as_slice(), &mut filtered, |name| name != long_name).unwrap();
+
+        assert_eq!(contents, Some(b"data".to_vec()));
+        assert_eq!(filtered, vec![0; 1024]);
+    }
+
+    #[test]
+    fn truncated_archive_is_an_error() {
+        let mut archive = file("blobs/sha256/aaa", &[1; 600]);
+        archive.truncate(700);
+
+        assert!(filter_tar(archive.as_slice(), io::sink(), |_| true).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Result};
+use std::io::{self, IsTerminal, Read};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const DEFAULT_SIZE: (u32, u32) = (80, 24);
+
+/// Puts the local terminal into raw mode, so keystrokes such as Ctrl-C reach
+/// the remote PTY instead of this process, and restores it when dropped.
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    /// Returns `None` when stdin is not a terminal and there is nothing to
+    /// switch.
+    pub fn enable() -> Result<Option<Self>> {
+        if !io::stdin().is_terminal() {
+            return Ok(None);
+        }
+
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Ok(Some(RawMode {
+            saved: saved.trim().to_string(),
+        }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Columns and rows of the local terminal, or 80x24 when unknown.
+pub fn size() -> (u32, u32) {
+    stty(&["size"])
+        .ok()
+        .and_then(|output| {
+            let mut parts = output.split_whitespace().map(str::parse::<u32>);
+            let rows = parts.next()?.ok()?;
+            let columns = parts.next()?.ok()?;
+            Some((columns, rows))
+        })
+        .filter(|(columns, rows)| *columns > 0 && *rows > 0)
+        .unwrap_or(DEFAULT_SIZE)
+}
+
+/// Reads stdin on a background thread. The receiver disconnects once stdin
+/// is closed.
+pub fn spawn_stdin_reader() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer = [0; 1024];
+        loop {
+            match stdin.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    if sender.send(buffer[..read].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn stty(args: &[&str]) -> Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|error| anyhow!("Failed to run stty: {}", error))?;
+    if !output.status.success() {
+        return Err(anyhow!("stty {} failed", args.join(" ")));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -1,13 +1,15 @@
 use anyhow::Result;
 use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::io::{Cursor, Read};
 
-use super::{LocalCommandRunner, RemoteClient};
+use super::{LocalCommandRunner, PortForward, RemoteClient};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LocalCommandInvocation {
     pub command: String,
     pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
 }
 
 #[derive(Default)]
@@ -40,16 +42,42 @@ impl FakeLocalCommandRunner {
 
 impl LocalCommandRunner for FakeLocalCommandRunner {
     fn execute(&self, command: &str, args: &[&str]) -> Result<(String, i32)> {
+        self.execute_with_env(command, args, &[])
+            .map(|(stdout, _, status)| (stdout, status))
+    }
+
+    fn execute_with_env(
+        &self,
+        command: &str,
+        args: &[&str],
+        env: &[(&str, &str)],
+    ) -> Result<(String, String, i32)> {
         self.commands.borrow_mut().push(LocalCommandInvocation {
             command: command.to_string(),
             args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: env
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
         });
 
-        Ok(self
+        let (output, status) = self
             .responses
             .borrow_mut()
             .pop_front()
-            .unwrap_or_else(|| (String::new(), 0)))
+            .unwrap_or_else(|| (String::new(), 0));
+        Ok((output, String::new(), status))
+    }
+
+    fn execute_piped(
+        &self,
+        command: &str,
+        args: &[&str],
+        consumer: &mut dyn FnMut(&mut dyn Read) -> Result<()>,
+    ) -> Result<(String, i32)> {
+        let (output, status) = self.execute(command, args)?;
+        consumer(&mut Cursor::new(output.clone().into_bytes()))?;
+        Ok((output, status))
     }
 }
 
@@ -58,7 +86,12 @@ pub struct FakeRemoteClient {
     commands: RefCell<Vec<String>>,
     streamed_commands: RefCell<Vec<String>>,
     copied_files: RefCell<Vec<(String, String)>>,
+    downloaded_files: RefCell<Vec<(String, String)>>,
+    stdin_inputs: RefCell<Vec<(String, Vec<u8>)>>,
+    interactive_commands: RefCell<Vec<String>>,
+    forwards: RefCell<Vec<PortForward>>,
     responses: RefCell<VecDeque<(String, i32)>>,
+    command_responses: RefCell<Vec<(String, String, i32)>>,
     stream_responses: RefCell<VecDeque<i32>>,
 }
 
@@ -86,6 +119,15 @@ impl FakeRemoteClient {
         }
     }
 
+    /// Answers the first command containing `pattern` once, ahead of the
+    /// ordered responses.
+    pub fn on_command(self, pattern: &str, output: &str, status: i32) -> Self {
+        self.command_responses
+            .borrow_mut()
+            .push((pattern.to_string(), output.to_string(), status));
+        self
+    }
+
     pub fn commands(&self) -> Vec<String> {
         self.commands.borrow().clone()
     }
@@ -97,11 +139,39 @@ impl FakeRemoteClient {
     pub fn copied_files(&self) -> Vec<(String, String)> {
         self.copied_files.borrow().clone()
     }
+
+    /// Downloads as `(remote_path, local_path)` pairs.
+    pub fn downloaded_files(&self) -> Vec<(String, String)> {
+        self.downloaded_files.borrow().clone()
+    }
+
+    /// Commands run with stdin, together with everything streamed to them.
+    pub fn stdin_inputs(&self) -> Vec<(String, Vec<u8>)> {
+        self.stdin_inputs.borrow().clone()
+    }
+
+    pub fn interactive_commands(&self) -> Vec<String> {
+        self.interactive_commands.borrow().clone()
+    }
+
+    pub fn forwards(&self) -> Vec<PortForward> {
+        self.forwards.borrow().clone()
+    }
 }
 
 impl RemoteClient for FakeRemoteClient {
     fn execute_command(&self, command: &str) -> Result<(String, i32)> {
         self.commands.borrow_mut().push(command.to_string());
+
+        let mut command_responses = self.command_responses.borrow_mut();
+        if let Some(index) = command_responses
+            .iter()
+            .position(|(pattern, _, _)| command.contains(pattern.as_str()))
+        {
+            let (_, output, status) = command_responses.remove(index);
+            return Ok((output, status));
+        }
+
         Ok(self
             .responses
             .borrow_mut()
@@ -116,10 +186,42 @@ impl RemoteClient for FakeRemoteClient {
         Ok(self.stream_responses.borrow_mut().pop_front().unwrap_or(0))
     }
 
+    fn execute_with_stdin(&self, command: &str, input: &mut dyn Read) -> Result<(String, i32)> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        self.stdin_inputs
+            .borrow_mut()
+            .push((command.to_string(), bytes));
+
+        self.execute_command(command)
+    }
+
+    /// Shares the stream responses, since both return only a status.
+    fn execute_interactive(&self, command: &str) -> Result<i32> {
+        self.interactive_commands
+            .borrow_mut()
+            .push(command.to_string());
+        Ok(self.stream_responses.borrow_mut().pop_front().unwrap_or(0))
+    }
+
+    /// Records the forwards and returns at once instead of running until
+    /// interrupted.
+    fn forward_ports(&self, forwards: &[PortForward]) -> Result<()> {
+        self.forwards.borrow_mut().extend_from_slice(forwards);
+        Ok(())
+    }
+
     fn copy_file(&self, local_path: &str, remote_path: &str) -> Result<()> {
         self.copied_files
             .borrow_mut()
             .push((local_path.to_string(), remote_path.to_string()));
         Ok(())
     }
+
+    fn download_file(&self, remote_path: &str, local_path: &str) -> Result<()> {
+        self.downloaded_files
+            .borrow_mut()
+            .push((remote_path.to_string(), local_path.to_string()));
+        Ok(())
+    }
 }
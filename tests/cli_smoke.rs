@@ -1,17 +1,54 @@
-use std::process::{Command, Output};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
 
 const MINION_ENV_KEYS: &[&str] = &[
+    "MINION_ENVIRONMENT",
     "MINION_VPS_HOST",
     "MINION_APP_NAME",
     "MINION_APP_URL",
     "MINION_APP_PORT",
     "MINION_APP_VOLUMES",
+    "MINION_CERT_RESOLVER",
     "MINION_SSH_USER",
     "MINION_SSH_KEY_PATH",
     "MINION_SSH_PRIVATE_KEY",
     "MINION_SSH_PASSWORD",
     "MINION_SSH_PASSPHRASE",
     "MINION_DOCKER_PLATFORM",
+    "MINION_IMAGE_COMPRESSION",
+    "MINION_IMAGE_SOURCE",
+    "MINION_IMAGE",
+    "MINION_REGISTRY_USERNAME",
+    "MINION_REGISTRY_PASSWORD",
+    "MINION_HEALTHCHECK_TYPE",
+    "MINION_HEALTHCHECK_PATH",
+    "MINION_HEALTHCHECK_WAIT",
+    "MINION_HEALTHCHECK_INTERVAL",
+    "MINION_HEALTHCHECK_TIMEOUT",
+    "MINION_HEALTHCHECK_RETRIES",
+    "MINION_PRE_DEPLOY",
+    "MINION_POST_DEPLOY",
+    "MINION_BACKUP_KEEP",
+    "MINION_BACKUP_SCHEDULE",
+    "MINION_BACKUP_S3_ENDPOINT",
+    "MINION_BACKUP_S3_BUCKET",
+    "MINION_BACKUP_S3_PREFIX",
+    "MINION_BACKUP_S3_KEEP",
+    "MINION_BACKUP_S3_ACCESS_KEY_ID",
+    "MINION_BACKUP_S3_SECRET_ACCESS_KEY",
+    "MINION_SECRETS_FILE",
+    "MINION_SECRETS_DECRYPT",
+    "MINION_AGE_KEY",
+    "MINION_WWW_REDIRECT",
+    "MINION_BASIC_AUTH",
+    "MINION_SECURITY_HEADERS",
+    "MINION_HSTS_MAX_AGE",
+    "MINION_STRIP_PREFIX",
+    "MINION_IP_ALLOWLIST",
+    "MINION_RATE_LIMIT",
+    "MINION_RATE_LIMIT_BURST",
+    "MINION_PROCESSES",
+    "MINION_ROUTES",
 ];
 
 fn minion_command() -> Command {
@@ -45,6 +82,29 @@ fn root_help_lists_control_commands() {
     assert!(stdout.contains("status"));
     assert!(stdout.contains("logs"));
     assert!(stdout.contains("doctor"));
+    assert!(stdout.contains("exec"));
+    assert!(stdout.contains("shell"));
+    assert!(stdout.contains("addons"));
+    assert!(stdout.contains("apps"));
+    assert!(stdout.contains("destroy"));
+    assert!(stdout.contains("certs"));
+}
+
+#[test]
+fn backup_help_lists_list_subcommand_and_options() {
+    let output = minion_command()
+        .args(["backup", "--help"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("list"));
+    assert!(stdout.contains("schedule"));
+    assert!(stdout.contains("--volume"));
+    assert!(stdout.contains("--stop"));
+    assert!(stdout.contains("--output"));
+    assert!(stdout.contains("--keep"));
 }
 
 #[test]
@@ -81,6 +141,27 @@ fn logs_help_lists_tail_and_follow_options() {
     assert!(stdout.contains("--follow"));
 }
 
+#[test]
+fn env_help_lists_subcommands_and_restart_option() {
+    let output = minion_command().args(["env", "--help"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("list"));
+    assert!(stdout.contains("set"));
+    assert!(stdout.contains("unset"));
+    assert!(stdout.contains("import"));
+
+    let output = minion_command()
+        .args(["env", "set", "--help"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("--restart"));
+}
+
 #[test]
 fn deploy_ci_without_config_fails_on_missing_host() {
     let output = run_in_empty_dir(&["deploy", "--ci"]);
@@ -107,3 +188,86 @@ fn invalid_status_app_name_fails_before_ssh() {
     assert!(!output.status.success());
     assert!(stderr.contains("APP_NAME may only contain"));
 }
+
+#[test]
+fn invalid_manifest_reports_file_and_line() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        temp_dir.path().join("minion.toml"),
+        "version = 1\n\n[app]\nport = \"3000\"\n",
+    )
+    .unwrap();
+
+    let output = minion_command()
+        .arg("status")
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("minion.toml:4: app.port must be an integer"));
+}
+
+#[test]
+fn legacy_minion_file_is_only_migrated_by_deploy() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join(".minion"), "APP_NAME=bad;name\n").unwrap();
+
+    let output = minion_command()
+        .args(["status", "--host", "example.com"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stderr.contains("APP_NAME may only contain"));
+    assert!(!temp_dir.path().join("minion.toml").exists());
+    assert!(temp_dir.path().join(".minion").exists());
+
+    let output = minion_command()
+        .args(["deploy", "--ci", "--host", "example.com"])
+        .current_dir(temp_dir.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let manifest = std::fs::read_to_string(temp_dir.path().join("minion.toml")).unwrap();
+
+    assert!(stderr.contains("APP_NAME may only contain"));
+    assert_eq!(manifest, "version = 1\n\n[app]\nname = \"bad;name\"\n");
+    assert!(temp_dir.path().join(".minion.bak").exists());
+}
+
+#[test]
+fn init_with_env_declares_the_environment() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        temp_dir.path().join("minion.toml"),
+        "version = 1\n\n[server]\nhost = \"203.0.113.10\"\n\n[app]\nname = \"my-app\"\nurl = [\"example.com\"]\nport = 3000\n",
+    )
+    .unwrap();
+
+    let mut child = minion_command()
+        .args(["init", "--env", "staging"])
+        .current_dir(temp_dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"\n\nstaging.example.com\n\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let manifest = std::fs::read_to_string(temp_dir.path().join("minion.toml")).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Enter app name [my-app-staging]"));
+    assert!(stdout.contains("✓ Environment staging saved to minion.toml"));
+    assert!(manifest.ends_with(
+        "[environments.staging.server]\nhost = \"203.0.113.10\"\n\n[environments.staging.app]\nname = \"my-app-staging\"\nurl = [\"staging.example.com\"]\nport = 3000\n"
+    ));
+}